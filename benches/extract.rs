@@ -0,0 +1,79 @@
+// The modules are included by their paths as the crate has no library target.
+// Their unit tests are not built without the test harness, leaving their imports unused.
+#![allow(dead_code, unused_imports)]
+
+#[path = "../src/extract.rs"]
+mod extract;
+#[path = "../src/format.rs"]
+mod format;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use extract::{extract_kakomon, ExtractOptions};
+use once_cell::sync::Lazy;
+use scraper::{Html, Selector};
+use std::hint::black_box;
+use url::Url;
+
+const HOME: &str = include_str!("../testdata/home.html");
+const Q31: &str = include_str!("../testdata/q31.html");
+const TABLE: &str = include_str!("../testdata/table.html");
+
+// the top page with many blocks before the question
+fn large_html() -> String {
+    let filler =
+        r#"<div class="filler"><div><p>filler</p><ul><li>a</li><li>b</li></ul></div></div>"#;
+    HOME.replacen("<body>", &format!("<body>{}", filler.repeat(2000)), 1)
+}
+
+fn bench_extract_kakomon(c: &mut Criterion) {
+    let large = large_html();
+    let pages = [
+        ("home", "https://www.ap-siken.com/", HOME),
+        (
+            "q31",
+            "https://www.ap-siken.com/kakomon/21_haru/q31.html",
+            Q31,
+        ),
+        ("table", "https://www.ap-siken.com/", TABLE),
+        ("large", "https://www.ap-siken.com/", large.as_str()),
+    ];
+    let options = ExtractOptions::default();
+    let mut group = c.benchmark_group("extract_kakomon");
+    for (name, url, html) in pages {
+        let url = Url::parse(url).unwrap();
+        group.bench_function(name, |b| {
+            b.iter(|| extract_kakomon(black_box(html), url.clone(), &options))
+        });
+    }
+    group.finish();
+}
+
+static DIV: Lazy<Selector> = Lazy::new(|| Selector::parse("div").unwrap());
+
+// Compiling the selector in the loop (before) against the cached one (after).
+// The whole effect is compared with `cargo bench -- --save-baseline before`
+// on the old commit and `cargo bench -- --baseline before` on the new one.
+fn bench_selector(c: &mut Criterion) {
+    let document = Html::parse_document(&large_html());
+    let mut group = c.benchmark_group("selector");
+    group.bench_function("parse_every_time", |b| {
+        b.iter(|| {
+            document
+                .select(&Selector::parse("div").unwrap())
+                .map(|div| div.select(&Selector::parse("div").unwrap()).count())
+                .sum::<usize>()
+        })
+    });
+    group.bench_function("cached", |b| {
+        b.iter(|| {
+            document
+                .select(&DIV)
+                .map(|div| div.select(&DIV).count())
+                .sum::<usize>()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_extract_kakomon, bench_selector);
+criterion_main!(benches);
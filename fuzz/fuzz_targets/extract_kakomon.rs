@@ -0,0 +1,42 @@
+// `cargo +nightly fuzz run extract_kakomon fuzz/corpus/extract_kakomon testdata`
+// feeds any bytes as html to the extraction of every page kind, which must not panic or hang.
+#![no_main]
+// the modules are included by their paths as the crate has no library target
+#![allow(dead_code, unused_imports, unexpected_cfgs)]
+
+#[path = "../../src/extract.rs"]
+mod extract;
+#[path = "../../src/format.rs"]
+mod format;
+
+use extract::{extract_explanation, extract_kakomon, ExtractOptions};
+use libfuzzer_sys::fuzz_target;
+use once_cell::sync::Lazy;
+use url::Url;
+
+static URLS: Lazy<Vec<Url>> = Lazy::new(|| {
+    [
+        "https://www.ap-siken.com/",
+        "https://www.ap-siken.com/kakomon/21_haru/",
+        "https://www.ap-siken.com/kakomon/21_haru/q31.html",
+    ]
+    .iter()
+    .map(|url| Url::parse(url).unwrap())
+    .collect()
+});
+
+static OPTIONS: Lazy<ExtractOptions> = Lazy::new(|| ExtractOptions {
+    normalize_text: true,
+    explanation: true,
+    ..Default::default()
+});
+
+fuzz_target!(|data: &[u8]| {
+    let html = String::from_utf8_lossy(data);
+    for url in URLS.iter() {
+        if let Some(kakomon) = extract_kakomon(&html, url.clone(), &OPTIONS) {
+            kakomon.text();
+        }
+    }
+    extract_explanation(&html, &OPTIONS);
+});
@@ -0,0 +1,109 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+
+// expect up to 50000 questions unless `bloom_capacity` is configured
+pub const DEFAULT_BLOOM_CAPACITY: usize = 50_000;
+// one in a thousand unseen questions is taken as seen (and not sent)
+pub const DEFAULT_BLOOM_FALSE_POSITIVE_RATE: f64 = 0.001;
+
+// A set of the keys which answers "maybe in it" or "surely not in it" in a fixed size.
+// A false positive only makes a question not sent, never sent twice.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BloomFilter {
+    // the bits as hex not to be a long json array
+    #[serde(serialize_with = "to_hex", deserialize_with = "from_hex")]
+    bits: Vec<u8>,
+    hashes: u32,
+    // the number of the keys inserted
+    pub len: usize,
+}
+
+impl BloomFilter {
+    // the optimal size for `capacity` keys at `false_positive_rate`
+    pub fn new(capacity: usize, false_positive_rate: f64) -> Self {
+        let capacity = capacity.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-capacity * false_positive_rate.ln() / (ln2 * ln2))
+            .ceil()
+            .max(8.0);
+        let hashes = (bits / capacity * ln2).round().max(1.0);
+        BloomFilter {
+            bits: vec![0; (bits as usize).div_ceil(8)],
+            hashes: hashes as u32,
+            len: 0,
+        }
+    }
+
+    // the bit positions of the key by the double hashing of its sha256
+    fn positions(&self, key: &str) -> impl Iterator<Item = usize> {
+        let hash = Sha256::digest(key.as_bytes());
+        let h1 = u64::from_le_bytes(hash[0..8].try_into().unwrap());
+        let h2 = u64::from_le_bytes(hash[8..16].try_into().unwrap()) | 1;
+        let size = self.bits.len() as u64 * 8;
+        (0..self.hashes as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % size) as usize)
+    }
+
+    pub fn insert(&mut self, key: &str) {
+        if self.contains(key) {
+            return;
+        }
+        for position in self.positions(key).collect::<Vec<_>>() {
+            self.bits[position / 8] |= 1 << (position % 8);
+        }
+        self.len += 1;
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.positions(key)
+            .all(|position| self.bits[position / 8] & (1 << (position % 8)) != 0)
+    }
+
+    // the capacity it was sized for is exceeded when the false positive rate is higher
+    pub fn capacity(&self) -> usize {
+        let ln2 = std::f64::consts::LN_2;
+        (self.bits.len() as f64 * 8.0 / self.hashes as f64 * ln2) as usize
+    }
+}
+
+fn to_hex<S: Serializer>(bits: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    let hex = bits
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    serializer.serialize_str(&hex)
+}
+
+fn from_hex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let hex = String::deserialize(deserializer)?;
+    if hex.is_empty() || hex.len() % 2 != 0 {
+        return Err(serde::de::Error::custom("invalid bloom filter bits"));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bloom_filter() {
+        let mut bloom = BloomFilter::new(1000, 0.01);
+        for i in 0..1000 {
+            bloom.insert(&format!("q{}", i));
+        }
+        assert_eq!(bloom.len, 1000);
+        assert!((900..1100).contains(&bloom.capacity()));
+        // no false negatives
+        assert!((0..1000).all(|i| bloom.contains(&format!("q{}", i))));
+        let false_positives = (1000..11000)
+            .filter(|i| bloom.contains(&format!("q{}", i)))
+            .count();
+        assert!(false_positives < 200, "{} false positives", false_positives);
+
+        let json = serde_json::to_string(&bloom).unwrap();
+        assert_eq!(serde_json::from_str::<BloomFilter>(&json).unwrap(), bloom);
+    }
+}
@@ -0,0 +1,192 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// the failures in a row opening the breaker unless `circuit_breaker.failure_threshold` is configured
+pub const DEFAULT_BREAKER_FAILURE_THRESHOLD: u32 = 5;
+// how long a destination is skipped unless `circuit_breaker.open_secs` is configured
+pub const DEFAULT_BREAKER_OPEN_DURATION: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerState {
+    // sending as usual
+    Closed,
+    // skipping the destination until `open_duration` passes
+    Open,
+    // trying a message, which closes the breaker if sent and opens it again otherwise
+    HalfOpen,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BreakerOptions {
+    pub failure_threshold: u32,
+    pub open_duration: Duration,
+}
+
+impl Default for BreakerOptions {
+    fn default() -> Self {
+        BreakerOptions {
+            failure_threshold: DEFAULT_BREAKER_FAILURE_THRESHOLD,
+            open_duration: DEFAULT_BREAKER_OPEN_DURATION,
+        }
+    }
+}
+
+// `circuit_breaker` in CONFIG
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct RawBreaker {
+    #[serde(default)]
+    pub failure_threshold: Option<u32>,
+    #[serde(default)]
+    pub open_secs: Option<u64>,
+}
+
+impl RawBreaker {
+    pub fn parse(&self) -> Result<BreakerOptions, String> {
+        if self.failure_threshold == Some(0) {
+            return Err("`circuit_breaker.failure_threshold` must be 1 or more".to_string());
+        }
+        Ok(BreakerOptions {
+            failure_threshold: self
+                .failure_threshold
+                .unwrap_or(DEFAULT_BREAKER_FAILURE_THRESHOLD),
+            open_duration: self
+                .open_secs
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_BREAKER_OPEN_DURATION),
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Breaker {
+    state: BreakerState,
+    // the failures in a row since the last message sent
+    failures: u32,
+    opened_at: Option<Instant>,
+}
+
+// The circuit breaker of a destination, kept over the runs of the daemon.
+// It opens after `failure_threshold` failures in a row (each after its retries) not to keep the others waiting,
+// and lets a message through after `open_duration` to see if the destination is back.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    options: BreakerOptions,
+    breaker: Mutex<Breaker>,
+}
+
+impl CircuitBreaker {
+    pub fn new(options: BreakerOptions) -> Self {
+        CircuitBreaker {
+            options,
+            breaker: Mutex::new(Breaker {
+                state: BreakerState::Closed,
+                failures: 0,
+                opened_at: None,
+            }),
+        }
+    }
+
+    pub fn options(&self) -> &BreakerOptions {
+        &self.options
+    }
+
+    // the state at `now`, which becomes half-open once `open_duration` passes
+    pub fn state(&self, now: Instant) -> BreakerState {
+        let mut breaker = self.breaker.lock().unwrap();
+        if breaker.state == BreakerState::Open
+            && breaker.opened_at.is_some_and(|opened_at| {
+                now.saturating_duration_since(opened_at) >= self.options.open_duration
+            })
+        {
+            breaker.state = BreakerState::HalfOpen;
+        }
+        breaker.state
+    }
+
+    // whether a message can be sent at `now`
+    pub fn allows(&self, now: Instant) -> bool {
+        self.state(now) != BreakerState::Open
+    }
+
+    // the state before the success, to tell the breaker is closed by it
+    pub fn record_success(&self) -> BreakerState {
+        let mut breaker = self.breaker.lock().unwrap();
+        let previous = breaker.state;
+        breaker.state = BreakerState::Closed;
+        breaker.failures = 0;
+        breaker.opened_at = None;
+        previous
+    }
+
+    // whether the breaker is opened by the failure
+    pub fn record_failure(&self, now: Instant) -> bool {
+        let mut breaker = self.breaker.lock().unwrap();
+        breaker.failures += 1;
+        let opens = match breaker.state {
+            BreakerState::Closed => breaker.failures >= self.options.failure_threshold,
+            BreakerState::HalfOpen => true,
+            BreakerState::Open => false,
+        };
+        if opens {
+            breaker.state = BreakerState::Open;
+            breaker.opened_at = Some(now);
+        }
+        opens
+    }
+
+    // the failures in a row since the last message sent
+    pub fn failures(&self) -> u32 {
+        self.breaker.lock().unwrap().failures
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_circuit_breaker() {
+        let breaker = CircuitBreaker::new(BreakerOptions {
+            failure_threshold: 2,
+            open_duration: Duration::from_secs(60),
+        });
+        let start = Instant::now();
+        assert!(breaker.allows(start));
+        assert!(!breaker.record_failure(start));
+        assert_eq!(breaker.record_success(), BreakerState::Closed);
+
+        assert!(!breaker.record_failure(start));
+        assert!(breaker.record_failure(start));
+        assert_eq!(breaker.failures(), 2);
+        assert_eq!(breaker.state(start), BreakerState::Open);
+        assert!(!breaker.allows(start + Duration::from_secs(59)));
+
+        // the trial fails and the breaker opens again from then
+        let later = start + Duration::from_secs(60);
+        assert_eq!(breaker.state(later), BreakerState::HalfOpen);
+        assert!(breaker.allows(later));
+        assert!(breaker.record_failure(later));
+        assert!(!breaker.allows(later + Duration::from_secs(1)));
+
+        let later = later + Duration::from_secs(60);
+        assert!(breaker.allows(later));
+        assert_eq!(breaker.record_success(), BreakerState::HalfOpen);
+        assert_eq!(breaker.state(later), BreakerState::Closed);
+        assert_eq!(breaker.failures(), 0);
+    }
+
+    #[test]
+    fn test_raw_breaker() {
+        let raw: RawBreaker = serde_json::from_str(r#"{"open_secs": 30}"#).unwrap();
+        assert_eq!(
+            raw.parse(),
+            Ok(BreakerOptions {
+                failure_threshold: DEFAULT_BREAKER_FAILURE_THRESHOLD,
+                open_duration: Duration::from_secs(30),
+            })
+        );
+        let raw: RawBreaker = serde_json::from_str(r#"{"failure_threshold": 0}"#).unwrap();
+        assert!(raw.parse().is_err());
+    }
+}
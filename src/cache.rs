@@ -0,0 +1,158 @@
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+use url::Url;
+
+// keep cached pages for an hour unless `html_cache_ttl_secs` is configured
+pub const DEFAULT_HTML_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+// Stores fetched html files in a directory, named after the sha256 of their url.
+#[derive(Debug, Clone)]
+pub struct HtmlCache {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+impl HtmlCache {
+    pub fn new(dir: PathBuf, ttl: Duration) -> Self {
+        HtmlCache { dir, ttl }
+    }
+
+    fn path(&self, url: &Url) -> PathBuf {
+        let hash = Sha256::digest(url.as_str().as_bytes());
+        self.dir.join(format!("{:x}.html", hash))
+    }
+
+    // get the cached html unless it is older than the ttl
+    pub fn get(&self, url: &Url) -> Option<String> {
+        let path = self.path(url);
+        let modified = fs::metadata(&path).and_then(|meta| meta.modified()).ok()?;
+        let age = SystemTime::now()
+            .duration_since(modified)
+            .unwrap_or_default();
+        if age > self.ttl {
+            return None;
+        }
+        fs::read_to_string(path).ok()
+    }
+
+    pub fn put(&self, url: &Url, html: &str) -> std::io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.path(url), html)
+    }
+}
+
+// keep up to 100 pages in memory unless `memory_cache_capacity` is configured
+pub const DEFAULT_MEMORY_CACHE_CAPACITY: usize = 100;
+
+// Keeps fetched html in memory for the daemon, evicting the least recently used one when full.
+#[derive(Debug)]
+pub struct MemoryCache {
+    ttl: Duration,
+    capacity: usize,
+    entries: Mutex<MemoryCacheEntries>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryCacheEntries {
+    // the url to the html, when it was fetched and when it was used in `clock`
+    pages: HashMap<Url, (String, Instant, u64)>,
+    clock: u64,
+}
+
+impl MemoryCache {
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        MemoryCache {
+            ttl,
+            capacity,
+            entries: Mutex::default(),
+        }
+    }
+
+    pub fn get(&self, url: &Url) -> Option<String> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.clock += 1;
+        let clock = entries.clock;
+        let (html, fetched, used) = entries.pages.get_mut(url)?;
+        if fetched.elapsed() > self.ttl {
+            entries.pages.remove(url);
+            return None;
+        }
+        *used = clock;
+        Some(html.clone())
+    }
+
+    pub fn put(&self, url: &Url, html: &str) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        entries.clock += 1;
+        let clock = entries.clock;
+        if !entries.pages.contains_key(url) && entries.pages.len() >= self.capacity {
+            let lru = entries
+                .pages
+                .iter()
+                .min_by_key(|(_, (_, _, used))| *used)
+                .map(|(url, _)| url.clone());
+            if let Some(lru) = lru {
+                entries.pages.remove(&lru);
+            }
+        }
+        entries
+            .pages
+            .insert(url.clone(), (html.to_string(), Instant::now(), clock));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = Url::parse("https://www.ap-siken.com/").unwrap();
+        let other = Url::parse("https://www.nw-siken.com/").unwrap();
+
+        let cache = HtmlCache::new(dir.path().join("html"), DEFAULT_HTML_CACHE_TTL);
+        assert_eq!(cache.get(&url), None);
+        cache.put(&url, "<html></html>").unwrap();
+        assert_eq!(cache.get(&url).as_deref(), Some("<html></html>"));
+        assert_eq!(cache.get(&other), None);
+
+        // expired
+        std::thread::sleep(Duration::from_millis(10));
+        let cache = HtmlCache::new(dir.path().join("html"), Duration::ZERO);
+        assert_eq!(cache.get(&url), None);
+    }
+
+    #[test]
+    fn test_memory_cache() {
+        let urls = [
+            "https://www.ap-siken.com/",
+            "https://www.nw-siken.com/",
+            "https://www.db-siken.com/",
+        ]
+        .map(|url| Url::parse(url).unwrap());
+
+        let cache = MemoryCache::new(DEFAULT_HTML_CACHE_TTL, 2);
+        cache.put(&urls[0], "ap");
+        cache.put(&urls[1], "nw");
+        assert_eq!(cache.get(&urls[0]).as_deref(), Some("ap"));
+        // nw is the least recently used
+        cache.put(&urls[2], "db");
+        assert_eq!(cache.get(&urls[1]), None);
+        assert_eq!(cache.get(&urls[0]).as_deref(), Some("ap"));
+        assert_eq!(cache.get(&urls[2]).as_deref(), Some("db"));
+
+        // expired
+        let cache = MemoryCache::new(Duration::ZERO, 2);
+        cache.put(&urls[0], "ap");
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(cache.get(&urls[0]), None);
+    }
+}
@@ -0,0 +1,109 @@
+use log::warn;
+use std::io::{self, BufRead, IsTerminal, Write};
+
+// an answer to the prompt of `--interactive`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Answer {
+    Yes,
+    No,
+    // send this one and the rest without asking
+    All,
+}
+
+pub fn parse_answer(line: &str) -> Option<Answer> {
+    match line.trim().to_ascii_lowercase().as_str() {
+        "y" | "yes" => Some(Answer::Yes),
+        "n" | "no" => Some(Answer::No),
+        "a" | "all" => Some(Answer::All),
+        _ => None,
+    }
+}
+
+// Asks whether to send each question with `--interactive`, showing the preview of it on stderr.
+// Nobody can answer without a terminal, so the questions are sent without asking then.
+pub struct Confirm {
+    asking: bool,
+}
+
+impl Confirm {
+    pub fn new(interactive: bool) -> Self {
+        let tty = io::stdin().is_terminal() && io::stderr().is_terminal();
+        if interactive && !tty {
+            warn!("--interactive is ignored as stdin is not a terminal, the questions are sent without asking");
+        }
+        Confirm {
+            asking: interactive && tty,
+        }
+    }
+
+    pub fn is_asking(&self) -> bool {
+        self.asking
+    }
+
+    // the preview is made only when it is shown
+    pub fn approve(&mut self, preview: impl FnOnce() -> String) -> bool {
+        if !self.asking {
+            return true;
+        }
+        self.ask(&preview(), &mut io::stdin().lock(), &mut io::stderr())
+    }
+
+    // asks again until y, n or a is answered. nothing is sent after the input is closed.
+    fn ask(&mut self, preview: &str, input: &mut impl BufRead, output: &mut impl Write) -> bool {
+        let _ = writeln!(output, "{}", preview);
+        loop {
+            let _ = write!(output, "send it? [y/n/a] ");
+            let _ = output.flush();
+            let mut line = String::new();
+            match input.read_line(&mut line) {
+                Ok(0) | Err(_) => {
+                    let _ = writeln!(output);
+                    return false;
+                }
+                Ok(_) => {}
+            }
+            match parse_answer(&line) {
+                Some(Answer::Yes) => return true,
+                Some(Answer::No) => return false,
+                Some(Answer::All) => {
+                    self.asking = false;
+                    return true;
+                }
+                None => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_confirm() {
+        assert_eq!(parse_answer(" Y\n"), Some(Answer::Yes));
+        assert_eq!(parse_answer("no"), Some(Answer::No));
+        assert_eq!(parse_answer("a"), Some(Answer::All));
+        assert_eq!(parse_answer(""), None);
+
+        let mut confirm = Confirm { asking: true };
+        let mut output = Vec::new();
+        let mut input = Cursor::new("x\nn\ny\n");
+        assert!(!confirm.ask("問1", &mut input, &mut output));
+        assert!(confirm.ask("問2", &mut input, &mut output));
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "問1\nsend it? [y/n/a] send it? [y/n/a] 問2\nsend it? [y/n/a] "
+        );
+        // the input is closed
+        assert!(!confirm.ask("問3", &mut input, &mut Vec::new()));
+        assert!(confirm.is_asking());
+
+        let mut input = Cursor::new("a\n");
+        assert!(confirm.ask("問4", &mut input, &mut Vec::new()));
+        assert!(!confirm.is_asking());
+        assert!(confirm.approve(|| unreachable!()));
+        assert!(Confirm::new(false).approve(|| unreachable!()));
+    }
+}
@@ -0,0 +1,26 @@
+use crate::extract::Kakomon;
+use crate::format::{format_text, FormatOptions};
+use serde_json::{json, Value};
+
+// discord rejects an embed with a longer description
+const MAX_DESCRIPTION_CHARS: usize = 4096;
+
+// build the webhook payload with an embed linking to the answer page.
+// the `note` on what is trimmed goes to the footer.
+pub fn to_discord_body(kakomon: &Kakomon, options: &FormatOptions, note: Option<&str>) -> Value {
+    let description = format_text(kakomon, options)
+        .chars()
+        .take(MAX_DESCRIPTION_CHARS)
+        .collect::<String>();
+    let mut embed = json!({
+        "title": kakomon.title,
+        "description": description,
+    });
+    if let Some(url) = &kakomon.answer_url {
+        embed["url"] = json!(url.as_str());
+    }
+    if let Some(note) = note {
+        embed["footer"] = json!({ "text": note });
+    }
+    json!({ "embeds": [embed] })
+}
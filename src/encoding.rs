@@ -0,0 +1,109 @@
+use encoding_rs::{Encoding, UTF_8};
+use log::{debug, warn};
+use reqwest::header::{HeaderMap, CONTENT_TYPE};
+
+// `<meta charset>` is looked for in this many bytes from the head, as the browsers do
+const META_PRESCAN_BYTES: usize = 1024;
+
+// the label after `charset=`, e.g. `Shift_JIS` of `text/html; charset="Shift_JIS"`
+fn charset_label(text: &str) -> Option<&str> {
+    let start = text.to_ascii_lowercase().find("charset=")? + "charset=".len();
+    let label = text[start..].trim_start_matches(['"', '\'']);
+    let end = label
+        .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ':' | '.')))
+        .unwrap_or(label.len());
+    Some(&label[..end]).filter(|label| !label.is_empty())
+}
+
+fn header_encoding(headers: &HeaderMap) -> Option<&'static Encoding> {
+    let content_type = headers.get(CONTENT_TYPE)?.to_str().ok()?;
+    Encoding::for_label(charset_label(content_type)?.as_bytes())
+}
+
+// `<meta charset="...">` or `<meta http-equiv="Content-Type" content="...; charset=...">`
+fn meta_encoding(bytes: &[u8]) -> Option<&'static Encoding> {
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(META_PRESCAN_BYTES)]);
+    let lower = head.to_ascii_lowercase();
+    lower.match_indices("<meta").find_map(|(start, _)| {
+        let end = lower[start..]
+            .find('>')
+            .map_or(lower.len(), |end| start + end);
+        let encoding = Encoding::for_label(charset_label(&head[start..end])?.as_bytes())?;
+        // the page is read as ascii to find it, so it cannot be utf-16
+        Some(encoding.output_encoding())
+    })
+}
+
+// Decodes the body of a page. The BOM decides first, then the charset of `Content-Type`
+// and the one of `<meta>`, the first of them decoding the body without errors.
+// Some sites send a wrong `Content-Type`, which is why `<meta>` is tried as well.
+// UTF-8 is assumed with a warning if none of them works.
+pub fn decode_body(bytes: &[u8], headers: &HeaderMap) -> String {
+    if let Some((encoding, bom_len)) = Encoding::for_bom(bytes) {
+        debug!("decode the page as {} by the BOM", encoding.name());
+        return encoding
+            .decode_without_bom_handling(&bytes[bom_len..])
+            .0
+            .into_owned();
+    }
+    let candidates = [header_encoding(headers), meta_encoding(bytes)];
+    for encoding in candidates.iter().flatten() {
+        if let Some(text) = encoding.decode_without_bom_handling_and_without_replacement(bytes) {
+            debug!("decode the page as {}", encoding.name());
+            return text.into_owned();
+        }
+        debug!("the page is not in {}", encoding.name());
+    }
+    let (text, had_errors) = UTF_8.decode_without_bom_handling(bytes);
+    if had_errors {
+        warn!("failed to find the encoding of the page, decoded it as UTF-8");
+    } else if candidates.iter().all(Option::is_none) {
+        debug!("the page has no charset, decoded it as UTF-8");
+    }
+    text.into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_decode_body() {
+        let shift_jis = include_bytes!("../testdata/shift_jis.html");
+        let none = HeaderMap::new();
+        let text = decode_body(shift_jis, &none);
+        assert!(text.contains("ストアドプロシージャ"));
+        assert!(text.contains("平成21年春期　応用情報技術者 問31"));
+
+        // a wrong charset of `Content-Type` falls back to the one of `<meta>`
+        let mut utf8 = HeaderMap::new();
+        utf8.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("text/html; charset=UTF-8"),
+        );
+        assert_eq!(decode_body(shift_jis, &utf8), text);
+        let mut sjis = HeaderMap::new();
+        sjis.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static(r#"text/html; charset="shift_jis""#),
+        );
+        assert_eq!(decode_body("問題".as_bytes(), &sjis), "問題");
+        let (euc_jp, _, _) = encoding_rs::EUC_JP.encode("<html>問題</html>");
+        let mut euc = HeaderMap::new();
+        euc.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("text/html; charset=EUC-JP"),
+        );
+        assert_eq!(decode_body(&euc_jp, &euc), "<html>問題</html>");
+
+        let bom = [b"\xEF\xBB\xBF".as_slice(), "問題".as_bytes()].concat();
+        assert_eq!(decode_body(&bom, &sjis), "問題");
+        assert_eq!(
+            decode_body(br#"<meta charset="utf-8"><p>ok</p>"#, &none),
+            r#"<meta charset="utf-8"><p>ok</p>"#
+        );
+        // broken bytes are replaced
+        assert_eq!(decode_body(b"\xFF ok", &none), "\u{FFFD} ok");
+    }
+}
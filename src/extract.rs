@@ -0,0 +1,1640 @@
+use crate::format::format_choices;
+use log::{debug, info};
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use unicode_normalization::UnicodeNormalization;
+use url::Url;
+
+// A selector from a literal, compiled once on the first use and kept in a static.
+macro_rules! selector {
+    ($css:expr) => {{
+        static SELECTOR: once_cell::sync::Lazy<scraper::Selector> =
+            once_cell::sync::Lazy::new(|| scraper::Selector::parse($css).unwrap());
+        &*SELECTOR
+    }};
+}
+pub(crate) use selector;
+
+// classes/ids excluded from the extracted text unless `exclude_selectors` is configured
+const DEFAULT_EXCLUDE_SELECTORS: &[&str] = &["ad", "ads", "advertisement", "adsbygoogle", "nav"];
+
+// A bare name (e.g. `ad`) matches elements whose class, id or tag name is that name.
+// Anything else is used as a css selector as is.
+pub fn parse_exclude_selectors<S: AsRef<str>>(
+    entries: &[S],
+) -> Result<Vec<Selector>, Box<dyn std::error::Error>> {
+    entries
+        .iter()
+        .map(|entry| {
+            let entry = entry.as_ref().trim();
+            let is_name = !entry.is_empty()
+                && entry
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+            let selector = if is_name {
+                format!(".{0}, #{0}, {0}", entry)
+            } else {
+                entry.to_string()
+            };
+            Selector::parse(selector.as_str())
+                .map_err(|_| format!("invalid exclude selector: {:?}", entry).into())
+        })
+        .collect()
+}
+
+// candidates of the title in the order of priority unless `title_selectors` is configured
+const DEFAULT_TITLE_SELECTORS: &[&str] = &["div.anslink", ".title", "h2", "h3"];
+
+// tried when none of the title selectors matches
+const FALLBACK_TITLE_SELECTOR: &str = "h1, h2, h3, h4, h5, h6";
+
+// the title when neither the question nor the `<title>` of the page has one
+pub const UNTITLED: &str = "(無題)";
+
+pub fn parse_selectors<S: AsRef<str>>(
+    entries: &[S],
+) -> Result<Vec<Selector>, Box<dyn std::error::Error>> {
+    entries
+        .iter()
+        .map(|entry| {
+            Selector::parse(entry.as_ref())
+                .map_err(|_| format!("invalid selector: {:?}", entry.as_ref()).into())
+        })
+        .collect()
+}
+
+// How the statement is taken from the html, `text_mode` in CONFIG
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TextMode {
+    #[default]
+    Plain,
+    // the bold, the italic, the lists and the links kept in markdown, see `element_markdown`
+    Markdown,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExtractOptions {
+    // elements removed (with their subtree) before extraction
+    pub exclude_selectors: Vec<Selector>,
+    // the first one matching a non-empty element gives the title
+    pub title_selectors: Vec<Selector>,
+    // remove the site name from the `<title>` used when the question has no title
+    pub title_trim_site_name: bool,
+    // apply NFKC to the title, the statement and the choices, e.g. "ＳＱＬ" into "SQL"
+    pub normalize_text: bool,
+    // extract the explanation in `#kaisetsu` of the question pages
+    pub explanation: bool,
+    // the first one matching a non-empty element gives the difficulty
+    pub difficulty_selectors: Vec<Selector>,
+    // the elements right after `div.kako` matching one of these are a part of the question, none by default
+    pub kako_sibling_selectors: Vec<Selector>,
+    pub text_mode: TextMode,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        ExtractOptions {
+            exclude_selectors: parse_exclude_selectors(DEFAULT_EXCLUDE_SELECTORS)
+                .expect("invalid default exclude selectors"),
+            title_selectors: parse_selectors(DEFAULT_TITLE_SELECTORS)
+                .expect("invalid default title selectors"),
+            title_trim_site_name: false,
+            normalize_text: false,
+            explanation: false,
+            difficulty_selectors: parse_selectors(DEFAULT_DIFFICULTY_SELECTORS)
+                .expect("invalid default difficulty selectors"),
+            kako_sibling_selectors: Vec::new(),
+            text_mode: TextMode::Plain,
+        }
+    }
+}
+
+// Get the text of the first element matching the title selectors in order.
+// If none matches, the first heading is used. The result may be empty.
+pub fn extract_title(element: ElementRef, selectors: &[Selector]) -> String {
+    let fallback = selector!(FALLBACK_TITLE_SELECTOR);
+    selectors
+        .iter()
+        .chain(std::iter::once(fallback))
+        .flat_map(|selector| element.select(selector))
+        .map(|elem| elem.text().collect::<Vec<_>>().join("").trim().to_string())
+        .find(|title| !title.is_empty())
+        .unwrap_or_default()
+}
+
+// candidates of the difficulty in the order of priority unless `difficulty_selectors` is configured
+const DEFAULT_DIFFICULTY_SELECTORS: &[&str] = &[".difficulty", ".nanido"];
+
+// the text of the first element matching the difficulty selectors, e.g. "★★★" or "正答率 45%"
+fn extract_difficulty(element: ElementRef, selectors: &[Selector]) -> Option<String> {
+    selectors
+        .iter()
+        .flat_map(|selector| element.select(selector))
+        .map(|elem| elem.text().collect::<String>())
+        .map(|text| text.split_whitespace().collect::<Vec<_>>().join(" "))
+        .find(|difficulty| !difficulty.is_empty())
+}
+
+// the characters of the choices in the order
+const ANSWER_CHARS: &[&str] = &["ア", "イ", "ウ", "エ", "オ", "カ", "キ", "ク"];
+
+// Saved as json to send it again offline, with the urls as strings.
+// The fields missing in the json, e.g. saved by an older version, are empty.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Kakomon {
+    pub title: String,
+    #[serde(default)]
+    pub answer_url: Option<Url>,
+    // the problem statement
+    #[serde(default)]
+    pub mondai: String,
+    #[serde(default)]
+    pub choices: Vec<String>,
+    #[serde(default)]
+    pub images: Vec<Url>,
+    // the correct choice as written on the page, e.g. "ウ"
+    #[serde(default)]
+    pub answer: Option<String>,
+    // only extracted with `ExtractOptions::explanation`
+    #[serde(default)]
+    pub explanation: Option<String>,
+    // the first part of the classification, e.g. "テクノロジ系"
+    #[serde(default)]
+    pub category: Option<String>,
+    // e.g. "★★★", only when the page has it
+    #[serde(default)]
+    pub difficulty: Option<String>,
+    // the ISO 639-3 code of the language of the statement, e.g. "jpn" or "eng",
+    // unless it is too short to tell
+    #[serde(default)]
+    pub language: Option<String>,
+    // the links in the statement in the order, whose texts are in `mondai`
+    #[serde(default)]
+    pub links: Vec<Link>,
+}
+
+// A link in the statement such as the source of it, sent as `<url|text>` to slack.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Link {
+    pub url: Url,
+    pub text: String,
+}
+
+impl Kakomon {
+    // the answer url, the statement, the numbered choices and the image urls, one per line
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        if let Some(url) = &self.answer_url {
+            text += url.as_str();
+            text += "\n";
+        }
+        text += self.mondai.as_str();
+        text += "\n";
+        text += format_choices(&self.choices, None).as_str();
+        for image in self.images.iter() {
+            text += image.as_str();
+            text += "\n";
+        }
+        text
+    }
+
+    // the index of the correct choice, from "ア", "イ", ... or "1", "2", ...
+    pub fn answer_index(&self) -> Option<usize> {
+        let answer = self.answer.as_deref()?.trim();
+        let index = match answer.parse::<usize>() {
+            Ok(number) => number.checked_sub(1)?,
+            Err(_) => ANSWER_CHARS.iter().position(|c| *c == answer)?,
+        };
+        (index < self.choices.len()).then_some(index)
+    }
+
+    // identifies the question, e.g. in the state file
+    pub fn key(&self) -> String {
+        match &self.answer_url {
+            Some(url) => url.to_string(),
+            None => self.title.clone(),
+        }
+    }
+
+    // the exam of the question from the title (`令和5年秋期 ... 問31`), or the url (`05_aki/q31.html`)
+    pub fn exam_meta(&self) -> Option<ExamMeta> {
+        exam_meta_of_title(&self.title).or_else(|| exam_meta_of_url(self.answer_url.as_ref()?))
+    }
+}
+
+// the characters of the statement shown by `Display`
+const DISPLAY_HEAD_CHARS: usize = 40;
+
+// The title, the head of the statement and the answer url, one per line, for the logs and `list`.
+impl fmt::Display for Kakomon {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.title)?;
+        let mondai = self.mondai.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !mondai.is_empty() {
+            let head = mondai.chars().take(DISPLAY_HEAD_CHARS).collect::<String>();
+            let ellipsis = if head.len() < mondai.len() { "…" } else { "" };
+            write!(f, "\n  {}{}", head, ellipsis)?;
+        }
+        if let Some(url) = &self.answer_url {
+            write!(f, "\n  {}", url)?;
+        }
+        Ok(())
+    }
+}
+
+// the spring exam of a year is before the autumn one
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum ExamSeason {
+    Spring,
+    // including the one held in october of 令和2年 instead of the spring one
+    Autumn,
+}
+
+// The exam and the number of a question, in the order of the exams.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ExamMeta {
+    // in the western calendar, e.g. 2023 of 令和5年
+    pub exam_year: u32,
+    pub exam_season: ExamSeason,
+    pub question_number: u32,
+}
+
+// the leading digits of the text, full-width ones as well
+fn leading_number(text: &str) -> Option<(u32, &str)> {
+    let digits = text
+        .chars()
+        .map_while(|c| match c {
+            '0'..='9' => c.to_digit(10),
+            '０'..='９' => Some(c as u32 - '０' as u32),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if digits.is_empty() {
+        return None;
+    }
+    let rest = &text[text
+        .char_indices()
+        .nth(digits.len())
+        .map_or(text.len(), |(i, _)| i)..];
+    Some((
+        digits.iter().fold(0, |number, digit| number * 10 + digit),
+        rest,
+    ))
+}
+
+fn exam_meta_of_title(title: &str) -> Option<ExamMeta> {
+    let (era, start) = [("平成", 1988), ("令和", 2018)]
+        .iter()
+        .find_map(|(era, start)| title.find(era).map(|i| (&title[i + era.len()..], start)))?;
+    let (year, rest) = match era.strip_prefix('元') {
+        Some(rest) => (1, rest),
+        None => leading_number(era)?,
+    };
+    let rest = rest.strip_prefix('年')?;
+    let exam_season = if rest.starts_with('春') {
+        ExamSeason::Spring
+    } else if rest.starts_with('秋') || rest.starts_with("10月") || rest.starts_with("１０月")
+    {
+        ExamSeason::Autumn
+    } else {
+        return None;
+    };
+    let (question_number, _) = leading_number(&rest[rest.find('問')? + '問'.len_utf8()..])?;
+    Some(ExamMeta {
+        exam_year: start + year,
+        exam_season,
+        question_number,
+    })
+}
+
+// `.../21_haru/q31.html`, whose year is in 平成 from 13 and in 令和 otherwise
+fn exam_meta_of_url(url: &Url) -> Option<ExamMeta> {
+    let mut segments = url.path_segments()?.rev();
+    let file = segments.next()?;
+    let (year, season) = segments.next()?.split_once('_')?;
+    let (question_number, _) = leading_number(file.strip_prefix('q')?)?;
+    let year = year.parse::<u32>().ok()?;
+    let exam_season = match season {
+        "haru" => ExamSeason::Spring,
+        "aki" | "oct" => ExamSeason::Autumn,
+        _ => return None,
+    };
+    Some(ExamMeta {
+        exam_year: if year >= 13 { 1988 + year } else { 2018 + year },
+        exam_season,
+        question_number,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageKind {
+    // the top page of the site with a "一問一答" block
+    Top,
+    // a page listing the questions of an exam (e.g. `/kakomon/21_haru/`)
+    List,
+    // a page of a single question (e.g. `/kakomon/21_haru/q31.html`)
+    Question,
+}
+
+// guess the page kind from the url alone
+fn page_kind_from_url(url: &Url) -> Option<PageKind> {
+    let segments = url
+        .path_segments()
+        .map(|segments| segments.filter(|s| !s.is_empty()).collect::<Vec<_>>())
+        .unwrap_or_default();
+    match segments.as_slice() {
+        [] | ["index.html"] => Some(PageKind::Top),
+        [.., last] if is_question_file_name(last) => Some(PageKind::Question),
+        ["kakomon", ..] => Some(PageKind::List),
+        _ => None,
+    }
+}
+
+// `q31.html` etc.
+fn is_question_file_name(name: &str) -> bool {
+    name.strip_prefix('q')
+        .and_then(|name| name.strip_suffix(".html"))
+        .is_some_and(|num| !num.is_empty() && num.chars().all(|c| c.is_ascii_digit()))
+}
+
+// check whether the document has the structure of the page kind
+fn has_page_structure(document: &Html, kind: PageKind) -> bool {
+    let has = |selector: &Selector| document.select(selector).next().is_some();
+    match kind {
+        PageKind::Top => has(selector!(r#"div.kako div.mondai"#)),
+        PageKind::Question => has(selector!(r#"h3.qno"#)) && has(selector!(r#".selectList"#)),
+        PageKind::List => {
+            document
+                .select(selector!(r#"a[href]"#))
+                .filter_map(|elem| elem.value().attr("href"))
+                .filter(|href| href.rsplit('/').next().is_some_and(is_question_file_name))
+                .count()
+                >= 2
+        }
+    }
+}
+
+// The url pattern is tried first and confirmed with the html structure.
+// If they disagree, the html structure decides.
+pub fn detect_page_kind(document: &Html, url: &Url) -> Option<PageKind> {
+    if let Some(kind) = page_kind_from_url(url) {
+        if has_page_structure(document, kind) {
+            return Some(kind);
+        }
+        debug!("{} looks like a {:?} page but its html does not", url, kind);
+    }
+    [PageKind::Question, PageKind::Top, PageKind::List]
+        .into_iter()
+        .find(|kind| has_page_structure(document, *kind))
+}
+
+// remove the elements matching the selectors together with their subtrees
+fn remove_elements(document: &mut Html, selectors: &[Selector]) {
+    let ids = selectors
+        .iter()
+        .flat_map(|selector| document.select(selector).map(|elem| elem.id()))
+        .collect::<Vec<_>>();
+    for id in ids {
+        if let Some(mut node) = document.tree.get_mut(id) {
+            node.detach();
+        }
+    }
+}
+
+// the characters of the raw text shown for each element by `debug_dump`
+const DEBUG_SNIPPET_CHARS: usize = 80;
+
+// What the extraction sees in the html, for `--debug-extract`: whether `div.kako` is there,
+// the counts of the elements looked for and the raw text of them before the extraction.
+pub fn debug_dump(html_text: &str) -> String {
+    let document = Html::parse_document(html_text);
+    let kako = document.select(selector!(r#"div.kako"#)).count();
+    let mut dump = format!(
+        "div.kako: {}\n",
+        match kako {
+            0 => "not found".to_string(),
+            n => format!("found ({})", n),
+        }
+    );
+    for (name, selector) in [
+        ("mondai", selector!(r#".mondai"#)),
+        ("anslink", selector!(r#".anslink"#)),
+        ("ansbg", selector!(r#".ansbg"#)),
+    ] {
+        let elements = document.select(selector).collect::<Vec<_>>();
+        dump += &format!(".{}: {}\n", name, elements.len());
+        for elem in elements {
+            let text = elem.text().collect::<String>();
+            let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+            let snippet = text.chars().take(DEBUG_SNIPPET_CHARS).collect::<String>();
+            let ellipsis = if snippet.len() < text.len() {
+                "…"
+            } else {
+                ""
+            };
+            dump += &format!("  {:?}{}\n", snippet, ellipsis);
+        }
+    }
+    dump
+}
+
+pub fn extract_kakomon(html_text: &str, url: Url, options: &ExtractOptions) -> Option<Kakomon> {
+    let mut document = Html::parse_document(html_text);
+    remove_elements(&mut document, &options.exclude_selectors);
+
+    let mut kakomon = match detect_page_kind(&document, &url) {
+        Some(PageKind::Top) => extract_from_top_page(&document, &url, options),
+        Some(PageKind::Question) => extract_from_question_page(&document, &url, options),
+        Some(PageKind::List) => {
+            info!("{} is a question list page, which is not supported", url);
+            None
+        }
+        None => {
+            info!("{} is not a page of questions", url);
+            None
+        }
+    }?;
+    sanitize_kakomon(&mut kakomon);
+    // the statement joined from several elements may have blank lines in a row.
+    // the lines are trimmed, including full-width spaces used for indentation.
+    kakomon.mondai = collapse_blank_lines(&kakomon.mondai);
+    for choice in kakomon.choices.iter_mut() {
+        *choice = collapse_blank_lines(choice);
+    }
+    kakomon.title = kakomon.title.trim().to_string();
+
+    // e.g. a "not found" page that happens to have the structure
+    if kakomon.title.trim().is_empty() && kakomon.text().trim().is_empty() {
+        info!("{}: skipped an empty question", url);
+        return None;
+    }
+
+    if kakomon.title.trim().is_empty() {
+        kakomon.title = document_title(&document, options.title_trim_site_name)
+            .unwrap_or_else(|| UNTITLED.to_string());
+    }
+    if options.normalize_text {
+        normalize_kakomon(&mut kakomon);
+    }
+    kakomon.language = detect_language(&kakomon.mondai);
+    Some(kakomon)
+}
+
+// the statements shorter than this are not detected, as the few characters are often misdetected
+const MIN_LANGUAGE_DETECTION_CHARS: usize = 20;
+
+// the ISO 639-3 code of the language of the text, only when whatlang is confident of it
+pub fn detect_language(text: &str) -> Option<String> {
+    if text.chars().filter(|c| !c.is_whitespace()).count() < MIN_LANGUAGE_DETECTION_CHARS {
+        return None;
+    }
+    whatlang::detect(text)
+        .filter(|info| info.is_reliable())
+        .map(|info| info.lang().code().to_string())
+}
+
+// Why `extract_kakomon` found no question in the page, for the report of the run:
+// the structure of the page missing, or an element needed for the question missing.
+pub fn extract_failure_reason(html_text: &str, url: &Url, options: &ExtractOptions) -> String {
+    let mut document = Html::parse_document(html_text);
+    remove_elements(&mut document, &options.exclude_selectors);
+    let has = |selector: &Selector| document.select(selector).next().is_some();
+    match detect_page_kind(&document, url) {
+        Some(PageKind::Top) => "div.kako without div.mondai".to_string(),
+        Some(PageKind::Question) if !has(selector!(r#"h3.qno + div"#)) => {
+            "no problem statement after h3.qno".to_string()
+        }
+        Some(PageKind::Question) => "an empty question".to_string(),
+        Some(PageKind::List) => "a question list page, which is not supported".to_string(),
+        None if has(selector!(r#"div.kako"#)) => "div.kako without div.mondai".to_string(),
+        None => "no div.kako nor question page".to_string(),
+    }
+}
+
+fn normalize_kakomon(kakomon: &mut Kakomon) {
+    let nfkc = |text: &str| text.nfkc().collect::<String>();
+    kakomon.title = nfkc(&kakomon.title);
+    kakomon.mondai = nfkc(&kakomon.mondai);
+    for choice in kakomon.choices.iter_mut() {
+        *choice = nfkc(choice);
+    }
+    if let Some(explanation) = kakomon.explanation.as_mut() {
+        *explanation = nfkc(explanation);
+    }
+    if let Some(difficulty) = kakomon.difficulty.as_mut() {
+        *difficulty = nfkc(difficulty);
+    }
+    for link in kakomon.links.iter_mut() {
+        link.text = nfkc(&link.text);
+    }
+}
+
+// the control characters break the messages, e.g. in slack
+fn sanitize_kakomon(kakomon: &mut Kakomon) {
+    kakomon.title = sanitize_text(&kakomon.title);
+    kakomon.mondai = sanitize_text(&kakomon.mondai);
+    for choice in kakomon.choices.iter_mut() {
+        *choice = sanitize_text(choice);
+    }
+    if let Some(answer) = kakomon.answer.as_mut() {
+        *answer = sanitize_text(answer);
+    }
+    if let Some(explanation) = kakomon.explanation.as_mut() {
+        *explanation = sanitize_text(explanation);
+    }
+    if let Some(difficulty) = kakomon.difficulty.as_mut() {
+        *difficulty = sanitize_text(difficulty);
+    }
+    for link in kakomon.links.iter_mut() {
+        link.text = sanitize_text(&link.text);
+    }
+}
+
+// the `<title>` of the document, without the site name (`... | AP過去問`) if `trim_site_name` is set
+fn document_title(document: &Html, trim_site_name: bool) -> Option<String> {
+    let title = document
+        .select(selector!(r#"title"#))
+        .next()?
+        .text()
+        .collect::<Vec<_>>()
+        .join("");
+    let mut title = title.trim();
+    if trim_site_name {
+        if let Some(idx) = title.rfind(['|', '｜']) {
+            title = title[..idx].trim_end();
+        }
+    }
+    Some(title.to_string()).filter(|title| !title.is_empty())
+}
+
+// get the first div element having "kako" class
+fn extract_from_top_page(document: &Html, url: &Url, options: &ExtractOptions) -> Option<Kakomon> {
+    for element in document.select(selector!(r#"div"#)) {
+        let mut mondai = Vec::new();
+        let mut choices = Vec::new();
+        let mut images = Vec::new();
+        let mut answer_url = None;
+        let mut category = None;
+        let mut links = Vec::new();
+
+        if element.value().attr("class") == Some("kako") {
+            // get the url to the answer page
+            // the links without `href` or with a broken one are skipped
+            for elem2 in element.select(selector!(r#"a"#)) {
+                if let Some(link) = elem2
+                    .value()
+                    .attr("href")
+                    .and_then(|href| url.join(href).ok())
+                {
+                    answer_url.get_or_insert(link);
+                }
+            }
+
+            // get the problem statement
+            for elem2 in element.select(selector!(r#"div"#)) {
+                match elem2.value().attr("class") {
+                    Some("mondai") => {
+                        mondai.push(statement_text(elem2, url, options.text_mode));
+                        links.extend(statement_links(elem2, url));
+                    }
+                    Some("anslink") => {
+                        // e.g. "平成21年春期　応用情報技術者 問31 [テクノロジ系]"
+                        category = category_of_anslink(&elem2.text().collect::<String>());
+                    }
+                    Some("ansbg") => {
+                        // answer background
+                        for elem3 in elem2.select(selector!(r#"ul > li"#)) {
+                            choices.push(elem3.text().collect::<Vec<_>>().join(""));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            if mondai.is_empty() {
+                info!(
+                    "{}: skipped a kako block without the problem statement",
+                    url
+                );
+                continue;
+            }
+            let title = extract_title(element, &options.title_selectors);
+
+            // e.g. the description of a figure put after the block
+            let siblings = kako_siblings(element, &options.kako_sibling_selectors);
+            for sibling in siblings.iter() {
+                mondai.push(statement_text(*sibling, url, options.text_mode));
+                links.extend(statement_links(*sibling, url));
+            }
+
+            // get urls of images
+            for elem2 in std::iter::once(element)
+                .chain(siblings)
+                .flat_map(|elem| elem.select(selector!(r#"img"#)))
+            {
+                if let Some(src) = elem2.value().attr("src").and_then(|src| url.join(src).ok()) {
+                    images.push(src);
+                }
+            }
+            return Some(Kakomon {
+                title,
+                answer_url,
+                mondai: mondai.join("\n"),
+                choices,
+                images,
+                answer: None,
+                // the top page only links to the question page with the explanation
+                explanation: None,
+                category,
+                difficulty: extract_difficulty(element, &options.difficulty_selectors),
+                // detected after the text is cleaned up
+                language: None,
+                links,
+            });
+        }
+    }
+
+    None
+}
+
+// The elements right after the kako block matching one of the selectors, up to the first one not matching.
+// The next kako block is not one of them even if it matches.
+fn kako_siblings<'a>(element: ElementRef<'a>, selectors: &[Selector]) -> Vec<ElementRef<'a>> {
+    if selectors.is_empty() {
+        return Vec::new();
+    }
+    element
+        .next_siblings()
+        .filter_map(ElementRef::wrap)
+        .take_while(|sibling| {
+            sibling.value().attr("class") != Some("kako")
+                && selectors.iter().any(|selector| selector.matches(sibling))
+        })
+        .collect()
+}
+
+// a question page has the title in `h2`, the statement right after `h3.qno` and the choices in `#ans`
+fn extract_from_question_page(
+    document: &Html,
+    url: &Url,
+    options: &ExtractOptions,
+) -> Option<Kakomon> {
+    // look for the title in the main column not to pick up the site header
+    let main = document
+        .select(selector!(r#"main"#))
+        .next()
+        .unwrap_or_else(|| document.root_element());
+    let title = extract_title(main, &options.title_selectors);
+
+    // get the problem statement
+    let mondai = match document.select(selector!(r#"h3.qno + div"#)).next() {
+        Some(mondai) => mondai,
+        None => {
+            info!(
+                "{}: skipped a question page without the problem statement",
+                url
+            );
+            return None;
+        }
+    };
+
+    // get the choices without the "ア", "イ", ... buttons
+    let choice_selector = selector!(r#"#ans .selectList > li"#);
+    let choices = document
+        .select(choice_selector)
+        .map(|choice| {
+            choice
+                .children()
+                .filter_map(ElementRef::wrap)
+                .filter(|child| child.value().name() != "button")
+                .map(|child| child.text().collect::<Vec<_>>().join(""))
+                .collect::<String>()
+        })
+        .collect();
+
+    // get urls of images in the statement and the choices
+    let img_selector = selector!(r#"img"#);
+    let ans_img_selector = selector!(r#"#ans img"#);
+    let images = mondai
+        .select(img_selector)
+        .chain(document.select(ans_img_selector))
+        .filter_map(|img| img.value().attr("src").and_then(|src| url.join(src).ok()))
+        .collect();
+
+    let answer = document
+        .select(selector!(r#"#answerChar"#))
+        .next()
+        .map(|elem| elem.text().collect::<String>().trim().to_string())
+        .filter(|answer| !answer.is_empty());
+
+    Some(Kakomon {
+        title,
+        // the page itself is the answer page
+        answer_url: Some(url.clone()),
+        mondai: statement_text(mondai, url, options.text_mode),
+        choices,
+        images,
+        answer,
+        explanation: options
+            .explanation
+            .then(|| explanation_text(document))
+            .flatten(),
+        category: category_of_question_page(document),
+        difficulty: extract_difficulty(main, &options.difficulty_selectors),
+        // detected after the text is cleaned up
+        language: None,
+        links: statement_links(mondai, url),
+    })
+}
+
+// The anchors with a text in the statement. The ones without a text are left in `mondai` as their urls.
+fn statement_links(element: ElementRef, url: &Url) -> Vec<Link> {
+    element
+        .select(selector!(r#"a[href]"#))
+        .filter_map(|anchor| {
+            let text = anchor.text().collect::<String>().replace(['\r', '\n'], " ");
+            let text = text.trim();
+            let link = url.join(anchor.value().attr("href")?).ok()?;
+            (!text.is_empty()).then(|| Link {
+                url: link,
+                text: text.to_string(),
+            })
+        })
+        .collect()
+}
+
+// the category in the brackets at the end of the anslink of the top page
+fn category_of_anslink(text: &str) -> Option<String> {
+    let (_, category) = text.trim().strip_suffix(']')?.rsplit_once('[')?;
+    Some(category.trim().to_string()).filter(|category| !category.is_empty())
+}
+
+// the first part of "分類<br>テクノロジ系 » データベース » ..." in `.grayBox` of a question page
+fn category_of_question_page(document: &Html) -> Option<String> {
+    document
+        .select(selector!(r#".grayBox"#))
+        .map(|elem| elem.text().collect::<String>())
+        .find_map(|text| {
+            let classification = text.trim().strip_prefix("分類")?;
+            let category = classification.split('»').next()?.trim();
+            Some(category.to_string()).filter(|category| !category.is_empty())
+        })
+}
+
+// the explanation in `#kaisetsu` of a question page
+fn explanation_text(document: &Html) -> Option<String> {
+    let selector = selector!(r#"#kaisetsu > div"#);
+    let text = document
+        .select(selector)
+        .map(element_text)
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    (!text.is_empty()).then_some(text)
+}
+
+// extract the explanation from the html of a question page, e.g. the one linked from the top page
+pub fn extract_explanation(html_text: &str, options: &ExtractOptions) -> Option<String> {
+    let mut document = Html::parse_document(html_text);
+    remove_elements(&mut document, &options.exclude_selectors);
+    let explanation = explanation_text(&document)?;
+    Some(match options.normalize_text {
+        true => explanation.nfkc().collect(),
+        false => explanation,
+    })
+}
+
+// the statement in the element as plain text or markdown
+fn statement_text(element: ElementRef, url: &Url, mode: TextMode) -> String {
+    match mode {
+        TextMode::Plain => element_text(element),
+        TextMode::Markdown => element_markdown(element, url),
+    }
+}
+
+// collect the text of the element.
+// `<br>` becomes a line break, `<p>` a paragraph break and nested tables are converted into markdown tables.
+fn element_text(element: ElementRef) -> String {
+    let mut text = String::new();
+    push_element_text(element, None, &mut text);
+    collapse_blank_lines(&text)
+}
+
+// `element_text` keeping `<b>` and `<strong>` as `**bold**`, `<i>` and `<em>` as `_italic_`,
+// the list items as `- ` or `1. ` and the links as `[text](url)` resolved on `url`.
+// the characters of the markdown syntax in the text are escaped with `\`.
+fn element_markdown(element: ElementRef, url: &Url) -> String {
+    let mut text = String::new();
+    push_element_text(element, Some(url), &mut text);
+    collapse_blank_lines(&text)
+}
+
+// the markdown is written with the base url of the links, and the plain text without it
+fn push_element_text(element: ElementRef, markdown: Option<&Url>, text: &mut String) {
+    for child in element.children() {
+        if let Some(elem) = ElementRef::wrap(child) {
+            match elem.value().name() {
+                "br" => *text += "\n",
+                // shown as the url itself
+                "a" if elem.text().all(|t| t.trim().is_empty()) => {
+                    *text += elem.value().attr("href").unwrap_or_default();
+                }
+                "a" if markdown.is_some() => {
+                    let link = elem
+                        .value()
+                        .attr("href")
+                        .and_then(|href| markdown?.join(href).ok());
+                    let label = inline_markdown(elem, markdown);
+                    match link {
+                        // `)` is allowed in urls but ends the link
+                        Some(link) => {
+                            *text += &format!("[{}]({})", label, link.as_str().replace(')', "%29"))
+                        }
+                        None => *text += &label,
+                    }
+                }
+                "b" | "strong" if markdown.is_some() => {
+                    push_emphasis(&inline_markdown(elem, markdown), "**", text);
+                }
+                "i" | "em" if markdown.is_some() => {
+                    push_emphasis(&inline_markdown(elem, markdown), "_", text);
+                }
+                name @ ("ul" | "ol") if markdown.is_some() => {
+                    *text += "\n\n";
+                    let items = elem
+                        .children()
+                        .filter_map(ElementRef::wrap)
+                        .filter(|item| item.value().name() == "li");
+                    for (idx, item) in items.enumerate() {
+                        let bullet = match name {
+                            "ol" => format!("{}. ", idx + 1),
+                            _ => "- ".to_string(),
+                        };
+                        *text += &format!("{}{}\n", bullet, inline_markdown(item, markdown));
+                    }
+                    *text += "\n";
+                }
+                "p" => {
+                    *text += "\n\n";
+                    push_element_text(elem, markdown, text);
+                    *text += "\n\n";
+                }
+                "table" => {
+                    // a markdown table needs blank lines around it
+                    *text += "\n\n";
+                    *text += table_to_markdown(elem).as_str();
+                    *text += "\n\n";
+                }
+                _ => push_element_text(elem, markdown, text),
+            }
+        } else if let Some(t) = child.value().as_text() {
+            // line breaks in the source are just whitespace in html
+            let t = t.replace(['\r', '\n'], " ");
+            match markdown {
+                Some(_) => *text += escape_markdown(&t).as_str(),
+                None => *text += t.as_str(),
+            }
+        }
+    }
+}
+
+// the markdown of an element in a line, e.g. a list item or the label of a link
+fn inline_markdown(element: ElementRef, markdown: Option<&Url>) -> String {
+    let mut text = String::new();
+    push_element_text(element, markdown, &mut text);
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// the markers need to be next to the text, e.g. `**bold**` rather than `** bold **`
+fn push_emphasis(inner: &str, marker: &str, text: &mut String) {
+    match inner.trim() {
+        "" => *text += inner,
+        trimmed => *text += &format!("{}{}{}", marker, trimmed, marker),
+    }
+}
+
+// the characters taken as the emphasis, the code or the links in markdown
+pub fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// Remove the C0 and C1 control characters except the line breaks and the tabs,
+// and the BOM and the zero width spaces, which are not printed either.
+fn sanitize_text(text: &str) -> String {
+    text.chars()
+        .filter(|&c| {
+            c == '\n' || c == '\t' || !(c.is_control() || c == '\u{feff}' || c == '\u{200b}')
+        })
+        .collect()
+}
+
+// trim each line and limit consecutive line breaks to two (i.e. a single blank line)
+fn collapse_blank_lines(text: &str) -> String {
+    let mut lines: Vec<&str> = Vec::new();
+    for line in text.split('\n').map(str::trim) {
+        if line.is_empty() && lines.last().is_some_and(|last| last.is_empty()) {
+            continue;
+        }
+        lines.push(line);
+    }
+    lines.join("\n").trim_matches('\n').to_string()
+}
+
+// the largest spans browsers take, not to expand a broken table into billions of cells
+const MAX_COLSPAN: usize = 1000;
+const MAX_ROWSPAN: usize = 65534;
+
+// convert a table element into a markdown table.
+// colspan is expanded into empty cells on the right, rowspan into empty cells on the rows below.
+fn table_to_markdown(element: ElementRef) -> String {
+    let mut rows: Vec<Vec<String>> = Vec::new();
+    // the number of rows each column is still occupied by a rowspan cell
+    let mut spans: Vec<usize> = Vec::new();
+
+    let fill_spanned = |row: &mut Vec<String>, spans: &mut Vec<usize>| {
+        while row.len() < spans.len() && spans[row.len()] > 0 {
+            spans[row.len()] -= 1;
+            row.push(String::new());
+        }
+    };
+
+    for tr in element.select(selector!(r#"tr"#)) {
+        let mut row = Vec::new();
+
+        for cell in tr.children().filter_map(ElementRef::wrap) {
+            if !matches!(cell.value().name(), "th" | "td") {
+                continue;
+            }
+            fill_spanned(&mut row, &mut spans);
+
+            // a number too large to parse is taken as the largest one
+            let span_of = |name: &str, max: usize| {
+                cell.value()
+                    .attr(name)
+                    .map(str::trim)
+                    .filter(|v| !v.is_empty() && v.chars().all(|c| c.is_ascii_digit()))
+                    .map_or(1, |v| v.parse::<usize>().unwrap_or(max))
+                    .clamp(1, max)
+            };
+            let colspan = span_of("colspan", MAX_COLSPAN);
+            let rowspan = span_of("rowspan", MAX_ROWSPAN);
+
+            let text = cell
+                .text()
+                .collect::<Vec<_>>()
+                .join("")
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+                .replace('|', "\\|");
+            for i in 0..colspan {
+                let col = row.len();
+                if spans.len() <= col {
+                    spans.resize(col + 1, 0);
+                }
+                spans[col] = rowspan - 1;
+                row.push(if i == 0 { text.clone() } else { String::new() });
+            }
+        }
+        fill_spanned(&mut row, &mut spans);
+        rows.push(row);
+    }
+
+    let width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+    if width == 0 {
+        return String::new();
+    }
+
+    let mut markdown = String::new();
+    for (row_idx, row) in rows.iter().enumerate() {
+        let cells = (0..width)
+            .map(|i| row.get(i).map(|s| s.as_str()).unwrap_or(""))
+            .collect::<Vec<_>>();
+        markdown += format!("| {} |\n", cells.join(" | ")).as_str();
+        if row_idx == 0 {
+            markdown += format!("|{}\n", " --- |".repeat(width)).as_str();
+        }
+    }
+    markdown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_kakomon_url_from_home() {
+        let html_text = include_str!("../testdata/home.html");
+        let url = Url::parse("https://www.ap-siken.com/").expect("invalid url");
+        let kakomon = extract_kakomon(html_text, url, &ExtractOptions::default()).unwrap();
+
+        let left = kakomon.text();
+        let right = r#"https://www.ap-siken.com/kakomon/21_haru/q31.html
+クライアントサーバシステムにおけるストアドプロシージャに関する記述のうち，誤っているものはどれか。
+1. 機密性の高いデータに対する処理を特定のプロシージャ呼出しに限定することによって，セキュリティを向上させることができる。
+2. システム全体に共通な処理をプロシージャとして格納することによって，処理の標準化を行うことができる。
+3. データベースへのアクセスを細かい単位でプロシージャ化することによって，処理性能(スループット)を向上させることができる。
+4. 複数のSQL文から成る手続を1回の呼出しで実行できるので，クライアントとサーバ間の通信回数を減らすことができる。
+"#;
+        println!("{}", &left);
+        println!("{}", &right);
+        assert_eq!(left.as_str(), right);
+        assert_eq!(kakomon.category.as_deref(), Some("テクノロジ系"));
+        assert_eq!(
+            category_of_anslink("平成21年春期　応用情報技術者 問31"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_debug_dump() {
+        let html_text = r#"<html><body><div class="kako">
+            <div class="mondai">ストアドプロシージャに
+              関する記述のうち，誤っているものはどれか。</div>
+            <div class="anslink"><a href="q31.html">解答</a></div>
+        </div></body></html>"#;
+        assert_eq!(
+            debug_dump(html_text),
+            "div.kako: found (1)\n\
+             .mondai: 1\n  \"ストアドプロシージャに 関する記述のうち，誤っているものはどれか。\"\n\
+             .anslink: 1\n  \"解答\"\n\
+             .ansbg: 0\n"
+        );
+
+        let long = "あ".repeat(DEBUG_SNIPPET_CHARS + 1);
+        let dump = debug_dump(&format!(r#"<div class="mondai">{}</div>"#, long));
+        assert!(dump.starts_with("div.kako: not found\n.mondai: 1\n"));
+        assert!(dump.contains(&format!("{:?}…", "あ".repeat(DEBUG_SNIPPET_CHARS))));
+    }
+
+    #[test]
+    fn test_extract_kakomon_table() {
+        let html_text = include_str!("../testdata/table.html");
+        let url = Url::parse("https://www.ap-siken.com/").expect("invalid url");
+        let kakomon = extract_kakomon(html_text, url, &ExtractOptions::default()).unwrap();
+
+        let left = kakomon.text();
+        let right = r#"https://www.ap-siken.com/kakomon/30_haru/q18.html
+表に示す処理時間のジョブを実行したときの平均ターンアラウンドタイムは何分か。
+
+| ジョブ | 時間(分) |  |
+| --- | --- | --- |
+| A | 到着 | 0 |
+|  | 処理 | 5 |
+| B | 到着\|処理 | 1\|3 |
+1. 4
+2. 5
+3. 6
+4. 7
+"#;
+        assert_eq!(left.as_str(), right);
+    }
+
+    #[test]
+    fn test_element_text_line_breaks() {
+        let fragment = Html::parse_fragment(
+            r#"<div class="mondai">一行目<br>二行目<br><br><br><br>三行目<p>段落1</p><p>段落2</p></div>"#,
+        );
+        let element = fragment
+            .select(&Selector::parse(r#"div"#).unwrap())
+            .next()
+            .unwrap();
+        assert_eq!(
+            element_text(element),
+            "一行目\n二行目\n\n三行目\n\n段落1\n\n段落2"
+        );
+    }
+
+    #[test]
+    fn test_element_markdown() {
+        let fragment = Html::parse_fragment(
+            r#"<div class="mondai">次の<b>誤っている</b>ものを<em> 一つ </em>選べ。<a href="/docs/a_b.html">資料 (1)</a>を参照。a*b<ul><li>一つ目</li><li><strong>二つ目</strong></li></ul><ol><li>手順1</li><li>手順2</li></ol>末尾<b> </b></div>"#,
+        );
+        let element = fragment.select(selector!(r#"div"#)).next().unwrap();
+        let url = Url::parse("https://www.ap-siken.com/kakomon/05_haru/q1.html").unwrap();
+        assert_eq!(
+            element_markdown(element, &url),
+            "次の**誤っている**ものを_一つ_選べ。[資料 (1)](https://www.ap-siken.com/docs/a_b.html)を参照。a\\*b\n\n- 一つ目\n- **二つ目**\n\n1. 手順1\n2. 手順2\n\n末尾"
+        );
+        // the plain text is not changed
+        assert_eq!(
+            element_text(element),
+            "次の誤っているものを 一つ 選べ。資料 (1)を参照。a*b一つ目二つ目手順1手順2末尾"
+        );
+
+        let html_text = r#"<html><body><div class="kako">
+            <div class="mondai"><b>正しい</b>ものはどれか。</div>
+            <div class="anslink"><a href="kakomon/05_haru/q1.html">令和5年春期 問1</a></div>
+        </div></body></html>"#;
+        let options = ExtractOptions {
+            text_mode: TextMode::Markdown,
+            ..ExtractOptions::default()
+        };
+        let url = Url::parse("https://www.ap-siken.com/").unwrap();
+        let kakomon = extract_kakomon(html_text, url, &options).unwrap();
+        assert_eq!(kakomon.mondai, "**正しい**ものはどれか。");
+        let mode: TextMode = serde_json::from_str(r#""markdown""#).unwrap();
+        assert_eq!(mode, TextMode::Markdown);
+    }
+
+    #[test]
+    fn test_exam_meta() {
+        let meta = |exam_year, exam_season, question_number| {
+            Some(ExamMeta {
+                exam_year,
+                exam_season,
+                question_number,
+            })
+        };
+        let kakomon = |title: &str, url: Option<&str>| Kakomon {
+            title: title.to_string(),
+            answer_url: url.map(|url| Url::parse(url).unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(
+            kakomon("平成21年春期　応用情報技術者 問31 [テクノロジ系]", None).exam_meta(),
+            meta(2009, ExamSeason::Spring, 31)
+        );
+        assert_eq!(
+            kakomon("令和元年秋期 問１２", None).exam_meta(),
+            meta(2019, ExamSeason::Autumn, 12)
+        );
+        assert_eq!(
+            kakomon("令和2年10月 問3", None).exam_meta(),
+            meta(2020, ExamSeason::Autumn, 3)
+        );
+        // from the url when the title does not have it
+        let url = "https://www.ap-siken.com/kakomon/05_haru/q7.html";
+        assert_eq!(
+            kakomon("問7", Some(url)).exam_meta(),
+            meta(2023, ExamSeason::Spring, 7)
+        );
+        let url = "https://www.ap-siken.com/kakomon/21_aki/q1.html";
+        assert_eq!(
+            kakomon("ストアドプロシージャ", Some(url)).exam_meta(),
+            meta(2009, ExamSeason::Autumn, 1)
+        );
+        assert_eq!(kakomon("今日の一問", None).exam_meta(), None);
+        let url = "https://www.ap-siken.com/";
+        assert_eq!(kakomon("令和5年 問1", Some(url)).exam_meta(), None);
+
+        let exam = |year, season, number| ExamMeta {
+            exam_year: year,
+            exam_season: season,
+            question_number: number,
+        };
+        assert!(exam(2019, ExamSeason::Spring, 80) < exam(2019, ExamSeason::Autumn, 1));
+        assert!(exam(2019, ExamSeason::Autumn, 80) < exam(2020, ExamSeason::Spring, 1));
+    }
+
+    #[test]
+    fn test_sanitize_text() {
+        let text = "\u{feff}問1\u{0}\tA\u{7}\r\nB\u{1b}[0m\u{85}\u{9f}\u{200b}。\n";
+        assert_eq!(sanitize_text(text), "問1\tA\nB[0m。\n");
+        // full-width spaces, emojis with the zero width joiners and the other characters stay
+        let text = "　ア\u{3000}イ 👨\u{200d}💻 ①～";
+        assert_eq!(sanitize_text(text), text);
+
+        let html = "<html><body><h3 class=\"qno\">\u{feff}問1\u{8}</h3><div>本文\u{0}です</div>\
+            <div id=\"ans\"><ul class=\"selectList\"><li><span>ア\u{200b}</span></li><li><span>イ</span></li></ul></div></body></html>";
+        let url = Url::parse("https://www.ap-siken.com/kakomon/05_aki/q1.html").unwrap();
+        let kakomon = extract_kakomon(html, url, &ExtractOptions::default()).unwrap();
+        assert_eq!(kakomon.title, "問1");
+        assert_eq!(kakomon.mondai, "本文です");
+        assert_eq!(kakomon.choices, ["ア", "イ"]);
+    }
+
+    #[test]
+    fn test_collapse_blank_lines() {
+        let text = "\n  一行目  \n\n\n\n　二行目\n \n\t\n三行目\n\n";
+        assert_eq!(collapse_blank_lines(text), "一行目\n\n二行目\n\n三行目");
+        assert_eq!(collapse_blank_lines("一行目\n二行目"), "一行目\n二行目");
+        assert_eq!(collapse_blank_lines(" \n\n "), "");
+    }
+
+    #[test]
+    fn test_extract_kakomon_from_question_page() {
+        let html_text = include_str!("../testdata/q31.html");
+        let url =
+            Url::parse("https://www.ap-siken.com/kakomon/21_haru/q31.html").expect("invalid url");
+        let kakomon = extract_kakomon(html_text, url, &ExtractOptions::default()).unwrap();
+
+        assert_eq!(kakomon.title, "平成21年春期　応用情報技術者 問31");
+        let right = r#"https://www.ap-siken.com/kakomon/21_haru/q31.html
+クライアントサーバシステムにおけるストアドプロシージャに関する記述のうち，誤っているものはどれか。
+1. 機密性の高いデータに対する処理を特定のプロシージャ呼出しに限定することによって，セキュリティを向上させることができる。
+2. システム全体に共通な処理をプロシージャとして格納することによって，処理の標準化を行うことができる。
+3. データベースへのアクセスを細かい単位でプロシージャ化することによって，処理性能(スループット)を向上させることができる。
+4. 複数のSQL文から成る手続を1回の呼出しで実行できるので，クライアントとサーバ間の通信回数を減らすことができる。
+"#;
+        assert_eq!(kakomon.text(), right);
+        assert_eq!(kakomon.answer.as_deref(), Some("ウ"));
+        assert_eq!(kakomon.answer_index(), Some(2));
+        assert_eq!(kakomon.explanation, None);
+        assert_eq!(kakomon.category.as_deref(), Some("テクノロジ系"));
+
+        let options = ExtractOptions {
+            explanation: true,
+            ..Default::default()
+        };
+        let kakomon = extract_kakomon(html_text, kakomon.answer_url.unwrap(), &options).unwrap();
+        let explanation = "ストアドプロシージャは、データベースに対する一連の処理をまとめてデータベース管理システムに格納したものです。
+アクセスを細かい単位でプロシージャ化すると呼出しの回数が増えるため、処理性能は低下します。";
+        assert_eq!(kakomon.explanation.as_deref(), Some(explanation));
+        assert_eq!(
+            extract_explanation(html_text, &options).as_deref(),
+            Some(explanation)
+        );
+    }
+
+    #[test]
+    fn test_detect_page_kind() {
+        let detect = |html_text: &str, url: &str| {
+            detect_page_kind(&Html::parse_document(html_text), &Url::parse(url).unwrap())
+        };
+        let home = include_str!("../testdata/home.html");
+        let question = include_str!("../testdata/q31.html");
+        let list = r#"<html><body><table>
+            <tr><td><a href="q1.html">問1</a></td></tr>
+            <tr><td><a href="q2.html">問2</a></td></tr>
+        </table></body></html>"#;
+
+        assert_eq!(
+            detect(home, "https://www.ap-siken.com/"),
+            Some(PageKind::Top)
+        );
+        assert_eq!(
+            detect(
+                question,
+                "https://www.ap-siken.com/kakomon/21_haru/q31.html"
+            ),
+            Some(PageKind::Question)
+        );
+        assert_eq!(
+            detect(list, "https://www.ap-siken.com/kakomon/21_haru/"),
+            Some(PageKind::List)
+        );
+        // the html structure wins when the url is misleading
+        assert_eq!(
+            detect(question, "https://mirror.example.com/ap/21_haru_31"),
+            Some(PageKind::Question)
+        );
+        assert_eq!(
+            detect(home, "https://www.ap-siken.com/kakomon/21_haru/q31.html"),
+            Some(PageKind::Top)
+        );
+        assert_eq!(
+            detect(
+                "<html><body>Not Found</body></html>",
+                "https://www.ap-siken.com/"
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_extract_kakomon_skips_non_question_pages() {
+        let url = Url::parse("https://www.ap-siken.com/").expect("invalid url");
+        let options = ExtractOptions::default();
+
+        // a "not found" page served instead of the top page
+        let not_found = r#"<html><head><title>404 Not Found</title></head><body><h1>Not Found</h1></body></html>"#;
+        assert!(extract_kakomon(not_found, url.clone(), &options).is_none());
+
+        // a kako block without the problem statement
+        let no_mondai = r#"<html><body><div class="kako">
+            <div class="anslink">平成21年春期　応用情報技術者 問31</div>
+            <div class="mondai_dummy"></div>
+        </div></body></html>"#;
+        assert!(extract_kakomon(no_mondai, url.clone(), &options).is_none());
+
+        // everything is empty
+        let empty = r#"<html><body><div class="kako"><div class="mondai"> </div><div class="anslink"></div></div></body></html>"#;
+        assert!(extract_kakomon(empty, url, &options).is_none());
+    }
+
+    #[test]
+    fn test_extract_title() {
+        let fragment = Html::parse_fragment(
+            r#"<div class="kako"><h4>見出し</h4><span class="qtitle"> </span><b class="qtitle">問31</b><div class="anslink">平成21年春期</div></div>"#,
+        );
+        let element = fragment.root_element();
+
+        let defaults = ExtractOptions::default().title_selectors;
+        assert_eq!(extract_title(element, &defaults), "平成21年春期");
+        // empty matches are skipped
+        let selectors = parse_selectors(&[".qtitle", "div.anslink"]).unwrap();
+        assert_eq!(extract_title(element, &selectors), "問31");
+        // falls back to the headings
+        let selectors = parse_selectors(&[".missing"]).unwrap();
+        assert_eq!(extract_title(element, &selectors), "見出し");
+        assert!(parse_selectors(&["div["]).is_err());
+    }
+
+    #[test]
+    fn test_extract_kakomon_title_fallback() {
+        let url = Url::parse("https://www.ap-siken.com/").expect("invalid url");
+        let no_title = |head: &str| {
+            format!(
+                r#"<html><head>{}</head><body><div class="kako"><div class="mondai">問題文</div></div></body></html>"#,
+                head
+            )
+        };
+        let html_text = no_title(
+            "<title>平成21年春期問31 ストアドプロシージャ｜応用情報技術者試験.com</title>",
+        );
+
+        let mut options = ExtractOptions::default();
+        let kakomon = extract_kakomon(&html_text, url.clone(), &options).unwrap();
+        assert_eq!(
+            kakomon.title,
+            "平成21年春期問31 ストアドプロシージャ｜応用情報技術者試験.com"
+        );
+
+        options.title_trim_site_name = true;
+        let kakomon = extract_kakomon(&html_text, url.clone(), &options).unwrap();
+        assert_eq!(kakomon.title, "平成21年春期問31 ストアドプロシージャ");
+        let kakomon = extract_kakomon(
+            &no_title("<title>問31 | AP過去問</title>"),
+            url.clone(),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(kakomon.title, "問31");
+
+        let kakomon = extract_kakomon(&no_title(""), url, &options).unwrap();
+        assert_eq!(kakomon.title, UNTITLED);
+    }
+
+    #[test]
+    fn test_extract_kakomon_exclude_selectors() {
+        let html_text = r#"<html><body><div class="kako">
+            <div class="mondai">問題文<div class="ad">広告<div>広告の中身</div></div><nav>メニュー</nav>です。</div>
+            <div class="anslink">タイトル</div>
+            <div class="ansbg"><ul><li>選択肢<span id="sponsor">PR</span></li></ul></div>
+        </div></body></html>"#;
+        let url = Url::parse("https://www.ap-siken.com/").expect("invalid url");
+
+        let kakomon = extract_kakomon(html_text, url.clone(), &ExtractOptions::default()).unwrap();
+        assert_eq!(kakomon.text(), "問題文です。\n1. 選択肢PR\n");
+
+        let options = ExtractOptions {
+            exclude_selectors: parse_exclude_selectors(&["sponsor", "div.ad"]).unwrap(),
+            ..Default::default()
+        };
+        let kakomon = extract_kakomon(html_text, url, &options).unwrap();
+        assert_eq!(kakomon.text(), "問題文メニューです。\n1. 選択肢\n");
+    }
+
+    #[test]
+    fn test_extract_kakomon_normalize_text() {
+        let html_text = r#"<html><body><div class="kako">
+            <div class="anslink">問１</div>
+            <div class="mondai">ＳＱＬ文を１回実行する。</div>
+            <div class="ansbg"><ul><li>ｱｲｳ</li><li>（ａ）</li></ul></div>
+        </div></body></html>"#;
+        let url = Url::parse("https://www.ap-siken.com/").unwrap();
+
+        let mut options = ExtractOptions::default();
+        let kakomon = extract_kakomon(html_text, url.clone(), &options).unwrap();
+        assert_eq!(kakomon.mondai, "ＳＱＬ文を１回実行する。");
+
+        options.normalize_text = true;
+        let kakomon = extract_kakomon(html_text, url, &options).unwrap();
+        assert_eq!(kakomon.title, "問1");
+        assert_eq!(kakomon.mondai, "SQL文を1回実行する。");
+        assert_eq!(kakomon.choices, vec!["アイウ", "(a)"]);
+    }
+
+    #[test]
+    fn test_extract_failure_reason() {
+        let url = Url::parse("https://www.ap-siken.com/").unwrap();
+        let options = ExtractOptions::default();
+        let reason = |html: &str, url: &Url| extract_failure_reason(html, url, &options);
+        assert_eq!(
+            reason("<html><body><p>Not Found</p></body></html>", &url),
+            "no div.kako nor question page"
+        );
+        assert_eq!(
+            reason(
+                r#"<html><body><div class="kako"><div class="ansbg"></div></div></body></html>"#,
+                &url
+            ),
+            "div.kako without div.mondai"
+        );
+        let question = Url::parse("https://www.ap-siken.com/kakomon/05_haru/q1.html").unwrap();
+        assert_eq!(
+            reason(
+                r#"<html><body><h3 class="qno">問1</h3><ul class="selectList"></ul></body></html>"#,
+                &question
+            ),
+            "no problem statement after h3.qno"
+        );
+    }
+
+    #[test]
+    fn test_extract_kakomon_links() {
+        let html_text = r#"<html><body><div class="kako">
+            <div class="mondai">出典は<a href="/docs/source.html">情報処理 推進機構</a>、<a href="https://www.ipa.go.jp/"></a>を参照。</div>
+            <div class="anslink"><a href="kakomon/05_haru/q1.html">令和5年春期 問1</a></div>
+        </div></body></html>"#;
+        let url = Url::parse("https://www.ap-siken.com/").unwrap();
+        let kakomon = extract_kakomon(html_text, url, &ExtractOptions::default()).unwrap();
+        // the anchor without a text is left as its url
+        assert_eq!(
+            kakomon.mondai,
+            "出典は情報処理 推進機構、https://www.ipa.go.jp/を参照。"
+        );
+        assert_eq!(
+            kakomon.links,
+            [Link {
+                url: Url::parse("https://www.ap-siken.com/docs/source.html").unwrap(),
+                text: "情報処理 推進機構".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_extract_kako_siblings() {
+        let html_text = r#"<html><body><div class="kako">
+            <div class="mondai">図の説明として適切なものはどれか。</div>
+            <div class="anslink"><a href="kakomon/05_haru/q1.html">令和5年春期 問1</a></div>
+        </div>
+        <div class="zu"><img src="/img/q1.png">図1 ネットワーク構成</div>
+        <p class="note">注記 <a href="/docs/note.html">補足</a></p>
+        <h3>問2</h3>
+        <p class="note">次の問題の注記</p>
+        </body></html>"#;
+        let url = Url::parse("https://www.ap-siken.com/").unwrap();
+        // not taken by default
+        let kakomon = extract_kakomon(html_text, url.clone(), &ExtractOptions::default()).unwrap();
+        assert_eq!(kakomon.mondai, "図の説明として適切なものはどれか。");
+        assert!(kakomon.images.is_empty());
+
+        let options = ExtractOptions {
+            kako_sibling_selectors: parse_selectors(&["div.zu", "p.note"]).unwrap(),
+            ..ExtractOptions::default()
+        };
+        let kakomon = extract_kakomon(html_text, url, &options).unwrap();
+        assert_eq!(
+            kakomon.mondai,
+            "図の説明として適切なものはどれか。\n図1 ネットワーク構成\n注記 補足"
+        );
+        assert_eq!(
+            kakomon.images,
+            [Url::parse("https://www.ap-siken.com/img/q1.png").unwrap()]
+        );
+        assert_eq!(kakomon.links.len(), 1);
+    }
+
+    #[test]
+    fn test_detect_language() {
+        let url = Url::parse("https://www.ap-siken.com/kakomon/05_haru/q1.html").unwrap();
+        let page = |mondai: &str| {
+            format!(
+                r#"<html><body><h3 class="qno">問1</h3><div>{}</div>
+                <div id="ans"><ul class="selectList"><li>ア</li></ul></div></body></html>"#,
+                mondai
+            )
+        };
+        let language = |mondai: &str| {
+            extract_kakomon(&page(mondai), url.clone(), &ExtractOptions::default())
+                .unwrap()
+                .language
+        };
+        assert_eq!(
+            language("Which of the following is the most appropriate description of a stack?")
+                .as_deref(),
+            Some("eng")
+        );
+        assert_eq!(
+            language("スタックの説明として、適切なものはどれか。").as_deref(),
+            Some("jpn")
+        );
+        assert_eq!(language("問題文"), None);
+    }
+
+    #[test]
+    fn test_extract_difficulty() {
+        let html_text = r#"<html><body><div class="kako">
+            <div class="mondai">問題文</div>
+            <span class="difficulty"> ★★★ </span>
+        </div></body></html>"#;
+        let url = Url::parse("https://www.ap-siken.com/").unwrap();
+        let kakomon = extract_kakomon(html_text, url.clone(), &ExtractOptions::default()).unwrap();
+        assert_eq!(kakomon.difficulty.as_deref(), Some("★★★"));
+
+        let html_text = r#"<html><body><main><h2>問1</h2><h3 class="qno">問1</h3><div>問題文</div>
+            <div id="ans"><ul class="selectList"><li>ア</li></ul></div>
+            <p class="rate">正答率
+              45%</p></main></body></html>"#;
+        let url = Url::parse("https://www.ap-siken.com/kakomon/05_haru/q1.html").unwrap();
+        let kakomon = extract_kakomon(html_text, url.clone(), &ExtractOptions::default()).unwrap();
+        assert_eq!(kakomon.difficulty, None);
+        let options = ExtractOptions {
+            difficulty_selectors: parse_selectors(&[".missing", "p.rate"]).unwrap(),
+            ..Default::default()
+        };
+        let kakomon = extract_kakomon(html_text, url, &options).unwrap();
+        assert_eq!(kakomon.difficulty.as_deref(), Some("正答率 45%"));
+    }
+
+    #[test]
+    fn test_kakomon_display() {
+        let html_text = include_str!("../testdata/q31.html");
+        let url = Url::parse("https://www.ap-siken.com/kakomon/21_haru/q31.html").unwrap();
+        let kakomon = extract_kakomon(html_text, url, &ExtractOptions::default()).unwrap();
+        assert_eq!(
+            kakomon.to_string(),
+            "平成21年春期　応用情報技術者 問31\n  \
+             クライアントサーバシステムにおけるストアドプロシージャに関する記述のうち，誤って…\n  \
+             https://www.ap-siken.com/kakomon/21_haru/q31.html"
+        );
+
+        let kakomon = Kakomon {
+            title: "問1".to_string(),
+            mondai: "短い\n問題文".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(kakomon.to_string(), "問1\n  短い 問題文");
+    }
+
+    #[test]
+    fn test_kakomon_serde_round_trip() {
+        let html_text = include_str!("../testdata/q31.html");
+        let url = Url::parse("https://www.ap-siken.com/kakomon/21_haru/q31.html").unwrap();
+        let options = ExtractOptions {
+            explanation: true,
+            ..Default::default()
+        };
+        let mut kakomon = extract_kakomon(html_text, url, &options).unwrap();
+        kakomon
+            .images
+            .push(Url::parse("https://www.ap-siken.com/kakomon/21_haru/img/31.png").unwrap());
+
+        let json = serde_json::to_value(&kakomon).unwrap();
+        assert_eq!(
+            json["answer_url"],
+            "https://www.ap-siken.com/kakomon/21_haru/q31.html"
+        );
+        assert_eq!(
+            json["images"][0],
+            "https://www.ap-siken.com/kakomon/21_haru/img/31.png"
+        );
+        let restored: Kakomon = serde_json::from_value(json).unwrap();
+        assert_eq!(restored, kakomon);
+        assert_eq!(restored.text(), kakomon.text());
+
+        let restored: Kakomon = serde_json::from_str(r#"{"title": "問1"}"#).unwrap();
+        assert_eq!(restored.answer_url, None);
+        assert!(restored.choices.is_empty());
+        assert!(
+            serde_json::from_str::<Kakomon>(r#"{"title": "問1", "answer_url": "not a url"}"#)
+                .is_err()
+        );
+    }
+
+    // the inputs found by `fuzz/fuzz_targets/extract_kakomon.rs`
+    #[test]
+    fn test_extract_kakomon_broken_html() {
+        let url = Url::parse("https://www.ap-siken.com/").unwrap();
+        let html = r#"<div class="kako"><a>no href</a><a href="http://[::1">broken</a>
+            <div class="mondai">問題文</div><img><img src="http://[::1"></div>"#;
+        let kakomon = extract_kakomon(html, url.clone(), &ExtractOptions::default()).unwrap();
+        assert_eq!(kakomon.answer_url, None);
+        assert!(kakomon.images.is_empty());
+        assert_eq!(kakomon.mondai, "問題文");
+
+        for html in [
+            "",
+            "<",
+            "<table><tr><td rowspan=99999999999>",
+            "\u{0}<div class=kako>",
+        ] {
+            extract_kakomon(html, url.clone(), &ExtractOptions::default());
+        }
+
+        // the spans are clamped as browsers do
+        for span in ["4000000000", "99999999999999999999999"] {
+            let html = format!(
+                r#"<div class="kako"><div class="mondai"><table><tr><td colspan={0} rowspan={0}>a</td></tr><tr><td>b</td></tr></table></div></div>"#,
+                span
+            );
+            let kakomon = extract_kakomon(&html, url.clone(), &ExtractOptions::default()).unwrap();
+            // "b" comes after the columns spanned by "a" on the second row
+            let lines = kakomon.mondai.lines().collect::<Vec<_>>();
+            assert_eq!(lines.len(), 3);
+            assert_eq!(lines[2].matches('|').count(), MAX_COLSPAN + 2);
+            assert!(lines[2].ends_with("| b |"));
+        }
+    }
+}
@@ -0,0 +1,670 @@
+use crate::encoding::decode_body;
+use crate::http::{build_http_client, HostLimits, HttpOptions};
+use crate::login::{load_cookies, login, save_cookies, LoginConfig};
+use crate::retry::{is_retryable_request, with_retry, RetryPolicy};
+use futures_util::StreamExt;
+use log::{debug, info, warn};
+use rand::rngs::StdRng;
+use rand::{RngExt, SeedableRng};
+use reqwest::cookie::Jar;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use url::{Origin, Url};
+
+// Gets the html of the pages, which is replaced by `MockFetcher` in the tests.
+pub trait Fetcher {
+    async fn fetch(&self, url: &Url) -> Result<String, Box<dyn Error>>;
+}
+
+// The same page written differently, ignoring a trailing slash, the order of the
+// query and the fragment. The scheme and the host are already lowercased by `Url`.
+pub fn normalize_url(url: &Url) -> String {
+    let mut normalized = url.clone();
+    normalized.set_fragment(None);
+    let mut query = url.query_pairs().into_owned().collect::<Vec<_>>();
+    query.sort();
+    if query.is_empty() {
+        normalized.set_query(None);
+    } else {
+        normalized.query_pairs_mut().clear().extend_pairs(query);
+    }
+    let path = normalized.path().trim_end_matches('/').to_string();
+    normalized.set_path(&path);
+    normalized.to_string()
+}
+
+// The credentials of a site protected by basic authentication.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BasicAuth {
+    pub username: String,
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+// not to print the password in the logs
+impl fmt::Debug for BasicAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BasicAuth")
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "***"))
+            .finish()
+    }
+}
+
+// The http headers of `headers` in CONFIG, checked before fetching anything.
+// The values are not printed in the logs as they may be tokens.
+pub fn parse_headers(headers: &BTreeMap<String, String>) -> Result<HeaderMap, Box<dyn Error>> {
+    let mut map = HeaderMap::new();
+    for (name, value) in headers.iter() {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|e| format!("invalid header name {:?}: {}", name, e))?;
+        let mut value = HeaderValue::from_str(value)
+            .map_err(|e| format!("invalid value of the header `{}`: {}", name, e))?;
+        value.set_sensitive(true);
+        map.insert(name, value);
+    }
+    Ok(map)
+}
+
+// The cookies sent to the fetch urls, e.g. of a login session, as `name=value`.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Cookies(Vec<String>);
+
+impl Cookies {
+    // from `cookies` (a list of `name=value`) and `cookie_header` (`name=value; name=value`)
+    pub fn parse(cookies: &[String], cookie_header: Option<&str>) -> Self {
+        let header = cookie_header.unwrap_or_default().split(';');
+        Cookies(
+            cookies
+                .iter()
+                .map(String::as_str)
+                .chain(header)
+                .map(str::trim)
+                .filter(|cookie| cookie.contains('='))
+                .map(String::from)
+                .collect(),
+        )
+    }
+}
+
+// only the names not to print the values in the logs
+impl fmt::Debug for Cookies {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.0.iter().filter_map(|cookie| cookie.split('=').next()))
+            .finish()
+    }
+}
+
+// The wait before every request, `delay ± rand(0..=jitter)` not to look like a bot.
+#[derive(Debug)]
+pub struct FetchDelay {
+    delay: Duration,
+    jitter: Duration,
+    rng: Mutex<StdRng>,
+}
+
+impl FetchDelay {
+    // the jitter is reproducible with the `seed`
+    pub fn new(delay: Duration, jitter: Duration, seed: Option<u64>) -> Self {
+        FetchDelay {
+            delay,
+            jitter,
+            rng: Mutex::new(match seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => rand::make_rng(),
+            }),
+        }
+    }
+
+    // each call draws its own jitter
+    pub fn next(&self) -> Duration {
+        if self.jitter.is_zero() {
+            return self.delay;
+        }
+        let jitter = self.jitter.as_millis() as i64;
+        let offset = self.rng.lock().unwrap().random_range(-jitter..=jitter);
+        let millis = (self.delay.as_millis() as i64 + offset).max(0);
+        Duration::from_millis(millis as u64)
+    }
+}
+
+impl Default for FetchDelay {
+    fn default() -> Self {
+        FetchDelay::new(Duration::ZERO, Duration::ZERO, None)
+    }
+}
+
+// a response shorter than this is taken for an error page unless `min_response_bytes` is configured
+pub const DEFAULT_MIN_RESPONSE_BYTES: usize = 100;
+
+// the reading of a response is given up after this many bytes unless `max_response_bytes` is configured
+pub const DEFAULT_MAX_RESPONSE_BYTES: usize = 10 * 1024 * 1024;
+
+// What a fetched page needs to be processed, not to take an error page or an empty response for a question.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseCheck {
+    pub min_bytes: usize,
+    // a page without `<body>` is skipped
+    pub require_body: bool,
+    // not to read all of a huge response into memory, see `read_body`. None for no limit.
+    pub max_bytes: Option<usize>,
+}
+
+impl Default for ResponseCheck {
+    fn default() -> Self {
+        ResponseCheck {
+            min_bytes: DEFAULT_MIN_RESPONSE_BYTES,
+            require_body: true,
+            max_bytes: Some(DEFAULT_MAX_RESPONSE_BYTES),
+        }
+    }
+}
+
+// A response given up as it is longer than `max_response_bytes`, which is skipped rather than failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseTooLarge {
+    pub max_bytes: usize,
+    // the `Content-Length`, or the bytes read until it was given up
+    pub bytes: u64,
+}
+
+impl fmt::Display for ResponseTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the response is more than {} bytes (got {})",
+            self.max_bytes, self.bytes
+        )
+    }
+}
+
+impl Error for ResponseTooLarge {}
+
+// Read the body in chunks, giving it up once it is longer than `max_bytes`.
+// A `Content-Length` longer than that is given up without reading anything.
+async fn read_body(
+    res: reqwest::Response,
+    max_bytes: Option<usize>,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let max_bytes = match max_bytes {
+        Some(max_bytes) => max_bytes,
+        None => return Ok(res.bytes().await?.to_vec()),
+    };
+    if let Some(bytes) = res.content_length().filter(|&len| len > max_bytes as u64) {
+        return Err(ResponseTooLarge { max_bytes, bytes }.into());
+    }
+    let mut body = Vec::new();
+    let mut stream = res.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        body.extend_from_slice(&chunk?);
+        if body.len() > max_bytes {
+            return Err(ResponseTooLarge {
+                max_bytes,
+                bytes: body.len() as u64,
+            }
+            .into());
+        }
+    }
+    Ok(body)
+}
+
+// the reason to skip the page, if it is not worth extracting
+pub fn validate_response(text: &str, check: &ResponseCheck) -> Result<(), String> {
+    if text.trim().is_empty() {
+        return Err("the response is empty".to_string());
+    }
+    if text.len() < check.min_bytes {
+        return Err(format!(
+            "the response is {} bytes, shorter than {}",
+            text.len(),
+            check.min_bytes
+        ));
+    }
+    if check.require_body && !text.to_ascii_lowercase().contains("<body") {
+        return Err("the response has no <body>".to_string());
+    }
+    Ok(())
+}
+
+// How the pages are requested.
+#[derive(Debug, Default)]
+pub struct FetchOptions {
+    // the fetch url to the credentials
+    pub basic_auth: Vec<(Url, BasicAuth)>,
+    pub cookies: Cookies,
+    // sent with every request
+    pub headers: HeaderMap,
+    // the fetch url to its headers, which override the ones above
+    pub url_headers: Vec<(Url, HeaderMap)>,
+    pub login: Option<LoginConfig>,
+    pub delay: FetchDelay,
+    pub http: HttpOptions,
+    pub retry: RetryPolicy,
+    // the fetch url to its `retry`, used for the same origin as well
+    pub url_retries: Vec<(Url, RetryPolicy)>,
+    pub check: ResponseCheck,
+}
+
+#[derive(Debug)]
+pub struct ReqwestFetcher {
+    client: reqwest::Client,
+    jar: Arc<Jar>,
+    // sent to the pages of the same origin, e.g. the answer pages linked from a fetch url
+    basic_auth: Vec<(Origin, BasicAuth)>,
+    // the headers of the fetch urls merged into the common ones, sent to the same origin as well
+    headers: HeaderMap,
+    url_headers: Vec<(Origin, HeaderMap)>,
+    retry: RetryPolicy,
+    url_retries: Vec<(Origin, RetryPolicy)>,
+    login: Option<LoginConfig>,
+    // the urls whose cookies are saved in the cookie file
+    cookie_urls: Vec<Url>,
+    host_limits: HostLimits,
+    max_response_bytes: Option<usize>,
+}
+
+impl ReqwestFetcher {
+    // the cookies are sent to the hosts of the fetch urls, and kept on redirects
+    pub fn new(options: &FetchOptions, fetch_urls: &[Url]) -> Result<Self, Box<dyn Error>> {
+        let jar = Arc::new(Jar::default());
+        for url in fetch_urls.iter() {
+            for cookie in options.cookies.0.iter() {
+                jar.add_cookie_str(&format!("{}; Path=/", cookie), url);
+            }
+        }
+        let mut cookie_urls = fetch_urls.to_vec();
+        if let Some(login) = &options.login {
+            if let Some(path) = &login.cookie_file {
+                load_cookies(&jar, path)?;
+            }
+            cookie_urls.push(Url::parse(&login.url)?);
+        }
+
+        Ok(ReqwestFetcher {
+            client: build_http_client(&options.http, Some(jar.clone()))?,
+            jar,
+            basic_auth: options
+                .basic_auth
+                .iter()
+                .map(|(url, auth)| (url.origin(), auth.clone()))
+                .collect(),
+            headers: options.headers.clone(),
+            url_headers: options
+                .url_headers
+                .iter()
+                .map(|(url, headers)| {
+                    let mut merged = options.headers.clone();
+                    merged.extend(headers.clone());
+                    (url.origin(), merged)
+                })
+                .collect(),
+            retry: options.retry,
+            url_retries: options
+                .url_retries
+                .iter()
+                .map(|(url, retry)| (url.origin(), *retry))
+                .collect(),
+            login: options.login.clone(),
+            cookie_urls,
+            host_limits: HostLimits::new(options.http.max_connections_per_host),
+            max_response_bytes: options.check.max_bytes,
+        })
+    }
+
+    // the errors of the servers are errors to be retried
+    async fn get_with_retry(&self, url: &Url) -> Result<reqwest::Response, reqwest::Error> {
+        let origin = url.origin();
+        let retry = self
+            .url_retries
+            .iter()
+            .find(|(o, _)| *o == origin)
+            .map_or(&self.retry, |(_, retry)| retry);
+        let (res, retried) = with_retry(retry, is_retryable_request, || async {
+            let res = self.get(url).await?;
+            let status = res.status();
+            if status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+                return res.error_for_status();
+            }
+            Ok(res)
+        })
+        .await;
+        if retried > 0 {
+            debug!("{} was retried {} times", url, retried);
+        }
+        res
+    }
+
+    async fn get(&self, url: &Url) -> Result<reqwest::Response, reqwest::Error> {
+        let origin = url.origin();
+        let headers = self
+            .url_headers
+            .iter()
+            .find(|(o, _)| *o == origin)
+            .map_or(&self.headers, |(_, headers)| headers);
+        let mut request = self.client.get(url.to_string()).headers(headers.clone());
+        if let Some((_, auth)) = self.basic_auth.iter().find(|(o, _)| *o == origin) {
+            request = request.basic_auth(&auth.username, auth.password.as_ref());
+        }
+        request.send().await
+    }
+
+    // log in again and keep the new session cookies
+    async fn relogin(&self, login_config: &LoginConfig) -> Result<(), Box<dyn Error>> {
+        info!("logging in to {}", login_config.url);
+        login(&self.client, login_config).await?;
+        if let Some(path) = &login_config.cookie_file {
+            if let Err(e) = save_cookies(&self.jar, &self.cookie_urls, path) {
+                warn!("failed to save the cookies to {}: {}", path.display(), e);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Fetcher for ReqwestFetcher {
+    async fn fetch(&self, url: &Url) -> Result<String, Box<dyn Error>> {
+        // kept until the body is read, the retries included
+        let _permit = self.host_limits.acquire(url).await;
+        let mut res = self.get_with_retry(url).await?;
+        if res.status() == reqwest::StatusCode::UNAUTHORIZED {
+            if let Some(login_config) = &self.login {
+                // the session has expired or there has been none yet
+                self.relogin(login_config).await?;
+                res = self.get_with_retry(url).await?;
+            }
+        }
+        if res.status() == reqwest::StatusCode::UNAUTHORIZED {
+            let origin = url.origin();
+            return Err(match self.basic_auth.iter().find(|(o, _)| *o == origin) {
+                Some((_, auth)) => format!("authentication failed as {}", auth.username),
+                None => "authentication required, set `username` and `password`".to_string(),
+            }
+            .into());
+        }
+        // decoded by itself, as the charset of `Content-Type` can be wrong
+        let res = res.error_for_status()?;
+        let headers = res.headers().clone();
+        let bytes = read_body(res, self.max_response_bytes).await?;
+        Ok(decode_body(&bytes, &headers))
+    }
+}
+
+// Reads the html of the urls from the local files given with `--html-file`, and fetches the
+// others such as the explanations with `inner`.
+pub struct FileFetcher<'a, F> {
+    files: HashMap<Url, PathBuf>,
+    inner: &'a F,
+}
+
+impl<'a, F> FileFetcher<'a, F> {
+    pub fn new(files: impl IntoIterator<Item = (Url, PathBuf)>, inner: &'a F) -> Self {
+        FileFetcher {
+            files: files.into_iter().collect(),
+            inner,
+        }
+    }
+}
+
+impl<F: Fetcher> Fetcher for FileFetcher<'_, F> {
+    async fn fetch(&self, url: &Url) -> Result<String, Box<dyn Error>> {
+        match self.files.get(url) {
+            Some(path) => std::fs::read(path)
+                .map(|bytes| decode_body(&bytes, &HeaderMap::new()))
+                .map_err(|e| format!("failed to read {}: {}", path.display(), e).into()),
+            None => self.inner.fetch(url).await,
+        }
+    }
+}
+
+// Returns the fixed html of the urls without the network.
+// only the tests of the runs use it, which post to slack
+#[cfg(all(test, feature = "slack"))]
+#[derive(Debug, Default)]
+pub struct MockFetcher {
+    pages: std::collections::HashMap<Url, String>,
+}
+
+#[cfg(all(test, feature = "slack"))]
+impl MockFetcher {
+    pub fn with_page(mut self, url: &str, html: &str) -> Self {
+        self.pages
+            .insert(Url::parse(url).unwrap(), html.to_string());
+        self
+    }
+}
+
+#[cfg(all(test, feature = "slack"))]
+impl Fetcher for MockFetcher {
+    async fn fetch(&self, url: &Url) -> Result<String, Box<dyn Error>> {
+        self.pages
+            .get(url)
+            .cloned()
+            .ok_or_else(|| format!("{} is not mocked", url).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_basic_auth_debug() {
+        let auth = BasicAuth {
+            username: "user".to_string(),
+            password: Some("secret".to_string()),
+        };
+        let debug = format!("{:?}", auth);
+        assert!(debug.contains("user"));
+        assert!(!debug.contains("secret"));
+    }
+
+    // a server answering the head of each request as the body
+    async fn echo_server() -> Url {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0; 4096];
+                let len = stream.read(&mut buf).await.unwrap();
+                let head = String::from_utf8_lossy(&buf[..len]).to_lowercase();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    head.len(),
+                    head
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        url
+    }
+
+    // a server answering the body, with `Content-Length` or until the connection is closed
+    async fn body_server(body: String, content_length: bool) -> Url {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let _ = stream.read(&mut vec![0; 4096]).await.unwrap();
+                let length = match content_length {
+                    true => format!("Content-Length: {}\r\n", body.len()),
+                    false => String::new(),
+                };
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\n{}Connection: close\r\n\r\n{}",
+                    length, body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_fetch_max_response_bytes() {
+        let body = format!("<html><body>{}</body></html>", "a".repeat(1000));
+        let with_length = body_server(body.clone(), true).await;
+        let without_length = body_server(body.clone(), false).await;
+        let fetcher = |max_bytes| {
+            let options = FetchOptions {
+                check: ResponseCheck {
+                    max_bytes,
+                    ..ResponseCheck::default()
+                },
+                ..Default::default()
+            };
+            ReqwestFetcher::new(&options, &[]).unwrap()
+        };
+
+        let small = fetcher(Some(500));
+        let e = small.fetch(&with_length).await.unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<ResponseTooLarge>(),
+            Some(&ResponseTooLarge {
+                max_bytes: 500,
+                bytes: body.len() as u64,
+            })
+        );
+        let e = small.fetch(&without_length).await.unwrap_err();
+        assert!(e.is::<ResponseTooLarge>());
+
+        let large = fetcher(Some(body.len()));
+        assert_eq!(large.fetch(&with_length).await.unwrap(), body);
+        assert_eq!(large.fetch(&without_length).await.unwrap(), body);
+        assert_eq!(fetcher(None).fetch(&without_length).await.unwrap(), body);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_headers() {
+        let headers = |pairs: &[(&str, &str)]| {
+            let map = pairs
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect::<BTreeMap<_, _>>();
+            parse_headers(&map)
+        };
+        let with_referer = echo_server().await;
+        let without = echo_server().await;
+        let options = FetchOptions {
+            headers: headers(&[("Referer", "https://example.com/"), ("X-Common", "1")]).unwrap(),
+            url_headers: vec![(
+                with_referer.clone(),
+                headers(&[("referer", "https://www.ap-siken.com/")]).unwrap(),
+            )],
+            ..Default::default()
+        };
+        let fetcher =
+            ReqwestFetcher::new(&options, &[with_referer.clone(), without.clone()]).unwrap();
+
+        let head = fetcher.fetch(&with_referer).await.unwrap();
+        assert!(head.contains("referer: https://www.ap-siken.com/\r\n"));
+        assert!(!head.contains("referer: https://example.com/"));
+        assert!(head.contains("x-common: 1\r\n"));
+        // the pages of the same origin get them as well
+        let head = fetcher
+            .fetch(&with_referer.join("q1.html").unwrap())
+            .await
+            .unwrap();
+        assert!(head.contains("referer: https://www.ap-siken.com/\r\n"));
+        let head = fetcher.fetch(&without).await.unwrap();
+        assert!(head.contains("referer: https://example.com/\r\n"));
+
+        assert!(headers(&[("bad name", "1")]).is_err());
+        assert!(headers(&[("X-Token", "a\nb")]).is_err());
+        // the values are not printed
+        let debug = format!("{:?}", headers(&[("X-Token", "secret")]).unwrap());
+        assert!(!debug.contains("secret"));
+    }
+
+    #[test]
+    fn test_fetch_delay() {
+        let delay = FetchDelay::new(Duration::from_millis(1000), Duration::ZERO, None);
+        assert_eq!(delay.next(), Duration::from_millis(1000));
+
+        let delays = |seed| {
+            let delay = FetchDelay::new(
+                Duration::from_millis(1000),
+                Duration::from_millis(200),
+                seed,
+            );
+            (0..10).map(|_| delay.next()).collect::<Vec<_>>()
+        };
+        let seeded = delays(Some(42));
+        assert_eq!(seeded, delays(Some(42)));
+        assert!(seeded.iter().all(|d| (800..=1200).contains(&d.as_millis())));
+        // not a fixed offset
+        assert!(seeded.iter().any(|d| *d != seeded[0]));
+
+        // never negative
+        let delay = FetchDelay::new(Duration::ZERO, Duration::from_millis(100), Some(1));
+        assert!((0..10).all(|_| delay.next() <= Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn test_cookies_parse() {
+        let cookies = Cookies::parse(&["session=abc".to_string()], Some("a=1; b=2; "));
+        assert_eq!(
+            cookies,
+            Cookies(vec!["session=abc".into(), "a=1".into(), "b=2".into()])
+        );
+        assert_eq!(format!("{:?}", cookies), r#"["session", "a", "b"]"#);
+        assert_eq!(Cookies::parse(&[], None), Cookies::default());
+    }
+
+    #[test]
+    fn test_validate_response() {
+        let check = ResponseCheck::default();
+        let page = format!("<html><BODY>{}</BODY></html>", "a".repeat(100));
+        assert_eq!(validate_response(&page, &check), Ok(()));
+        assert_eq!(
+            validate_response(" \n", &check),
+            Err("the response is empty".to_string())
+        );
+        assert_eq!(
+            validate_response("<html><body>503</body></html>", &check),
+            Err("the response is 29 bytes, shorter than 100".to_string())
+        );
+        let text = "a".repeat(100);
+        assert_eq!(
+            validate_response(&text, &check),
+            Err("the response has no <body>".to_string())
+        );
+        let check = ResponseCheck {
+            min_bytes: 0,
+            require_body: false,
+            max_bytes: None,
+        };
+        assert_eq!(validate_response("ok", &check), Ok(()));
+    }
+
+    #[test]
+    fn test_normalize_url() {
+        let normalize = |url| normalize_url(&Url::parse(url).unwrap());
+        assert_eq!(
+            normalize("https://WWW.ap-siken.com/kakomon/"),
+            normalize("https://www.ap-siken.com/kakomon")
+        );
+        assert_eq!(
+            normalize("https://www.ap-siken.com/s/?b=2&a=1"),
+            normalize("https://www.ap-siken.com/s?a=1&b=2#top")
+        );
+        assert_ne!(
+            normalize("https://www.ap-siken.com/s?a=1"),
+            normalize("https://www.ap-siken.com/s?a=2")
+        );
+        assert_ne!(
+            normalize("https://www.ap-siken.com/"),
+            normalize("https://www.nw-siken.com/")
+        );
+    }
+}
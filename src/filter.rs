@@ -0,0 +1,158 @@
+use crate::extract::{ExamMeta, ExamSeason};
+use serde::{Deserialize, Serialize};
+
+// the first year of 平成, before which there is no year of the exams in the western calendar
+const FIRST_EXAM_YEAR: u32 = 1989;
+
+// what is done with the questions whose exam is unknown while filtering
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterUnknown {
+    #[default]
+    Exclude,
+    Include,
+}
+
+// The exams of the questions to send, from `filter_years` and `filter_seasons`.
+// An empty one does not filter, and nothing is filtered if both are empty.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExamFilter {
+    // in the western calendar, e.g. 2023 of 令和5年
+    pub years: Vec<u32>,
+    pub seasons: Vec<ExamSeason>,
+    pub unknown: FilterUnknown,
+}
+
+impl ExamFilter {
+    pub fn new(
+        years: Vec<u32>,
+        seasons: Vec<ExamSeason>,
+        unknown: FilterUnknown,
+    ) -> Result<Self, String> {
+        if let Some(year) = years.iter().find(|&&year| year < FIRST_EXAM_YEAR) {
+            return Err(format!(
+                "`filter_years` are in the western calendar, e.g. 2023 of 令和5年, but got {}",
+                year
+            ));
+        }
+        Ok(ExamFilter {
+            years,
+            seasons,
+            unknown,
+        })
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.years.is_empty() || !self.seasons.is_empty()
+    }
+
+    pub fn matches(&self, meta: Option<&ExamMeta>) -> bool {
+        if !self.is_active() {
+            return true;
+        }
+        match meta {
+            Some(meta) => {
+                (self.years.is_empty() || self.years.contains(&meta.exam_year))
+                    && (self.seasons.is_empty() || self.seasons.contains(&meta.exam_season))
+            }
+            None => self.unknown == FilterUnknown::Include,
+        }
+    }
+}
+
+// The languages of the questions to send, from `filter_languages`, in the ISO 639-3 codes.
+// The questions too short to detect the language of are of an unknown language.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LanguageFilter {
+    pub languages: Vec<String>,
+    pub unknown: FilterUnknown,
+}
+
+impl LanguageFilter {
+    pub fn new(languages: Vec<String>, unknown: FilterUnknown) -> Result<Self, String> {
+        if let Some(language) = languages
+            .iter()
+            .find(|language| whatlang::Lang::from_code(language.as_str()).is_none())
+        {
+            return Err(format!(
+                "`filter_languages` are the ISO 639-3 codes, e.g. \"jpn\" or \"eng\", but got {:?}",
+                language
+            ));
+        }
+        Ok(LanguageFilter { languages, unknown })
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.languages.is_empty()
+    }
+
+    pub fn matches(&self, language: Option<&str>) -> bool {
+        if !self.is_active() {
+            return true;
+        }
+        match language {
+            Some(language) => self.languages.iter().any(|filter| filter == language),
+            None => self.unknown == FilterUnknown::Include,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exam_filter() {
+        let meta = |exam_year, exam_season| ExamMeta {
+            exam_year,
+            exam_season,
+            question_number: 1,
+        };
+        let spring_2023 = meta(2023, ExamSeason::Spring);
+        let autumn_2023 = meta(2023, ExamSeason::Autumn);
+        let spring_2022 = meta(2022, ExamSeason::Spring);
+
+        let none = ExamFilter::default();
+        assert!(!none.is_active());
+        assert!(none.matches(None));
+
+        let years = ExamFilter::new(vec![2023], vec![], FilterUnknown::Exclude).unwrap();
+        assert!(years.matches(Some(&spring_2023)));
+        assert!(years.matches(Some(&autumn_2023)));
+        assert!(!years.matches(Some(&spring_2022)));
+        assert!(!years.matches(None));
+
+        let both = ExamFilter::new(
+            vec![2022, 2023],
+            vec![ExamSeason::Spring],
+            FilterUnknown::Include,
+        )
+        .unwrap();
+        assert!(both.matches(Some(&spring_2022)));
+        assert!(!both.matches(Some(&autumn_2023)));
+        assert!(both.matches(None));
+
+        assert!(ExamFilter::new(vec![5], vec![], FilterUnknown::Exclude).is_err());
+        let seasons: Vec<ExamSeason> = serde_json::from_str(r#"["spring", "autumn"]"#).unwrap();
+        assert_eq!(seasons, [ExamSeason::Spring, ExamSeason::Autumn]);
+    }
+
+    #[test]
+    fn test_language_filter() {
+        let none = LanguageFilter::default();
+        assert!(!none.is_active());
+        assert!(none.matches(None));
+
+        let english = LanguageFilter::new(vec!["eng".to_string()], FilterUnknown::Exclude).unwrap();
+        assert!(english.matches(Some("eng")));
+        assert!(!english.matches(Some("jpn")));
+        assert!(!english.matches(None));
+        let english = LanguageFilter {
+            unknown: FilterUnknown::Include,
+            ..english
+        };
+        assert!(english.matches(None));
+
+        assert!(LanguageFilter::new(vec!["en".to_string()], FilterUnknown::Exclude).is_err());
+    }
+}
@@ -0,0 +1,640 @@
+use crate::extract::Kakomon;
+use serde_json::Value;
+use url::Url;
+
+// the statements longer than this are summarized with `summarize` unless `summary_max_chars` is configured
+pub const DEFAULT_SUMMARY_MAX_CHARS: usize = 200;
+// put in place of the rest of a summarized statement
+pub const SUMMARY_NOTE: &str = "…続きは解答ページで";
+
+// How the questions are rendered in the messages.
+#[derive(Debug, Clone)]
+pub struct FormatOptions {
+    // put the problem statement in a code block not to break code and commands in it
+    pub wrap_mondai_in_codeblock: bool,
+    // output the image urls. they are extracted anyway.
+    pub include_images: bool,
+    // leave "画像 N 枚あり" instead of the image urls left out
+    pub image_count_note: bool,
+    // output the correct choice. the answer url is output anyway to check it by yourself.
+    pub include_answer: bool,
+    // output the explanation. it is not even fetched unless this is set.
+    pub include_explanation: bool,
+    // cut the statements longer than this many characters, see `summarize`
+    pub summary_max_chars: Option<usize>,
+    // leave out the lines of the body after this many, see `limit_lines`
+    pub max_lines: Option<usize>,
+    // label the answer and the explanation in the detected language of the question, see `labels_of`
+    pub localize_labels: bool,
+    // the statement is in markdown with `TextMode::Markdown`, which slack needs in mrkdwn
+    #[cfg(feature = "slack")]
+    pub text_mode: crate::extract::TextMode,
+    // the host of the fetch url to the color of the slack attachment, e.g. `#36a64f`
+    #[cfg(feature = "slack")]
+    pub slack_colors: std::collections::BTreeMap<String, String>,
+    // keep slack from expanding the urls in the message. the images are only shown by the expansion.
+    #[cfg(feature = "slack")]
+    pub disable_unfurl: bool,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            wrap_mondai_in_codeblock: false,
+            include_images: true,
+            image_count_note: false,
+            include_answer: true,
+            include_explanation: false,
+            summary_max_chars: None,
+            max_lines: None,
+            localize_labels: false,
+            #[cfg(feature = "slack")]
+            text_mode: crate::extract::TextMode::Plain,
+            #[cfg(feature = "slack")]
+            slack_colors: std::collections::BTreeMap::new(),
+            #[cfg(feature = "slack")]
+            disable_unfurl: false,
+        }
+    }
+}
+
+// the words put around the answer and the explanation in the messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Labels {
+    pub answer: &'static str,
+    pub correct: &'static str,
+    pub explanation: &'static str,
+}
+
+const JAPANESE_LABELS: Labels = Labels {
+    answer: "正解",
+    correct: "(正解)",
+    explanation: "解説",
+};
+
+const ENGLISH_LABELS: Labels = Labels {
+    answer: "Answer",
+    correct: "(correct)",
+    explanation: "Explanation",
+};
+
+// the labels in english for the questions detected as english with `localize_labels`,
+// and in japanese otherwise, including the ones of an unknown language
+pub fn labels_of(kakomon: &Kakomon, options: &FormatOptions) -> Labels {
+    match kakomon.language.as_deref() {
+        Some("eng") if options.localize_labels => ENGLISH_LABELS,
+        _ => JAPANESE_LABELS,
+    }
+}
+
+// the "解説" section, if the explanation is extracted and `include_explanation` is set
+pub fn format_explanation(kakomon: &Kakomon, options: &FormatOptions) -> String {
+    match &kakomon.explanation {
+        Some(explanation) if options.include_explanation => format!(
+            "{}:\n{}\n",
+            labels_of(kakomon, options).explanation,
+            explanation
+        ),
+        _ => String::new(),
+    }
+}
+
+// the question in plain text (with markdown code blocks) for the destinations other than slack
+// and the `text` of the templates
+pub fn format_text(kakomon: &Kakomon, options: &FormatOptions) -> String {
+    let mut text = String::new();
+    if let Some(url) = &kakomon.answer_url {
+        text += format!("{}\n", url).as_str();
+    }
+    if options.wrap_mondai_in_codeblock {
+        text += format!("```\n{}\n```\n", kakomon.mondai.replace("```", "'''")).as_str();
+    } else {
+        text += format!("{}\n", kakomon.mondai).as_str();
+    }
+    text += format_choices_with_answer(kakomon, options).as_str();
+    text += format_explanation(kakomon, options).as_str();
+    text += format_images(&kakomon.images, options).as_str();
+    text
+}
+
+// the image urls, one per line, or the note on how many images are left out
+pub fn format_images(images: &[Url], options: &FormatOptions) -> String {
+    if options.include_images {
+        images.iter().map(|image| format!("{}\n", image)).collect()
+    } else if options.image_count_note && !images.is_empty() {
+        format!("(画像 {} 枚あり)\n", images.len())
+    } else {
+        String::new()
+    }
+}
+
+// Render the question into a body of at most `max_bytes` of json.
+// The images, the explanation and the choices are dropped from the last one in this order,
+// and then the statement is cut, until it fits. `render` gets the note on what is dropped.
+pub fn trim_to_fit(
+    kakomon: &Kakomon,
+    max_bytes: usize,
+    render: impl Fn(&Kakomon, Option<&str>) -> Value,
+) -> Value {
+    let body = render(kakomon, None);
+    if body.to_string().len() <= max_bytes {
+        return body;
+    }
+
+    let mut trimmed = kakomon.clone();
+    let mut dropped = Dropped::default();
+    loop {
+        if trimmed.images.pop().is_some() {
+            dropped.images += 1;
+        } else if trimmed.explanation.take().is_some() {
+            dropped.explanation = true;
+        } else if trimmed.choices.pop().is_some() {
+            dropped.choices += 1;
+        } else {
+            let chars = trimmed.mondai.chars().count();
+            if chars == 0 {
+                // only the title and the note are left
+                return render(&trimmed, Some(&dropped.note()));
+            }
+            trimmed.mondai = trimmed.mondai.chars().take(chars / 2).collect::<String>();
+            dropped.mondai = true;
+        }
+        let body = render(&trimmed, Some(&dropped.note()));
+        if body.to_string().len() <= max_bytes {
+            return body;
+        }
+    }
+}
+
+// The question with the statement cut at the end of a sentence (`。`) within `max_chars`,
+// followed by `SUMMARY_NOTE`. The first sentence is kept as a whole even if it is longer.
+// None if the statement is short enough, or there is no answer page to read the rest on.
+pub fn summarize(kakomon: &Kakomon, max_chars: usize) -> Option<Kakomon> {
+    kakomon.answer_url.as_ref()?;
+    if kakomon.mondai.chars().count() <= max_chars {
+        return None;
+    }
+    let ends = kakomon
+        .mondai
+        .match_indices('。')
+        .map(|(idx, end)| idx + end.len());
+    let mut cut = None;
+    for end in ends {
+        if cut.is_some() && kakomon.mondai[..end].chars().count() > max_chars {
+            break;
+        }
+        cut = Some(end);
+    }
+    let cut = cut.filter(|&cut| cut < kakomon.mondai.trim_end().len())?;
+    let mut summarized = kakomon.clone();
+    summarized.mondai = format!("{}{}", &kakomon.mondai[..cut], SUMMARY_NOTE);
+    Some(summarized)
+}
+
+// the lines of the statement, the choices and the explanation shown with the options
+fn line_count(kakomon: &Kakomon, options: &FormatOptions) -> usize {
+    let explanation = match &kakomon.explanation {
+        // and the "解説:" line
+        Some(explanation) if options.include_explanation => explanation.lines().count() + 1,
+        _ => 0,
+    };
+    kakomon.mondai.lines().count()
+        + kakomon
+            .choices
+            .iter()
+            .map(|choice| choice.lines().count().max(1))
+            .sum::<usize>()
+        + explanation
+}
+
+// The question with the statement, the choices and the explanation cut after `max_lines` lines,
+// with the note "…(N 行省略)" on the lines left out. A choice is left out as a whole rather than
+// cut in the middle. The answer url is kept. None if there is no limit or the body is short enough.
+pub fn limit_lines(kakomon: &Kakomon, options: &FormatOptions) -> Option<(Kakomon, String)> {
+    let max_lines = options.max_lines?;
+    let total = line_count(kakomon, options);
+    if total <= max_lines {
+        return None;
+    }
+    let mut limited = kakomon.clone();
+    let mondai = kakomon.mondai.lines().collect::<Vec<_>>();
+    if mondai.len() >= max_lines {
+        limited.mondai = mondai[..max_lines].join("\n");
+        limited.choices.clear();
+        limited.explanation = None;
+    } else {
+        let mut left = max_lines - mondai.len();
+        let kept = kakomon
+            .choices
+            .iter()
+            .take_while(
+                |choice| match left.checked_sub(choice.lines().count().max(1)) {
+                    Some(rest) => {
+                        left = rest;
+                        true
+                    }
+                    None => false,
+                },
+            )
+            .count();
+        limited.choices.truncate(kept);
+        limited.explanation = match &kakomon.explanation {
+            // after the "解説:" line
+            Some(explanation) if kept == kakomon.choices.len() && left > 1 => Some(
+                explanation
+                    .lines()
+                    .take(left - 1)
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ),
+            _ => None,
+        };
+    }
+    let omitted = total - line_count(&limited, options);
+    Some((limited, format!("…({} 行省略)", omitted)))
+}
+
+// what `trim_to_fit` dropped
+#[derive(Debug, Default)]
+struct Dropped {
+    images: usize,
+    explanation: bool,
+    choices: usize,
+    mondai: bool,
+}
+
+impl Dropped {
+    fn note(&self) -> String {
+        let mut parts = Vec::new();
+        if self.images > 0 {
+            parts.push(format!("画像 {} 件", self.images));
+        }
+        if self.explanation {
+            parts.push("解説".to_string());
+        }
+        if self.choices > 0 {
+            parts.push(format!("選択肢 {} 件", self.choices));
+        }
+        if self.mondai {
+            parts.push("問題文の後半".to_string());
+        }
+        format!(
+            "メッセージの上限を超えるため{}を省略しました",
+            parts.join("、")
+        )
+    }
+}
+
+// the choices as a numbered list, one per line. the `answer` is marked as "(正解)".
+pub fn format_choices(choices: &[String], answer: Option<usize>) -> String {
+    format_marked_choices(choices, answer, JAPANESE_LABELS.correct)
+}
+
+fn format_marked_choices(choices: &[String], answer: Option<usize>, correct: &str) -> String {
+    choices
+        .iter()
+        .enumerate()
+        .map(|(idx, choice)| {
+            let mark = if answer == Some(idx) {
+                format!(" {}", correct)
+            } else {
+                String::new()
+            };
+            format!("{}. {}{}\n", idx + 1, strip_choice_number(choice), mark)
+        })
+        .collect()
+}
+
+// the choices with the answer marked and the "正解: ウ" line, unless `include_answer` is off
+pub fn format_choices_with_answer(kakomon: &Kakomon, options: &FormatOptions) -> String {
+    if !options.include_answer {
+        return format_choices(&kakomon.choices, None);
+    }
+    let labels = labels_of(kakomon, options);
+    let mut text = format_marked_choices(&kakomon.choices, kakomon.answer_index(), labels.correct);
+    if let Some(answer) = &kakomon.answer {
+        text += format!("{}: {}\n", labels.answer, answer).as_str();
+    }
+    text
+}
+
+// remove the numbering written in the choice itself, e.g. "1. ", "(2)", "ア．" or "エ "
+fn strip_choice_number(choice: &str) -> &str {
+    let choice = choice.trim();
+    let rest = if let Some(rest) = choice.strip_prefix(['(', '（']) {
+        // "(1)", "（ア）"
+        let number = rest.trim_start_matches(|c: char| c.is_ascii_digit() || is_katakana(c));
+        match number.strip_prefix([')', '）']) {
+            Some(after) if number.len() < rest.len() => after,
+            _ => return choice,
+        }
+    } else {
+        let rest = choice.trim_start_matches(|c: char| c.is_ascii_digit());
+        let rest = if rest.len() == choice.len() {
+            // a single katakana like "ア" as the number
+            let mut chars = choice.chars();
+            match chars.next() {
+                Some(c) if is_katakana(c) => chars.as_str(),
+                _ => return choice,
+            }
+        } else {
+            rest
+        };
+        let after = match rest.strip_prefix(['.', '．', ')', '）', ':', '：', '、']) {
+            // "0.8" or "1:2" is a number rather than the numbering
+            Some(after) if after.starts_with(|c: char| c.is_ascii_digit()) => return choice,
+            Some(after) => after,
+            // "ア 選択肢" without any punctuation
+            None if rest.starts_with(char::is_whitespace) => rest,
+            None => return choice,
+        };
+        // "10 ms" is a quantity rather than "ms" numbered 10
+        if rest.len() < choice.len() && is_unit(after.trim_start()) {
+            return choice;
+        }
+        after
+    };
+    rest.trim_start()
+}
+
+// the units written after a number in the choices
+const UNITS: &[&str] = &[
+    "%",
+    "％",
+    "倍",
+    "個",
+    "回",
+    "件",
+    "台",
+    "人",
+    "円",
+    "桁",
+    "年",
+    "か月",
+    "ヶ月",
+    "日",
+    "時間",
+    "分",
+    "秒",
+    "ミリ秒",
+    "マイクロ秒",
+    "ナノ秒",
+    "ビット",
+    "バイト",
+    "ns",
+    "μs",
+    "ms",
+    "s",
+    "bit",
+    "bps",
+    "kbps",
+    "Mbps",
+    "Gbps",
+    "B",
+    "kB",
+    "KB",
+    "MB",
+    "GB",
+    "TB",
+    "Hz",
+    "kHz",
+    "MHz",
+    "GHz",
+];
+
+// whether the text starts with a unit as a word, e.g. "ms" or "秒 以内" but not "sample" or "分析"
+fn is_unit(text: &str) -> bool {
+    UNITS.iter().any(|unit| {
+        text.strip_prefix(unit)
+            .is_some_and(|after| !after.starts_with(char::is_alphanumeric))
+    })
+}
+
+fn is_katakana(c: char) -> bool {
+    ('ア'..='ン').contains(&c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_format_choices() {
+        let choices = [
+            "1. 一つ目",
+            "(2) 二つ目",
+            "ウ．三つ目",
+            "エ　四つ目",
+            "アクセスを減らす",
+            "10進数で表す",
+            "(注) 番号ではない",
+        ]
+        .map(String::from);
+        assert_eq!(
+            format_choices(&choices, None),
+            "1. 一つ目\n2. 二つ目\n3. 三つ目\n4. 四つ目\n5. アクセスを減らす\n6. 10進数で表す\n7. (注) 番号ではない\n"
+        );
+    }
+
+    #[test]
+    fn test_format_choices_numbers() {
+        let choices = [
+            "0.8",
+            "1.5倍",
+            "10 ms",
+            "2 秒 以内",
+            "3 分析する",
+            "1：2",
+            "3 sample",
+            "4. 2の補数",
+        ]
+        .map(String::from);
+        assert_eq!(
+            format_choices(&choices, None),
+            "1. 0.8\n2. 1.5倍\n3. 10 ms\n4. 2 秒 以内\n5. 分析する\n6. 1：2\n7. sample\n8. 2の補数\n"
+        );
+    }
+
+    #[test]
+    fn test_format_choices_with_answer() {
+        let kakomon = Kakomon {
+            title: "問1".to_string(),
+            mondai: "どれか。".to_string(),
+            choices: ["ア", "イ", "ウ"].map(String::from).to_vec(),
+            answer: Some("イ".to_string()),
+            ..Default::default()
+        };
+        let mut options = FormatOptions::default();
+        assert_eq!(
+            format_choices_with_answer(&kakomon, &options),
+            "1. ア\n2. イ (正解)\n3. ウ\n正解: イ\n"
+        );
+        options.include_answer = false;
+        assert_eq!(
+            format_choices_with_answer(&kakomon, &options),
+            "1. ア\n2. イ\n3. ウ\n"
+        );
+
+        let kakomon = Kakomon {
+            language: Some("eng".to_string()),
+            explanation: Some("B is right.".to_string()),
+            ..kakomon
+        };
+        let mut options = FormatOptions {
+            include_explanation: true,
+            ..FormatOptions::default()
+        };
+        assert_eq!(
+            format_choices_with_answer(&kakomon, &options),
+            "1. ア\n2. イ (正解)\n3. ウ\n正解: イ\n"
+        );
+        options.localize_labels = true;
+        assert_eq!(
+            format_choices_with_answer(&kakomon, &options),
+            "1. ア\n2. イ (correct)\n3. ウ\nAnswer: イ\n"
+        );
+        assert_eq!(
+            format_explanation(&kakomon, &options),
+            "Explanation:\nB is right.\n"
+        );
+    }
+
+    #[test]
+    fn test_trim_to_fit() {
+        let kakomon = Kakomon {
+            title: "問1".to_string(),
+            mondai: "あ".repeat(100),
+            choices: ["ア", "イ"].map(String::from).to_vec(),
+            images: (0..3)
+                .map(|idx| Url::parse(&format!("https://www.ap-siken.com/{}.png", idx)).unwrap())
+                .collect(),
+            ..Default::default()
+        };
+        let options = FormatOptions::default();
+        let render = |kakomon: &Kakomon, note: Option<&str>| json!({ "text": format_text_for_test(kakomon, &options), "note": note });
+
+        // fits as it is
+        let body = trim_to_fit(&kakomon, 10_000, render);
+        assert_eq!(body["note"], Value::Null);
+
+        // without the images
+        let note = "メッセージの上限を超えるため画像 3 件を省略しました";
+        let without_images = Kakomon {
+            images: vec![],
+            ..kakomon.clone()
+        };
+        let size = render(&without_images, Some(note)).to_string().len();
+        let body = trim_to_fit(&kakomon, size, render);
+        assert_eq!(body, render(&without_images, Some(note)));
+
+        // nothing fits
+        let body = trim_to_fit(&kakomon, 10, render);
+        assert_eq!(
+            body["note"],
+            "メッセージの上限を超えるため画像 3 件、選択肢 2 件、問題文の後半を省略しました"
+        );
+    }
+
+    fn format_text_for_test(kakomon: &Kakomon, options: &FormatOptions) -> String {
+        format!(
+            "{}\n{}{}",
+            kakomon.mondai,
+            format_choices(&kakomon.choices, None),
+            format_images(&kakomon.images, options)
+        )
+    }
+
+    #[test]
+    fn test_summarize() {
+        let mut kakomon = Kakomon {
+            title: "問1".to_string(),
+            answer_url: Some(
+                Url::parse("https://www.ap-siken.com/kakomon/05_haru/q1.html").unwrap(),
+            ),
+            mondai: "一文目です。二文目です。三文目です。".to_string(),
+            ..Default::default()
+        };
+        // cut at the last sentence within the limit
+        let summarized = summarize(&kakomon, 13).unwrap();
+        assert_eq!(
+            summarized.mondai,
+            "一文目です。二文目です。…続きは解答ページで"
+        );
+        assert_eq!(summarized.answer_url, kakomon.answer_url);
+        // the first sentence is not cut in the middle
+        let summarized = summarize(&kakomon, 3).unwrap();
+        assert_eq!(summarized.mondai, "一文目です。…続きは解答ページで");
+        assert!(summarize(&kakomon, 18).is_none());
+        // nothing is left to cut
+        kakomon.mondai = "句点のない長い問題文".to_string();
+        assert!(summarize(&kakomon, 3).is_none());
+        kakomon.mondai = "一文目です。二文目です。".to_string();
+        kakomon.answer_url = None;
+        assert!(summarize(&kakomon, 3).is_none());
+    }
+
+    #[test]
+    fn test_limit_lines() {
+        let kakomon = Kakomon {
+            title: "問1".to_string(),
+            answer_url: Some(
+                Url::parse("https://www.ap-siken.com/kakomon/05_haru/q1.html").unwrap(),
+            ),
+            mondai: "一行目\n二行目".to_string(),
+            choices: vec!["選択肢1".to_string(), "選択肢2の\n二行目".to_string()],
+            explanation: Some("解説1\n解説2".to_string()),
+            ..Default::default()
+        };
+        let mut options = FormatOptions {
+            include_explanation: true,
+            max_lines: Some(8),
+            ..Default::default()
+        };
+        assert!(limit_lines(&kakomon, &options).is_none());
+
+        // the explanation is cut at a line
+        options.max_lines = Some(7);
+        let (limited, note) = limit_lines(&kakomon, &options).unwrap();
+        assert_eq!(limited.explanation.as_deref(), Some("解説1"));
+        assert_eq!(note, "…(1 行省略)");
+        // the second choice is not cut in the middle
+        options.max_lines = Some(4);
+        let (limited, note) = limit_lines(&kakomon, &options).unwrap();
+        assert_eq!(limited.choices, ["選択肢1"]);
+        assert_eq!(limited.explanation, None);
+        assert_eq!(note, "…(5 行省略)");
+        assert_eq!(limited.answer_url, kakomon.answer_url);
+        options.max_lines = Some(1);
+        let (limited, note) = limit_lines(&kakomon, &options).unwrap();
+        assert_eq!(limited.mondai, "一行目");
+        assert!(limited.choices.is_empty());
+        assert_eq!(note, "…(7 行省略)");
+
+        // the explanation is not counted unless it is shown
+        options.include_explanation = false;
+        options.max_lines = Some(5);
+        assert!(limit_lines(&kakomon, &options).is_none());
+        options.max_lines = None;
+        assert!(limit_lines(&kakomon, &options).is_none());
+    }
+
+    #[test]
+    fn test_format_images() {
+        let images = [
+            Url::parse("https://www.ap-siken.com/kakomon/21_haru/img/31.png").unwrap(),
+            Url::parse("https://www.ap-siken.com/kakomon/21_haru/img/31a.png").unwrap(),
+        ];
+        let mut options = FormatOptions::default();
+        assert_eq!(
+            format_images(&images, &options),
+            "https://www.ap-siken.com/kakomon/21_haru/img/31.png\nhttps://www.ap-siken.com/kakomon/21_haru/img/31a.png\n"
+        );
+
+        options.include_images = false;
+        assert_eq!(format_images(&images, &options), "");
+        options.image_count_note = true;
+        assert_eq!(format_images(&images, &options), "(画像 2 枚あり)\n");
+        assert_eq!(format_images(&[], &options), "");
+    }
+}
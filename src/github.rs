@@ -0,0 +1,127 @@
+use crate::stats::RunStats;
+use log::warn;
+use std::env;
+use std::fs::OpenOptions;
+use std::io::Write;
+
+// Reports the run to GitHub Actions: the failures are annotated with the workflow
+// commands and the summary is appended to `GITHUB_STEP_SUMMARY`. Nothing happens outside of it.
+pub fn report(stats: &RunStats, destinations: &[&str]) {
+    if env::var("GITHUB_ACTIONS").is_ok_and(|value| value == "true") {
+        for command in workflow_commands(stats) {
+            println!("{}", command);
+        }
+    }
+    if let Some(path) = env::var_os("GITHUB_STEP_SUMMARY") {
+        let appended = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| file.write_all(summary_markdown(stats, destinations).as_bytes()));
+        if let Err(e) = appended {
+            warn!("failed to write the step summary: {}", e);
+        }
+    }
+}
+
+// the failures to be sent again are warnings, and the others are errors
+pub fn workflow_commands(stats: &RunStats) -> Vec<String> {
+    stats
+        .failures
+        .iter()
+        .map(|(url, reason)| {
+            let level = if reason.ends_with(", retryable") {
+                "warning"
+            } else {
+                "error"
+            };
+            format!(
+                "::{} title={}::{}",
+                level,
+                escape_property(url.as_str()),
+                escape_data(reason)
+            )
+        })
+        .collect()
+}
+
+pub fn summary_markdown(stats: &RunStats, destinations: &[&str]) -> String {
+    let mut markdown = String::from("## ipa-shiken-fetcher\n\n");
+    markdown += "| fetched | sent | failed |\n| ---: | ---: | ---: |\n";
+    markdown += &format!(
+        "| {} | {} | {} |\n\n",
+        stats.fetched,
+        stats.sent,
+        stats.failed()
+    );
+    if stats.duplicates > 0 {
+        markdown += &format!("skipped {} duplicates\n\n", stats.duplicates);
+    }
+    markdown += &format!("destinations: {}\n", destinations.join(", "));
+    if !stats.failures.is_empty() {
+        markdown += "\n### failed urls\n\n";
+        for (url, reason) in stats.failures.iter() {
+            markdown += &format!("- {} ({})\n", url, reason.replace('\n', " "));
+        }
+    }
+    markdown += "\n";
+    markdown
+}
+
+fn escape_data(value: &str) -> String {
+    value
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn escape_property(value: &str) -> String {
+    escape_data(value).replace(':', "%3A").replace(',', "%2C")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use url::Url;
+
+    #[test]
+    fn test_github_report() {
+        let mut stats = RunStats {
+            fetched: 2,
+            sent: 1,
+            fetch_failed: 1,
+            send_failed: 1,
+            ..Default::default()
+        };
+        let url = Url::parse("https://www.nw-siken.com/").unwrap();
+        stats.add_failure(&url, "HTTP 404 Not Found".to_string());
+        stats.add_failure(
+            &url,
+            "send to slack failed: rate limited, retryable".to_string(),
+        );
+
+        assert_eq!(
+            workflow_commands(&stats),
+            [
+                "::error title=https%3A//www.nw-siken.com/::HTTP 404 Not Found",
+                "::warning title=https%3A//www.nw-siken.com/::send to slack failed: rate limited, retryable",
+            ]
+        );
+        assert_eq!(
+            summary_markdown(&stats, &["slack", "discord"]),
+            "## ipa-shiken-fetcher\n\n| fetched | sent | failed |\n| ---: | ---: | ---: |\n| 2 | 1 | 2 |\n\n\
+             destinations: slack, discord\n\n### failed urls\n\n\
+             - https://www.nw-siken.com/ (HTTP 404 Not Found)\n\
+             - https://www.nw-siken.com/ (send to slack failed: rate limited, retryable)\n\n"
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("summary.md");
+        std::fs::write(&path, "# before\n").unwrap();
+        env::set_var("GITHUB_STEP_SUMMARY", &path);
+        report(&stats, &["slack"]);
+        env::remove_var("GITHUB_STEP_SUMMARY");
+        let summary = std::fs::read_to_string(&path).unwrap();
+        assert!(summary.starts_with("# before\n## ipa-shiken-fetcher\n"));
+    }
+}
@@ -0,0 +1,187 @@
+use reqwest::cookie::Jar;
+use reqwest::redirect::Policy;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use url::Url;
+
+// a request taking longer than this fails unless `http_timeout_secs` is configured
+pub const DEFAULT_HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+// the redirects followed unless `max_redirects` is configured
+pub const DEFAULT_MAX_REDIRECTS: usize = 10;
+// the connections to a host at the same time unless `max_connections_per_host` is configured
+pub const DEFAULT_MAX_CONNECTIONS_PER_HOST: usize = 2;
+
+// How every http request is made, both the fetches and the webhooks.
+#[derive(Debug, Clone)]
+pub struct HttpOptions {
+    pub timeout: Duration,
+    // the proxy of all the requests. `HTTP_PROXY` and `HTTPS_PROXY` are used if it is not set.
+    pub proxy: Option<Url>,
+    // 0 does not follow any redirect
+    pub max_redirects: usize,
+    // 1 or more, the idle connections kept and the fetches at the same time for each host
+    pub max_connections_per_host: usize,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        HttpOptions {
+            timeout: DEFAULT_HTTP_TIMEOUT,
+            proxy: None,
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+            max_connections_per_host: DEFAULT_MAX_CONNECTIONS_PER_HOST,
+        }
+    }
+}
+
+// The client of all the requests, so that they have the same settings.
+// The responses are decompressed (gzip, brotli and deflate) and TLS is 1.2 or later.
+// The fetcher gives the cookie jar, which the webhooks are sent without.
+pub fn build_http_client(
+    options: &HttpOptions,
+    jar: Option<Arc<Jar>>,
+) -> Result<reqwest::Client, reqwest::Error> {
+    let redirect = match options.max_redirects {
+        0 => Policy::none(),
+        max => Policy::limited(max),
+    };
+    let mut builder = reqwest::Client::builder()
+        .user_agent(crate::version::USER_AGENT)
+        .timeout(options.timeout)
+        .connect_timeout(options.timeout.min(Duration::from_secs(10)))
+        .redirect(redirect)
+        .pool_max_idle_per_host(options.max_connections_per_host)
+        .gzip(true)
+        .brotli(true)
+        .deflate(true);
+    #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+    {
+        builder = builder.min_tls_version(reqwest::tls::Version::TLS_1_2);
+    }
+    if let Some(proxy) = &options.proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy.as_str())?);
+    }
+    if let Some(jar) = jar {
+        builder = builder.cookie_provider(jar);
+    }
+    builder.build()
+}
+
+// The requests to a host wait for one of its `max` permits, not to put a load on the server.
+// The hosts do not wait for each other.
+#[derive(Debug)]
+pub struct HostLimits {
+    max: usize,
+    hosts: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+impl HostLimits {
+    pub fn new(max: usize) -> Self {
+        HostLimits {
+            max,
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // the connection to the host of the url may be made while the permit is kept
+    pub async fn acquire(&self, url: &Url) -> OwnedSemaphorePermit {
+        let semaphore = self
+            .hosts
+            .lock()
+            .unwrap()
+            .entry(url.host_str().unwrap_or_default().to_string())
+            .or_insert_with(|| Arc::new(Semaphore::new(self.max)))
+            .clone();
+        semaphore
+            .acquire_owned()
+            .await
+            .expect("the semaphore is never closed")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_build_http_client() {
+        // redirects every request, and answers nothing to a request of `/slow`
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut buf = vec![0; 4096];
+                    let len = stream.read(&mut buf).await.unwrap();
+                    if String::from_utf8_lossy(&buf[..len]).starts_with("GET /slow ") {
+                        tokio::time::sleep(Duration::from_secs(5)).await;
+                    }
+                    let response =
+                        "HTTP/1.1 302 Found\r\nLocation: /next\r\nContent-Length: 0\r\n\r\n";
+                    let _ = stream.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+
+        let options = HttpOptions {
+            timeout: Duration::from_millis(200),
+            max_redirects: 0,
+            ..Default::default()
+        };
+        let client = build_http_client(&options, None).unwrap();
+        let res = client
+            .get(format!("http://{}/", addr))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), reqwest::StatusCode::FOUND);
+        let err = client
+            .get(format!("http://{}/slow", addr))
+            .send()
+            .await
+            .unwrap_err();
+        assert!(err.is_timeout());
+
+        // a redirect loop is an error after `max_redirects`
+        let client =
+            build_http_client(&HttpOptions::default(), Some(Arc::new(Jar::default()))).unwrap();
+        let err = client
+            .get(format!("http://{}/", addr))
+            .send()
+            .await
+            .unwrap_err();
+        assert!(err.is_redirect());
+
+        let options = HttpOptions {
+            proxy: Some(Url::parse("http://127.0.0.1:3128").unwrap()),
+            ..Default::default()
+        };
+        assert!(build_http_client(&options, None).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_host_limits() {
+        let limits = HostLimits::new(2);
+        let url = Url::parse("https://www.ap-siken.com/kakomon/05_haru/q1.html").unwrap();
+        let first = limits.acquire(&url).await;
+        let _second = limits.acquire(&url).await;
+        let wait = Duration::from_millis(50);
+        assert!(tokio::time::timeout(wait, limits.acquire(&url))
+            .await
+            .is_err());
+        // another host is not limited by them
+        let other = Url::parse("https://www.nw-siken.com/").unwrap();
+        assert!(tokio::time::timeout(wait, limits.acquire(&other))
+            .await
+            .is_ok());
+        drop(first);
+        assert!(tokio::time::timeout(wait, limits.acquire(&url))
+            .await
+            .is_ok());
+    }
+}
@@ -0,0 +1,116 @@
+use reqwest::cookie::{CookieStore, Jar};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+// `login` in CONFIG: the login form posted to get the session cookies
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LoginConfig {
+    pub url: String,
+    // the fields of the form, e.g. `{"id": "user", "password": "..."}`
+    pub form: BTreeMap<String, String>,
+    // the session cookies are saved in and reused from it
+    #[serde(default)]
+    pub cookie_file: Option<PathBuf>,
+}
+
+// only the names of the fields not to print the password in the logs
+impl fmt::Debug for LoginConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LoginConfig")
+            .field("url", &self.url)
+            .field("form", &self.form.keys().collect::<Vec<_>>())
+            .field("cookie_file", &self.cookie_file)
+            .finish()
+    }
+}
+
+// post the login form. the session cookies are stored in the cookie store of the client.
+pub async fn login(client: &reqwest::Client, login: &LoginConfig) -> Result<(), Box<dyn Error>> {
+    let res = client
+        .post(login.url.as_str())
+        .form(&login.form)
+        .send()
+        .await?;
+    if let Err(e) = res.error_for_status_ref() {
+        return Err(format!("failed to log in to {}: {}", login.url, e).into());
+    }
+    Ok(())
+}
+
+// save the cookies of the urls as `{"https://host/": "name=value; ..."}`, readable only by the owner
+pub fn save_cookies(jar: &Jar, urls: &[Url], path: &Path) -> Result<(), Box<dyn Error>> {
+    let mut cookies = BTreeMap::new();
+    for url in urls.iter() {
+        if let Some(header) = jar.cookies(url) {
+            cookies.insert(
+                url.origin().ascii_serialization(),
+                header.to_str()?.to_string(),
+            );
+        }
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    file.write_all(serde_json::to_string(&cookies)?.as_bytes())?;
+    Ok(())
+}
+
+// restore the cookies saved with `save_cookies`. a missing file is not an error.
+pub fn load_cookies(jar: &Jar, path: &Path) -> Result<(), Box<dyn Error>> {
+    if !path.exists() {
+        return Ok(());
+    }
+    let cookies: BTreeMap<String, String> = serde_json::from_str(&fs::read_to_string(path)?)?;
+    for (origin, header) in cookies.iter() {
+        let url = Url::parse(origin)?;
+        for cookie in header.split(';').map(str::trim).filter(|c| !c.is_empty()) {
+            jar.add_cookie_str(&format!("{}; Path=/", cookie), &url);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_and_load_cookies() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cookies.json");
+        let url = Url::parse("https://www.ap-siken.com/kakomon/").unwrap();
+
+        let jar = Jar::default();
+        jar.add_cookie_str("session=abc; Path=/", &url);
+        save_cookies(&jar, std::slice::from_ref(&url), &path).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let jar = Jar::default();
+        load_cookies(&jar, &path).unwrap();
+        assert_eq!(jar.cookies(&url).unwrap(), "session=abc");
+        load_cookies(&jar, &dir.path().join("missing.json")).unwrap();
+    }
+
+    #[test]
+    fn test_login_config_debug() {
+        let login: LoginConfig = serde_json::from_str(
+            r#"{"url": "https://example.com/login", "form": {"id": "user", "password": "secret"}}"#,
+        )
+        .unwrap();
+        assert!(!format!("{:?}", login).contains("secret"));
+    }
+}
@@ -1,197 +1,3598 @@
-use json::object;
-use log::debug;
-use scraper::{Html, Selector};
+mod bloom;
+mod breaker;
+mod cache;
+mod confirm;
+mod destination;
+#[cfg(feature = "discord")]
+mod discord;
+#[cfg(feature = "email")]
+mod email;
+mod encoding;
+mod extract;
+mod fetch;
+mod filter;
+mod format;
+mod github;
+mod http;
+mod login;
+mod message_template;
+mod metrics;
+mod output;
+mod pipeline;
+mod quiet;
+mod report;
+mod retry;
+mod schedule;
+mod site;
+#[cfg(feature = "slack")]
+mod slack;
+mod state;
+mod stats;
+#[cfg(feature = "teams")]
+mod teams;
+#[cfg(feature = "telegram")]
+mod telegram;
+mod template;
+mod version;
+
+use bloom::{DEFAULT_BLOOM_CAPACITY, DEFAULT_BLOOM_FALSE_POSITIVE_RATE};
+use breaker::{CircuitBreaker, RawBreaker};
+use cache::{HtmlCache, MemoryCache, DEFAULT_HTML_CACHE_TTL, DEFAULT_MEMORY_CACHE_CAPACITY};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use clap::{Parser, Subcommand, ValueEnum};
+use confirm::Confirm;
+use destination::{Destination, Question, Target};
+use extract::{
+    extract_explanation, parse_exclude_selectors, parse_selectors, ExamSeason, ExtractOptions,
+    Kakomon, TextMode,
+};
+use fetch::{
+    normalize_url, parse_headers, validate_response, BasicAuth, Cookies, FetchDelay, FetchOptions,
+    Fetcher, FileFetcher, ReqwestFetcher, ResponseCheck, ResponseTooLarge,
+};
+use filter::{ExamFilter, FilterUnknown, LanguageFilter};
+use format::{FormatOptions, DEFAULT_SUMMARY_MAX_CHARS};
+use futures_util::StreamExt;
+use http::{
+    build_http_client, HttpOptions, DEFAULT_HTTP_TIMEOUT, DEFAULT_MAX_CONNECTIONS_PER_HOST,
+    DEFAULT_MAX_REDIRECTS,
+};
+use indicatif::ProgressBar;
+use log::{debug, error, info, warn};
+use login::LoginConfig;
+use message_template::Templates;
+use metrics::Metrics;
+use pipeline::{PipelineOptions, RawPipeline, SharedRun};
+use quiet::{QuietHours, QuietMode};
+use report::{post_report, ReportWebhook};
+use retry::{RawRetry, RetryPolicy};
+use schedule::Schedule;
 use serde::{Deserialize, Serialize};
+use state::{
+    ContentChange, DedupKey, PendingQuestion, QueuedQuestion, SeenStore, State,
+    DEFAULT_PENDING_MAX_ATTEMPTS,
+};
+use stats::{describe_error, RunStats, EXIT_CONFIG_ERROR, EXIT_FAILURE, EXIT_SUCCESS};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
 use std::env;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use template::{expand_url_template, is_url_template, listing_url};
+use tokio::sync::mpsc;
 use url::Url;
 
+// wait between the messages not to hit the rate limit of the webhooks
+const DEFAULT_PER_MESSAGE_DELAY: Duration = Duration::from_millis(300);
+
+#[derive(Parser, Debug)]
+#[command(about, version = version::VERSION)]
+struct Args {
+    /// Fetch pages again even if they are cached in `html_cache_dir`
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Print the body sent to the webhook to stderr
+    #[arg(long)]
+    print_body: bool,
+
+    /// Extract the questions without sending them
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Show the body of each question and ask whether to send it: y (send), n (skip) or a (send all the rest)
+    #[arg(long)]
+    interactive: bool,
+
+    /// Dump the elements found for the extraction to stderr
+    #[arg(long)]
+    debug_extract: bool,
+
+    /// Extract the question from this html file instead of fetching `fetch_urls` (repeatable)
+    #[arg(long, requires = "source_url")]
+    html_file: Vec<PathBuf>,
+
+    /// The url of the page of each `--html-file`, in the same order
+    #[arg(long, requires = "html_file")]
+    source_url: Vec<Url>,
+
+    /// Keep running on the `interval_secs` or `cron` schedule
+    #[arg(long)]
+    daemon: bool,
+
+    /// Run once and exit even if a schedule is configured (takes precedence over `--daemon`)
+    #[arg(long)]
+    once: bool,
+
+    /// Run even if `fetch_urls` is empty, only warning about it
+    #[arg(long)]
+    allow_empty: bool,
+
+    /// Fail on the questionable configs such as the duplicate `fetch_urls`
+    #[arg(long)]
+    strict: bool,
+
+    /// Send the questions of a run in the order of the exams and the question numbers
+    #[arg(long)]
+    sort: bool,
+
+    /// Exit with an error if a question is not extracted from a fetched page
+    #[arg(long)]
+    fail_on_extract_error: bool,
+
+    /// Fetch only this many of the fetch urls, after the templates are expanded
+    #[arg(long)]
+    limit: Option<usize>,
+
+    /// Skip this many of the fetch urls first
+    #[arg(long, default_value_t = 0)]
+    offset: usize,
+
+    /// Do not show the progress bar
+    #[arg(long, global = true)]
+    quiet: bool,
+
+    /// The profile of `profiles` in CONFIG to use, `APP_ENV` if not given
+    #[arg(long, global = true)]
+    profile: Option<String>,
+
+    /// Format of the summary printed at the end of a run
+    #[arg(long, global = true, value_enum, default_value_t = Format::Text)]
+    format: Format,
+
+    /// Do not color the output
+    #[arg(long, global = true)]
+    no_color: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Text,
+    Json,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the questions of the pages without sending them
+    List,
+    /// Print when each url was fetched last from `state_file`
+    Status,
+    /// Send the questions saved as json without fetching the pages
+    Replay {
+        /// A json file of the questions, an array or a single one
+        #[arg(long)]
+        input: PathBuf,
+
+        /// Send to this url instead of the destinations in CONFIG
+        #[arg(long)]
+        webhook: Option<Url>,
+
+        /// Send the questions in `state_file` as well
+        #[arg(long)]
+        force: bool,
+    },
+}
+
 #[derive(Debug)]
 struct Config {
-    webhook_url: Url,
+    // every question is sent to all of them unless its category is in `category_routes`
+    // only the enabled ones
+    destinations: Vec<Target>,
+    // the number of the ones with `enabled: false`
+    disabled_destinations: usize,
+    // the category of the questions to the destination of them instead of `destinations`
+    category_routes: BTreeMap<String, Target>,
+    exam_filter: ExamFilter,
+    language_filter: LanguageFilter,
+    pipeline: PipelineOptions,
+    // None for no limit
+    parse_timeout: Option<Duration>,
+    templates: Templates,
     fetch_urls: Vec<Url>,
+    fetch: FetchOptions,
+    extract: ExtractOptions,
+    // the host to the options overriding `extract`
+    site_extract: BTreeMap<String, ExtractOptions>,
+    format: FormatOptions,
+    html_cache: Option<HtmlCache>,
+    memory_cache: Option<MemoryCache>,
+    state_file: Option<PathBuf>,
+    state_compress: bool,
+    // the questions failed to send this many times are given up and moved to `dead_letter_file`
+    pending_max_attempts: u32,
+    dead_letter_file: Option<PathBuf>,
+    schedule: Option<Schedule>,
+    // the fetch urls with their own intervals in the daemon, the others follow `schedule`
+    url_intervals: Vec<(Url, Duration)>,
+    per_message_delay: Duration,
+    // the `retry` of the destinations not overriding it, e.g. the one of `--webhook`
+    retry: RetryPolicy,
+    update_sent_messages: bool,
+    // send the questions changed since they were sent again as "(更新)"
+    notify_updates: bool,
+    quiet_hours: Option<QuietHours>,
+    timezone: Tz,
+    dedup_key: DedupKey,
+    seen_store: SeenStore,
+    metrics_addr: Option<SocketAddr>,
+    report_webhook: Option<ReportWebhook>,
+    // removed from `fetch_urls`
+    duplicate_fetch_urls: Vec<Url>,
+    // the webhooks are sent with it, built with the same `fetch.http` as the fetcher
+    http_client: reqwest::Client,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 struct RawConfig {
-    webhook_url: String,
-    fetch_urls: Vec<String>,
+    #[serde(default)]
+    webhook_url: Option<RawWebhook>,
+    // the kind of the destination to its url, e.g. `{"slack": "...", "discord": "..."}`
+    #[serde(default)]
+    webhooks: BTreeMap<String, RawWebhook>,
+    // the category of the questions to the webhook of them, e.g. `{"テクノロジ系": "..."}`
+    #[serde(default)]
+    category_routes: BTreeMap<String, RawWebhook>,
+    // only the questions of these years (e.g. 2023 of 令和5年) and seasons (`spring`, `autumn`) are sent
+    #[serde(default)]
+    filter_years: Vec<u32>,
+    #[serde(default)]
+    filter_seasons: Vec<ExamSeason>,
+    // only the questions whose statement is detected to be of these languages, e.g. `["jpn"]`
+    #[serde(default)]
+    filter_languages: Vec<String>,
+    // `exclude` or `include` the questions whose exam or language is unknown while filtering
+    #[serde(default)]
+    filter_unknown: FilterUnknown,
+    // the name to the handlebars template of the messages
+    #[serde(default)]
+    templates: BTreeMap<String, String>,
+    #[cfg(feature = "slack")]
+    #[serde(default)]
+    slack_api: Option<slack::SlackApiConfig>,
+    fetch_urls: Vec<RawFetchUrl>,
+    #[serde(default)]
+    cookies: Vec<String>,
+    #[serde(default)]
+    cookie_header: Option<String>,
+    // sent to all the fetch urls
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    // how the fetches and the webhooks are retried, overridden by each of them
+    #[serde(default)]
+    retry: RawRetry,
+    // skip a destination failing in a row for a while, off unless given
+    #[serde(default)]
+    circuit_breaker: Option<RawBreaker>,
+    #[serde(default)]
+    pipeline: RawPipeline,
+    #[serde(default)]
+    login: Option<LoginConfig>,
+    #[serde(default)]
+    fetch_delay_ms: u64,
+    #[serde(default)]
+    fetch_jitter_ms: u64,
+    #[serde(default)]
+    fetch_jitter_seed: Option<u64>,
+    #[serde(default)]
+    http_timeout_secs: Option<u64>,
+    // the pages taking longer than this to parse are skipped, 0 for no limit
+    #[serde(default)]
+    parse_timeout_secs: Option<u64>,
+    #[serde(default)]
+    http_proxy: Option<String>,
+    #[serde(default)]
+    max_redirects: Option<usize>,
+    #[serde(default)]
+    max_connections_per_host: Option<usize>,
+    #[serde(default)]
+    exclude_selectors: Option<Vec<String>>,
+    #[serde(default)]
+    title_selectors: Option<Vec<String>>,
+    #[serde(default)]
+    title_trim_site_name: bool,
+    #[serde(default)]
+    difficulty_selectors: Option<Vec<String>>,
+    #[serde(default)]
+    kako_sibling_selectors: Option<Vec<String>>,
+    // the host to the selectors of the site
+    #[serde(default)]
+    sites: BTreeMap<String, RawSiteConfig>,
+    #[serde(default)]
+    normalize_text: bool,
+    // `plain` or `markdown`
+    #[serde(default)]
+    text_mode: TextMode,
+    #[serde(default)]
+    wrap_mondai_in_codeblock: bool,
+    #[serde(default)]
+    include_images: Option<bool>,
+    #[serde(default)]
+    image_count_note: bool,
+    #[serde(default)]
+    include_answer: Option<bool>,
+    #[serde(default)]
+    include_explanation: bool,
+    // cut the statements longer than `summary_max_chars`
+    #[serde(default)]
+    summarize: bool,
+    #[serde(default)]
+    summary_max_chars: Option<usize>,
+    // leave out the lines of the body after this many, 0 for no limit
+    #[serde(default)]
+    max_lines: Option<usize>,
+    // the answer and the explanation are labeled in english for the questions in english
+    #[serde(default)]
+    localize_labels: bool,
+    // the host of the fetch url to the color of the slack messages
+    #[serde(default)]
+    slack_colors: BTreeMap<String, String>,
+    // send the slack messages with `"unfurl_links": false, "unfurl_media": false`
+    #[serde(default)]
+    disable_unfurl: bool,
+    // the fetched pages shorter than this or without `<body>` are skipped
+    #[serde(default)]
+    min_response_bytes: Option<usize>,
+    #[serde(default)]
+    require_body: Option<bool>,
+    // the fetches longer than this are given up and skipped, 0 for no limit
+    #[serde(default)]
+    max_response_bytes: Option<usize>,
+    #[serde(default)]
+    html_cache_dir: Option<PathBuf>,
+    #[serde(default)]
+    html_cache_ttl_secs: Option<u64>,
+    #[serde(default)]
+    memory_cache_ttl_secs: Option<u64>,
+    #[serde(default)]
+    memory_cache_capacity: Option<usize>,
+    #[serde(default)]
+    state_file: Option<PathBuf>,
+    #[serde(default)]
+    state_compress: Option<bool>,
+    #[serde(default)]
+    pending_max_attempts: Option<u32>,
+    #[serde(default)]
+    dead_letter_file: Option<PathBuf>,
+    #[serde(default)]
+    per_message_delay_ms: Option<u64>,
+    #[serde(default)]
+    update_sent_messages: bool,
+    #[serde(default)]
+    notify_updates: bool,
+    #[serde(default)]
+    dedup_key: DedupKey,
+    #[serde(default)]
+    seen_store: RawSeenStore,
+    #[serde(default)]
+    bloom_capacity: Option<usize>,
+    #[serde(default)]
+    bloom_false_positive_rate: Option<f64>,
+    #[serde(default)]
+    metrics_addr: Option<String>,
+    // the summary of every run is posted to it in json
+    #[serde(default)]
+    report_webhook: Option<Url>,
+    #[serde(default)]
+    report_on_failure_only: bool,
+    #[serde(default)]
+    interval_secs: Option<u64>,
+    #[serde(default)]
+    cron: Option<String>,
+    #[serde(default)]
+    quiet_hours: Option<String>,
+    #[serde(default)]
+    quiet_hours_mode: QuietMode,
+    #[serde(default)]
+    timezone: Option<String>,
+}
+
+// a webhook url, or one with the name of the template of its messages and whether it is enabled
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+enum RawWebhook {
+    Url(String),
+    WithOptions {
+        url: String,
+        #[serde(default)]
+        template: Option<String>,
+        #[serde(default)]
+        enabled: Option<bool>,
+        // overrides `retry`
+        #[serde(default)]
+        retry: Option<RawRetry>,
+    },
+}
+
+impl RawWebhook {
+    fn url(&self) -> Result<Url, url::ParseError> {
+        match self {
+            RawWebhook::Url(url) | RawWebhook::WithOptions { url, .. } => Url::parse(url),
+        }
+    }
+
+    fn template(&self) -> Option<String> {
+        match self {
+            RawWebhook::Url(_) => None,
+            RawWebhook::WithOptions { template, .. } => template.clone(),
+        }
+    }
+
+    fn enabled(&self) -> bool {
+        match self {
+            RawWebhook::Url(_) => true,
+            RawWebhook::WithOptions { enabled, .. } => enabled.unwrap_or(true),
+        }
+    }
+
+    fn retry(&self, base: &RetryPolicy) -> RetryPolicy {
+        match self {
+            RawWebhook::WithOptions {
+                retry: Some(retry), ..
+            } => retry.over(base),
+            _ => *base,
+        }
+    }
+}
+
+// a url, or a url with the credentials of basic authentication and its http headers
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+enum RawFetchUrl {
+    Url(String),
+    WithOptions {
+        url: String,
+        #[serde(flatten)]
+        auth: Option<BasicAuth>,
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+        // fetched on its own timer of the daemon instead of `interval_secs`
+        #[serde(default)]
+        interval_secs: Option<u64>,
+        // overrides `retry`, for the pages of the same origin as well
+        #[serde(default)]
+        retry: Option<RawRetry>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+enum RawSeenStore {
+    #[default]
+    Exact,
+    Bloom,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct RawSiteConfig {
+    #[serde(default)]
+    exclude_selectors: Option<Vec<String>>,
+    #[serde(default)]
+    title_selectors: Option<Vec<String>>,
+    #[serde(default)]
+    difficulty_selectors: Option<Vec<String>>,
+    #[serde(default)]
+    kako_sibling_selectors: Option<Vec<String>>,
+}
+
+impl Config {
+    // the extract options for the site of the url
+    fn extract_options(&self, url: &Url) -> &ExtractOptions {
+        url.host_str()
+            .and_then(|host| self.site_extract.get(host))
+            .unwrap_or(&self.extract)
+    }
+
+    // the destination of the category of the question in `category_routes`, or `destinations`
+    fn targets_of(&self, kakomon: &Kakomon) -> &[Target] {
+        kakomon
+            .category
+            .as_ref()
+            .and_then(|category| self.category_routes.get(category))
+            .map_or(&self.destinations, std::slice::from_ref)
+    }
+
+    fn all_targets(&self) -> impl Iterator<Item = &Target> {
+        self.destinations
+            .iter()
+            .chain(self.category_routes.values())
+    }
 }
 
 impl RawConfig {
     fn parse(&self) -> Result<Config, Box<dyn std::error::Error>> {
-        Ok(Config {
-            webhook_url: Url::parse(self.webhook_url.as_str())?,
-            fetch_urls: self
-                .fetch_urls
+        let mut extract = ExtractOptions::default();
+        if let Some(exclude_selectors) = &self.exclude_selectors {
+            extract.exclude_selectors = parse_exclude_selectors(exclude_selectors)?;
+        }
+        if let Some(title_selectors) = &self.title_selectors {
+            extract.title_selectors = parse_selectors(title_selectors)?;
+        }
+        if let Some(difficulty_selectors) = &self.difficulty_selectors {
+            extract.difficulty_selectors = parse_selectors(difficulty_selectors)?;
+        }
+        if let Some(kako_sibling_selectors) = &self.kako_sibling_selectors {
+            extract.kako_sibling_selectors = parse_selectors(kako_sibling_selectors)?;
+        }
+        extract.title_trim_site_name = self.title_trim_site_name;
+        extract.normalize_text = self.normalize_text;
+        extract.text_mode = self.text_mode;
+        extract.explanation = self.include_explanation;
+
+        let mut site_extract = BTreeMap::new();
+        for (host, site) in self.sites.iter() {
+            let mut options = extract.clone();
+            if let Some(exclude_selectors) = &site.exclude_selectors {
+                options.exclude_selectors = parse_exclude_selectors(exclude_selectors)?;
+            }
+            if let Some(title_selectors) = &site.title_selectors {
+                options.title_selectors = parse_selectors(title_selectors)?;
+            }
+            if let Some(difficulty_selectors) = &site.difficulty_selectors {
+                options.difficulty_selectors = parse_selectors(difficulty_selectors)?;
+            }
+            if let Some(kako_sibling_selectors) = &site.kako_sibling_selectors {
+                options.kako_sibling_selectors = parse_selectors(kako_sibling_selectors)?;
+            }
+            site_extract.insert(host.clone(), options);
+        }
+
+        let retry = self.retry.over(&RetryPolicy::default());
+        let breaker = self
+            .circuit_breaker
+            .as_ref()
+            .map(RawBreaker::parse)
+            .transpose()?;
+        // the disabled ones are still validated, to be enabled as they are
+        let mut destinations = Vec::new();
+        if let Some(webhook) = &self.webhook_url {
+            destinations.push((
+                Target {
+                    destination: Destination::from_url(webhook.url()?)?,
+                    template: webhook.template(),
+                    retry: webhook.retry(&retry),
+                    breaker: breaker.map(CircuitBreaker::new),
+                },
+                webhook.enabled(),
+            ));
+        }
+        for (kind, webhook) in self.webhooks.iter() {
+            destinations.push((
+                Target {
+                    destination: Destination::from_kind(kind, webhook.url()?)?,
+                    template: webhook.template(),
+                    retry: webhook.retry(&retry),
+                    breaker: breaker.map(CircuitBreaker::new),
+                },
+                webhook.enabled(),
+            ));
+        }
+        #[cfg(feature = "slack")]
+        if let Some(slack_api) = &self.slack_api {
+            destinations.push((
+                Target {
+                    destination: Destination::SlackApi(slack::SlackApi::new(slack_api.clone())),
+                    template: slack_api.template.clone(),
+                    retry: slack_api
+                        .retry
+                        .as_ref()
+                        .map_or(retry, |slack_retry| slack_retry.over(&retry)),
+                    breaker: breaker.map(CircuitBreaker::new),
+                },
+                slack_api.enabled.unwrap_or(true),
+            ));
+        }
+        if destinations.is_empty() {
+            return Err("either `webhook_url`, `webhooks` or `slack_api` is needed".into());
+        }
+        // the disabled ones are not routed, and the questions go to `destinations`
+        let mut category_routes = BTreeMap::new();
+        for (category, webhook) in self.category_routes.iter() {
+            let target = Target {
+                destination: Destination::from_url(webhook.url()?)?,
+                template: webhook.template(),
+                retry: webhook.retry(&retry),
+                breaker: breaker.map(CircuitBreaker::new),
+            };
+            if webhook.enabled() {
+                category_routes.insert(category.clone(), target);
+            }
+        }
+        let templates = Templates::compile(&self.templates)?;
+        if let Some(name) = destinations
+            .iter()
+            .map(|(target, _)| target)
+            .chain(category_routes.values())
+            .filter_map(|target| target.template.as_ref())
+            .find(|name| !templates.contains(name))
+        {
+            return Err(format!("the template `{}` is not in `templates`", name).into());
+        }
+        let disabled_destinations = destinations.iter().filter(|(_, enabled)| !enabled).count();
+        let destinations = destinations
+            .into_iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(target, _)| target)
+            .collect();
+
+        if let Some(login) = &self.login {
+            Url::parse(&login.url)?;
+        }
+
+        let mut fetch_urls = Vec::<Url>::new();
+        let mut basic_auth = Vec::new();
+        let mut url_headers = Vec::new();
+        let mut url_intervals = Vec::new();
+        let mut url_retries = Vec::new();
+        let mut duplicate_fetch_urls = Vec::new();
+        for fetch_url in self.fetch_urls.iter() {
+            let url = match fetch_url {
+                RawFetchUrl::Url(url) => Url::parse(url)?,
+                RawFetchUrl::WithOptions {
+                    url,
+                    auth,
+                    headers,
+                    interval_secs,
+                    retry: url_retry,
+                } => {
+                    let url = Url::parse(url)?;
+                    match interval_secs {
+                        Some(0) => {
+                            return Err(
+                                format!("`interval_secs` of {} must be positive", url).into()
+                            )
+                        }
+                        Some(secs) => url_intervals.push((url.clone(), Duration::from_secs(*secs))),
+                        None => {}
+                    }
+                    if let Some(auth) = auth {
+                        basic_auth.push((url.clone(), auth.clone()));
+                    }
+                    if !headers.is_empty() {
+                        url_headers.push((url.clone(), parse_headers(headers)?));
+                    }
+                    if let Some(url_retry) = url_retry {
+                        url_retries.push((url.clone(), url_retry.over(&retry)));
+                    }
+                    url
+                }
+            };
+            // the first one is kept not to send the questions twice
+            let normalized = normalize_url(&url);
+            if fetch_urls
                 .iter()
-                .map(|url| Url::parse(url))
-                .collect::<Result<Vec<_>, _>>()?,
+                .any(|seen| normalize_url(seen) == normalized)
+            {
+                duplicate_fetch_urls.push(url);
+            } else {
+                fetch_urls.push(url);
+            }
+        }
+
+        #[cfg(feature = "slack")]
+        if let Some((host, color)) = self
+            .slack_colors
+            .iter()
+            .find(|(_, color)| !slack::is_slack_color(color))
+        {
+            return Err(format!(
+                "invalid color {:?} of {} in `slack_colors`, expected e.g. `#36a64f`",
+                color, host
+            )
+            .into());
+        }
+
+        let timezone = match &self.timezone {
+            Some(name) => name
+                .parse::<Tz>()
+                .map_err(|e| format!("invalid `timezone` {:?}: {}", name, e))?,
+            None => Tz::UTC,
+        };
+
+        let http = HttpOptions {
+            timeout: self
+                .http_timeout_secs
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_HTTP_TIMEOUT),
+            proxy: self
+                .http_proxy
+                .as_deref()
+                .map(|proxy| {
+                    Url::parse(proxy)
+                        .map_err(|e| format!("invalid `http_proxy` {:?}: {}", proxy, e))
+                })
+                .transpose()?,
+            max_redirects: self.max_redirects.unwrap_or(DEFAULT_MAX_REDIRECTS),
+            max_connections_per_host: match self.max_connections_per_host {
+                Some(0) => return Err("`max_connections_per_host` must be 1 or more".into()),
+                Some(max) => max,
+                None => DEFAULT_MAX_CONNECTIONS_PER_HOST,
+            },
+        };
+
+        let seen_store = match self.seen_store {
+            RawSeenStore::Exact => SeenStore::Exact,
+            RawSeenStore::Bloom => {
+                let false_positive_rate = self
+                    .bloom_false_positive_rate
+                    .unwrap_or(DEFAULT_BLOOM_FALSE_POSITIVE_RATE);
+                if !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+                    return Err("`bloom_false_positive_rate` must be between 0 and 1".into());
+                }
+                SeenStore::Bloom {
+                    capacity: self.bloom_capacity.unwrap_or(DEFAULT_BLOOM_CAPACITY),
+                    false_positive_rate,
+                }
+            }
+        };
+
+        Ok(Config {
+            destinations,
+            disabled_destinations,
+            category_routes,
+            exam_filter: ExamFilter::new(
+                self.filter_years.clone(),
+                self.filter_seasons.clone(),
+                self.filter_unknown,
+            )?,
+            language_filter: LanguageFilter::new(
+                self.filter_languages.clone(),
+                self.filter_unknown,
+            )?,
+            pipeline: self.pipeline.parse()?,
+            parse_timeout: match self.parse_timeout_secs {
+                Some(0) => None,
+                secs => Some(secs.map_or(DEFAULT_PARSE_TIMEOUT, Duration::from_secs)),
+            },
+            templates,
+            fetch_urls,
+            duplicate_fetch_urls,
+            fetch: FetchOptions {
+                basic_auth,
+                cookies: Cookies::parse(&self.cookies, self.cookie_header.as_deref()),
+                headers: parse_headers(&self.headers)?,
+                url_headers,
+                login: self.login.clone(),
+                delay: FetchDelay::new(
+                    Duration::from_millis(self.fetch_delay_ms),
+                    Duration::from_millis(self.fetch_jitter_ms),
+                    self.fetch_jitter_seed,
+                ),
+                http: http.clone(),
+                retry,
+                url_retries,
+                check: ResponseCheck {
+                    min_bytes: self
+                        .min_response_bytes
+                        .unwrap_or(fetch::DEFAULT_MIN_RESPONSE_BYTES),
+                    require_body: self.require_body.unwrap_or(true),
+                    max_bytes: match self.max_response_bytes {
+                        Some(0) => None,
+                        max_bytes => Some(max_bytes.unwrap_or(fetch::DEFAULT_MAX_RESPONSE_BYTES)),
+                    },
+                },
+            },
+            extract,
+            site_extract,
+            format: FormatOptions {
+                wrap_mondai_in_codeblock: self.wrap_mondai_in_codeblock,
+                include_images: self.include_images.unwrap_or(true),
+                image_count_note: self.image_count_note,
+                include_answer: self.include_answer.unwrap_or(true),
+                include_explanation: self.include_explanation,
+                summary_max_chars: self
+                    .summarize
+                    .then(|| self.summary_max_chars.unwrap_or(DEFAULT_SUMMARY_MAX_CHARS)),
+                max_lines: self.max_lines.filter(|&max_lines| max_lines > 0),
+                localize_labels: self.localize_labels,
+                #[cfg(feature = "slack")]
+                text_mode: self.text_mode,
+                #[cfg(feature = "slack")]
+                slack_colors: self.slack_colors.clone(),
+                #[cfg(feature = "slack")]
+                disable_unfurl: self.disable_unfurl,
+            },
+            html_cache: self.html_cache_dir.as_ref().map(|dir| {
+                HtmlCache::new(
+                    dir.clone(),
+                    self.html_cache_ttl_secs
+                        .map(Duration::from_secs)
+                        .unwrap_or(DEFAULT_HTML_CACHE_TTL),
+                )
+            }),
+            memory_cache: self.memory_cache_ttl_secs.map(|ttl| {
+                MemoryCache::new(
+                    Duration::from_secs(ttl),
+                    self.memory_cache_capacity
+                        .unwrap_or(DEFAULT_MEMORY_CACHE_CAPACITY),
+                )
+            }),
+            state_file: self.state_file.clone(),
+            state_compress: self.state_compress.unwrap_or_else(|| {
+                self.state_file
+                    .as_deref()
+                    .is_some_and(state::is_compressed_path)
+            }),
+            pending_max_attempts: self
+                .pending_max_attempts
+                .unwrap_or(DEFAULT_PENDING_MAX_ATTEMPTS)
+                .max(1),
+            // e.g. `state.dead_letter.jsonl` next to `state.json`
+            dead_letter_file: self.dead_letter_file.clone().or_else(|| {
+                self.state_file
+                    .as_ref()
+                    .map(|path| path.with_extension("dead_letter.jsonl"))
+            }),
+            schedule: Schedule::parse(self.interval_secs, self.cron.as_deref(), timezone)?,
+            url_intervals,
+            retry,
+            per_message_delay: self
+                .per_message_delay_ms
+                .map(Duration::from_millis)
+                .unwrap_or(DEFAULT_PER_MESSAGE_DELAY),
+            update_sent_messages: self.update_sent_messages,
+            notify_updates: self.notify_updates,
+            quiet_hours: self
+                .quiet_hours
+                .as_deref()
+                .map(|spec| QuietHours::parse(spec, timezone, self.quiet_hours_mode))
+                .transpose()?,
+            timezone,
+            dedup_key: self.dedup_key,
+            seen_store,
+            metrics_addr: self
+                .metrics_addr
+                .as_deref()
+                .map(|addr| {
+                    addr.parse()
+                        .map_err(|e| format!("invalid `metrics_addr` {:?}: {}", addr, e))
+                })
+                .transpose()?,
+            report_webhook: self.report_webhook.clone().map(|url| ReportWebhook {
+                url,
+                on_failure_only: self.report_on_failure_only,
+            }),
+            http_client: build_http_client(&http, None)?,
         })
     }
 }
 
+// the fetch urls of the config first, then the ones no longer in it
+fn print_status(args: &Args, config: &Config, state: &State) {
+    let mut urls = config
+        .fetch_urls
+        .iter()
+        .map(|url| url.to_string())
+        .collect::<Vec<_>>();
+    for url in state.fetch_history.keys() {
+        if !urls.contains(url) {
+            urls.push(url.clone());
+        }
+    }
+    match args.format {
+        Format::Text => {
+            for url in urls.iter() {
+                output::print_fetch_history(url, state.fetch_history.get(url), config.timezone);
+            }
+            output::print_pending(&state.pending, config.pending_max_attempts);
+        }
+        Format::Json => {
+            let history = urls
+                .iter()
+                .map(|url| (url.clone(), state.fetch_history.get(url)))
+                .collect::<BTreeMap<_, _>>();
+            println!(
+                "{}",
+                serde_json::json!({"fetch_history": history, "pending": state.pending})
+            );
+        }
+    }
+}
+
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let config = serde_json::from_str::<RawConfig>(env::var("CONFIG")?.as_str())?.parse()?;
-    dbg!(&config);
-
-    for url in config.fetch_urls.iter() {
-        let text = reqwest::get(url.to_string()).await?.text().await?;
-        let kakomon;
-        match extract_kakomon(&text, url.clone()) {
-            Some(kako) => kakomon = kako,
-            _ => continue,
-        }
-
-        let body = object! {
-            "blocks": [
-                {
-                    "type": "header",
-                    "text": {
-                        "type": "plain_text",
-                        "text": kakomon.title.clone(),
-                        "emoji": true
-                    }
-                },
-                {
-                    "type": "divider",
-                },
-                {
-                    "type": "section",
-                    "text": {
-                        "type": "mrkdwn",
-                        "text": kakomon.text.clone(),
-                    }
+async fn main() -> ExitCode {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let args = Args::parse();
+    if args.no_color {
+        output::disable_colors();
+    }
+
+    let (mut config, mut state) = match load_config(Profile::of(&args).as_ref()) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            error!("failed to load the config: {}", e);
+            return ExitCode::from(EXIT_CONFIG_ERROR);
+        }
+    };
+
+    if matches!(args.command, Some(Command::Status)) {
+        if config.state_file.is_none() {
+            error!("`status` needs `state_file` in CONFIG");
+            return ExitCode::from(EXIT_CONFIG_ERROR);
+        }
+        print_status(&args, &config, &state);
+        return ExitCode::from(EXIT_SUCCESS);
+    }
+
+    if let Some(Command::Replay {
+        input,
+        webhook,
+        force,
+    }) = &args.command
+    {
+        if let Some(webhook) = webhook {
+            match Destination::from_url(webhook.clone()) {
+                Ok(destination) => {
+                    config.destinations = vec![Target {
+                        destination,
+                        template: None,
+                        retry: config.retry,
+                        breaker: None,
+                    }];
+                    config.category_routes.clear();
                 }
-            ]
+                Err(e) => {
+                    error!("invalid --webhook {}: {}", webhook, e);
+                    return ExitCode::from(EXIT_CONFIG_ERROR);
+                }
+            }
+        }
+        let questions = match load_questions(input) {
+            Ok(questions) => questions,
+            Err(e) => {
+                error!(
+                    "failed to load the questions from {}: {}",
+                    input.display(),
+                    e
+                );
+                return ExitCode::from(EXIT_CONFIG_ERROR);
+            }
         };
+        let stats = replay(&args, &config, &mut state, input, &questions, *force).await;
+        return ExitCode::from(stats.exit_code());
+    }
 
-        // send to webhook urls.
-        send_to_slack_webhook(&config.webhook_url, body.to_string()).await?;
+    if !config.duplicate_fetch_urls.is_empty() {
+        for url in config.duplicate_fetch_urls.iter() {
+            warn!("{} is in `fetch_urls` more than once", url);
+        }
+        if args.strict {
+            error!("`fetch_urls` has duplicates, which is an error with --strict");
+            return ExitCode::from(EXIT_CONFIG_ERROR);
+        }
+        info!(
+            "{} fetch urls after removing {} duplicates",
+            config.fetch_urls.len(),
+            config.duplicate_fetch_urls.len()
+        );
+    }
+    if config.disabled_destinations > 0 {
+        info!(
+            "{} destinations are disabled, sending to the other {}",
+            config.disabled_destinations,
+            config.destinations.len()
+        );
+        if config.destinations.is_empty() {
+            warn!("all the destinations are disabled, nothing is sent");
+        }
+    }
+    let fetcher = match ReqwestFetcher::new(&config.fetch, &config.fetch_urls) {
+        Ok(fetcher) => fetcher,
+        Err(e) => {
+            error!("failed to build the http client: {}", e);
+            return ExitCode::from(EXIT_CONFIG_ERROR);
+        }
+    };
+    if !args.html_file.is_empty() {
+        let files = match html_files(&args) {
+            Ok(files) => files,
+            Err(e) => {
+                error!("{}", e);
+                return ExitCode::from(EXIT_CONFIG_ERROR);
+            }
+        };
+        // the files are not cached as the pages of the urls
+        config.html_cache = None;
+        config.memory_cache = None;
+        let urls = files.iter().map(|(url, _)| url.clone()).collect::<Vec<_>>();
+        let fetcher = FileFetcher::new(files, &fetcher);
+        let stats = run_urls(&fetcher, &args, &config, &mut state, &urls).await;
+        return ExitCode::from(run_exit_code(&args, &stats));
     }
 
-    Ok(())
-}
+    if config.fetch_urls.is_empty() {
+        if !args.allow_empty {
+            error!("`fetch_urls` is empty, use --allow-empty to run anyway");
+            return ExitCode::from(EXIT_CONFIG_ERROR);
+        }
+        warn!("`fetch_urls` is empty, nothing is fetched");
+    }
 
-async fn send_to_slack_webhook(
-    webhook: &Url,
-    body: String,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let client = reqwest::Client::new();
-    let res = client
-        .post(webhook.to_string())
-        .header("Content-type", "application/json")
-        .body(body)
-        .send()
-        .await?;
-    debug!("{:?}", res.status());
+    // a configured schedule makes it a daemon unless `--once` or a subcommand is given
+    let daemon = args.command.is_none() && !args.once && (args.daemon || config.schedule.is_some());
+    if !daemon {
+        let stats = run(&fetcher, &args, &config, &mut state).await;
+        return ExitCode::from(run_exit_code(&args, &stats));
+    }
 
-    Ok(())
+    let schedule = match &config.schedule {
+        Some(schedule) => schedule,
+        None => {
+            error!("--daemon needs `interval_secs` or `cron` in CONFIG");
+            return ExitCode::from(EXIT_CONFIG_ERROR);
+        }
+    };
+    let metrics = Arc::new(Mutex::new(Metrics::default()));
+    if let Some(addr) = config.metrics_addr {
+        tokio::spawn(metrics::serve(addr, metrics.clone()));
+    }
+    // every fetch url is fetched on the start, and then on its own timer
+    let mut next_runs = BTreeMap::new();
+    loop {
+        let due = due_fetch_urls(&config, &next_runs, Utc::now());
+        // failures are counted in the stats and the daemon keeps running
+        let stats = run_urls(&fetcher, &args, &config, &mut state, &due).await;
+        metrics.lock().unwrap().record(&stats, Utc::now());
+        let now = Utc::now();
+        for url in due.iter() {
+            let next = next_run_of(&config, schedule, url, now);
+            state.record_next_run(url, next);
+            next_runs.insert(url.clone(), next);
+        }
+        if let Some(path) = &config.state_file {
+            if let Err(e) = state.save(path, config.state_compress) {
+                error!("failed to save the state to {}: {}", path.display(), e);
+            }
+        }
+        let mut wait = match next_runs.values().min() {
+            Some(next) => (*next - now).to_std().unwrap_or(Duration::ZERO),
+            // nothing to fetch, only the pending questions to send
+            None => schedule.wait_from(now),
+        };
+        // wake up when the quiet hours end to send the held questions
+        if let Some(quiet_hours) = config
+            .quiet_hours
+            .as_ref()
+            .filter(|_| !state.held.is_empty())
+        {
+            wait = wait.min(quiet_hours.wait_until_end(now));
+        }
+        let next = (now + wait).with_timezone(&config.timezone);
+        info!("next run at {}", next.format("%Y-%m-%d %H:%M (%Z)"));
+        tokio::time::sleep(wait).await;
+    }
 }
 
-#[derive(Debug, Clone)]
-struct Kakomon {
-    title: String,
-    text: String,
+// the fetch urls whose timers are up, or not started yet
+fn due_fetch_urls(
+    config: &Config,
+    next_runs: &BTreeMap<Url, DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> Vec<Url> {
+    config
+        .fetch_urls
+        .iter()
+        .filter(|url| next_runs.get(*url).is_none_or(|next| *next <= now))
+        .cloned()
+        .collect()
 }
 
-// get the first div element having "kako" class
-fn extract_kakomon(html_text: &str, url: Url) -> Option<Kakomon> {
-    let document = Html::parse_document(html_text);
-    for element in document.select(&Selector::parse(r#"div"#).unwrap()) {
-        let mut title = String::new();
-        let mut text = String::new();
+// after `interval_secs` of the fetch url if it has one, or on the schedule of all of them
+fn next_run_of(
+    config: &Config,
+    schedule: &Schedule,
+    url: &Url,
+    now: DateTime<Utc>,
+) -> DateTime<Utc> {
+    let wait = config
+        .url_intervals
+        .iter()
+        .find(|(interval_url, _)| interval_url == url)
+        .map(|(_, interval)| *interval)
+        .unwrap_or_else(|| schedule.wait_from(now));
+    now + wait
+}
 
-        if element.value().attr("class") == Some("kako") {
-            // get the url to the answer page
-            for elem2 in element.select(&Selector::parse(r#"a"#).unwrap()) {
-                let href = elem2.value().attr("href").unwrap();
-                match Url::parse(href) {
-                    Ok(url) => {
-                        text += url.to_string().as_str();
-                    }
-                    Err(_) => {
-                        text += url.join(href).unwrap().to_string().as_str();
+// Merges the objects of `overrides` into the ones of `base` key by key.
+// Any other value of `overrides`, including an array, replaces the one of `base`.
+fn merge_json(base: &mut serde_json::Value, overrides: serde_json::Value) {
+    match (base, overrides) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(&key) {
+                    Some(base) => merge_json(base, value),
+                    None => {
+                        base.insert(key, value);
                     }
                 }
-                text += "\n";
             }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
 
-            // get the problem statement
-            for elem2 in element.select(&Selector::parse(r#"div"#).unwrap()) {
-                match elem2.value().attr("class") {
-                    Some("mondai") => {
-                        text += elem2.text().collect::<Vec<_>>().join("").as_str();
-                        text += "\n";
-                    }
-                    Some("anslink") => {
-                        title += elem2.text().collect::<Vec<_>>().join("").as_str();
-                    }
-                    Some("ansbg") => {
-                        // answer background
-
-                        for (elem3_idx, elem3) in elem2
-                            .select(&Selector::parse(r#"ul > li"#).unwrap())
-                            .enumerate()
-                        {
-                            text += format!("{}. ", elem3_idx + 1).as_str();
-                            text += elem3.text().collect::<Vec<_>>().join("").as_str();
-                            text += "\n";
-                        }
-                    }
-                    _ => {}
-                }
+// the profile of `profiles` chosen by `--profile` or `APP_ENV`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Profile {
+    name: String,
+    // `APP_ENV` may be set for other tools, so it is ignored unless it is in `profiles`
+    from_env: bool,
+}
+
+impl Profile {
+    fn of(args: &Args) -> Option<Self> {
+        match &args.profile {
+            Some(name) => Some(Profile {
+                name: name.clone(),
+                from_env: false,
+            }),
+            None => env::var("APP_ENV")
+                .ok()
+                .filter(|name| !name.is_empty())
+                .map(|name| Profile {
+                    name,
+                    from_env: true,
+                }),
+        }
+    }
+}
+
+// The table of the profile in `profiles` merged into the top level, which is used as it is without a profile.
+fn with_profile(
+    mut config: serde_json::Value,
+    profile: Option<&Profile>,
+) -> Result<serde_json::Value, String> {
+    let profiles = config
+        .as_object_mut()
+        .and_then(|config| config.remove("profiles"));
+    let profile = match profile {
+        Some(profile) => profile,
+        None => return Ok(config),
+    };
+    match profiles
+        .as_ref()
+        .and_then(|profiles| profiles.get(&profile.name))
+    {
+        Some(overrides) => {
+            info!("use the profile `{}`", profile.name);
+            merge_json(&mut config, overrides.clone());
+        }
+        None if profile.from_env => {
+            warn!(
+                "APP_ENV `{}` is not in `profiles`, using the top level config",
+                profile.name
+            );
+        }
+        None => {
+            return Err(format!(
+                "the profile `{}` is not in `profiles`",
+                profile.name
+            ))
+        }
+    }
+    Ok(config)
+}
+
+// `CONFIG_OVERRIDE` merged into `CONFIG`, e.g. the base config and the one of an environment
+fn merge_config(
+    mut base: serde_json::Value,
+    overrides: serde_json::Value,
+    profile: Option<&Profile>,
+) -> Result<RawConfig, Box<dyn std::error::Error>> {
+    merge_json(&mut base, overrides);
+    let config = with_profile(base, profile)?;
+    Ok(serde_json::from_value(with_includes(
+        config,
+        Path::new("."),
+        &mut Vec::new(),
+    )?)?)
+}
+
+// The `fetch_urls` of the files in `include` appended to the ones of the config, the includes of them as well.
+// The paths are relative to the file including them, or to the working directory in `CONFIG`.
+// `chain` is the files including the config, to find a circular include.
+fn with_includes(
+    mut config: serde_json::Value,
+    dir: &Path,
+    chain: &mut Vec<PathBuf>,
+) -> Result<serde_json::Value, String> {
+    let not_paths = || "`include` must be an array of the paths".to_string();
+    let includes = match config
+        .as_object_mut()
+        .and_then(|config| config.remove("include"))
+    {
+        None => return Ok(config),
+        Some(serde_json::Value::Array(includes)) => includes,
+        Some(_) => return Err(not_paths()),
+    };
+    for include in includes.iter() {
+        let path = dir.join(include.as_str().ok_or_else(not_paths)?);
+        let path = path
+            .canonicalize()
+            .map_err(|e| format!("failed to read the include {}: {}", path.display(), e))?;
+        if chain.contains(&path) {
+            let files = chain
+                .iter()
+                .chain(std::iter::once(&path))
+                .map(|file| file.display().to_string())
+                .collect::<Vec<_>>();
+            return Err(format!("circular include: {}", files.join(" -> ")));
+        }
+        let invalid =
+            |e: &dyn std::fmt::Display| format!("invalid include {}: {}", path.display(), e);
+        let text = std::fs::read_to_string(&path).map_err(|e| invalid(&e))?;
+        let included = parse_config_value(&text).map_err(|e| invalid(&e))?;
+        chain.push(path.clone());
+        let included = with_includes(included, path.parent().unwrap_or(dir), chain)?;
+        chain.pop();
+
+        let object = included.as_object().cloned().unwrap_or_default();
+        if object.keys().any(|key| key != "fetch_urls") {
+            warn!(
+                "only `fetch_urls` is taken from the include {}",
+                path.display()
+            );
+        }
+        let urls = match object.get("fetch_urls") {
+            Some(serde_json::Value::Array(urls)) => urls.clone(),
+            None => Vec::new(),
+            Some(_) => return Err(invalid(&"`fetch_urls` is not an array")),
+        };
+        info!("included {} fetch urls from {}", urls.len(), path.display());
+        if let Some(config) = config.as_object_mut() {
+            match config
+                .entry("fetch_urls")
+                .or_insert_with(|| serde_json::Value::Array(Vec::new()))
+            {
+                serde_json::Value::Array(fetch_urls) => fetch_urls.extend(urls),
+                _ => return Err("`fetch_urls` is not an array".to_string()),
             }
+        }
+    }
+    Ok(config)
+}
 
-            // get urls of images
-            for elem2 in element.select(&Selector::parse(r#"img"#).unwrap()) {
-                let href = elem2.value().attr("src").unwrap();
-                match Url::parse(href) {
-                    Ok(url) => {
-                        text += url.to_string().as_str();
-                    }
-                    Err(_) => {
-                        text += url.join(href).unwrap().to_string().as_str();
-                    }
+// The config in json, toml or yaml. The format guessed from the text is tried first and then the others,
+// and the errors of all of them are returned if none gives a table.
+fn parse_config_value(text: &str) -> Result<serde_json::Value, String> {
+    type Parse = fn(&str) -> Result<serde_json::Value, String>;
+    let json: (&str, Parse) = ("json", |text| {
+        serde_json::from_str(text).map_err(|e| e.to_string())
+    });
+    let toml: (&str, Parse) = ("toml", |text| {
+        toml::from_str(text).map_err(|e| e.to_string())
+    });
+    let yaml: (&str, Parse) = ("yaml", |text| {
+        serde_yaml::from_str(text).map_err(|e| e.to_string())
+    });
+    let first_line = text
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .unwrap_or_default();
+    let formats = if first_line.starts_with('{') {
+        [json, yaml, toml]
+    } else if first_line.starts_with('[')
+        // `key = value`, where yaml has `key: value`
+        || first_line.split_once('=').is_some_and(|(key, _)| {
+            let key = key.trim();
+            !key.is_empty()
+                && key
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "_-.\"".contains(c))
+        })
+    {
+        [toml, yaml, json]
+    } else {
+        [yaml, toml, json]
+    };
+    let mut errors = Vec::new();
+    for (name, parse) in formats {
+        match parse(text) {
+            Ok(value) if value.is_object() => return Ok(value),
+            Ok(_) => errors.push(format!("{}: not a table", name)),
+            Err(e) => errors.push(format!("{}: {}", name, e.trim_end())),
+        }
+    }
+    Err(format!("not json, toml nor yaml ({})", errors.join("; ")))
+}
+
+// a whole config in any of the formats of `parse_config_value`
+fn parse_config_str(
+    text: &str,
+    profile: Option<&Profile>,
+) -> Result<RawConfig, Box<dyn std::error::Error>> {
+    let config = with_profile(parse_config_value(text)?, profile)?;
+    Ok(serde_json::from_value(with_includes(
+        config,
+        Path::new("."),
+        &mut Vec::new(),
+    )?)?)
+}
+
+// either `CONFIG` or `CONFIG_OVERRIDE` may be missing, but not both
+fn read_config(profile: Option<&Profile>) -> Result<RawConfig, Box<dyn std::error::Error>> {
+    let read = |name: &str| -> Result<Option<String>, Box<dyn std::error::Error>> {
+        match env::var(name) {
+            Ok(text) => Ok(Some(text)),
+            Err(env::VarError::NotPresent) => Ok(None),
+            Err(e) => Err(format!("invalid `{}`: {}", name, e).into()),
+        }
+    };
+    let invalid = |name: &str, e: &dyn std::fmt::Display| format!("invalid `{}`: {}", name, e);
+    match (read("CONFIG")?, read("CONFIG_OVERRIDE")?) {
+        (None, None) => Err("set `CONFIG` or `CONFIG_OVERRIDE`".into()),
+        (Some(text), None) => {
+            Ok(parse_config_str(&text, profile).map_err(|e| invalid("CONFIG", &e))?)
+        }
+        (None, Some(text)) => {
+            Ok(parse_config_str(&text, profile).map_err(|e| invalid("CONFIG_OVERRIDE", &e))?)
+        }
+        (Some(base), Some(overrides)) => {
+            let base = parse_config_value(&base).map_err(|e| invalid("CONFIG", &e))?;
+            let overrides =
+                parse_config_value(&overrides).map_err(|e| invalid("CONFIG_OVERRIDE", &e))?;
+            Ok(merge_config(base, overrides, profile)?)
+        }
+    }
+}
+
+fn load_config(profile: Option<&Profile>) -> Result<(Config, State), Box<dyn std::error::Error>> {
+    let config = read_config(profile)?.parse()?;
+    debug!("{:?}", config);
+
+    let mut state = match &config.state_file {
+        Some(path) => State::load(path)?,
+        None => State::default(),
+    };
+    state.migrate_keys();
+    state.use_seen_store(config.seen_store);
+    Ok((config, state))
+}
+
+// fetch all the urls and send the new questions
+async fn run<F: Fetcher>(fetcher: &F, args: &Args, config: &Config, state: &mut State) -> RunStats {
+    run_urls(fetcher, args, config, state, &config.fetch_urls).await
+}
+
+// what the sends of a run share besides the state and the stats
+struct Sends {
+    // the keys of the questions of the run, which are not sent twice even without `state_file`
+    run_keys: Mutex<HashSet<String>>,
+    confirm: Mutex<Confirm>,
+}
+
+// a page from the fetch stage to the parse stage
+struct Fetched {
+    url: Url,
+    text: Result<String, Box<dyn std::error::Error>>,
+    fetched_at: DateTime<Utc>,
+}
+
+// fetch some of `fetch_urls`, e.g. the ones due in the daemon. the pending and held questions are sent anyway.
+async fn run_urls<F: Fetcher>(
+    fetcher: &F,
+    args: &Args,
+    config: &Config,
+    state: &mut State,
+    urls: &[Url],
+) -> RunStats {
+    let fetch_urls = expand_fetch_urls(fetcher, args, config, urls).await;
+    let fetch_urls = limit_urls(fetch_urls, args.offset, args.limit);
+    let confirm = Confirm::new(args.interactive && !args.dry_run);
+    // the bar would be drawn over the prompt
+    let progress = output::progress_bar(fetch_urls.len(), args.quiet || confirm.is_asking());
+    for target in config.all_targets() {
+        target.destination.start_run();
+    }
+    let run = SharedRun::new(state, RunStats::default());
+    let sends = Sends {
+        run_keys: Mutex::new(HashSet::new()),
+        confirm: Mutex::new(confirm),
+    };
+    send_pending(config, &run, &sends).await;
+    send_held(config, &run, &sends).await;
+
+    let (run, sends, progress) = (&run, &sends, &progress);
+    let (page_tx, page_rx) = mpsc::channel::<Fetched>(config.pipeline.buffer);
+    let (question_tx, question_rx) = mpsc::channel::<QueuedQuestion>(config.pipeline.buffer);
+    let fetch_stage = async move {
+        // in the order of the urls however many are fetched at the same time
+        let mut pages = futures_util::stream::iter(fetch_urls.iter())
+            .map(|url| async move {
+                progress.set_message(url.to_string());
+                let text = fetch_html(fetcher, url, config, !args.no_cache).await;
+                Fetched {
+                    url: url.clone(),
+                    text,
+                    fetched_at: Utc::now(),
                 }
-                text += "\n";
+            })
+            .buffered(config.pipeline.fetch_concurrency);
+        while let Some(fetched) = pages.next().await {
+            // waits while the parse stage is behind
+            if page_tx.send(fetched).await.is_err() {
+                break;
             }
-            return Some(Kakomon {
-                title: title,
-                text: text,
-            });
         }
+    };
+    let parse_stage = async move {
+        // in the order of the pages however many are parsed at the same time
+        let mut questions = received(page_rx)
+            .map(|fetched| async move {
+                let question = process_url(fetcher, args, config, run, fetched, progress).await;
+                progress.inc(1);
+                question
+            })
+            .buffered(config.pipeline.parse_concurrency);
+        while let Some(question) = questions.next().await {
+            if let Some(question) = question {
+                if question_tx.send(question).await.is_err() {
+                    break;
+                }
+            }
+        }
+    };
+    let send_stage = async move {
+        let questions = received(question_rx);
+        // sent after all the urls are fetched
+        if args.sort {
+            return questions.collect::<Vec<_>>().await;
+        }
+        questions
+            .for_each_concurrent(config.pipeline.send_concurrency, |question| async move {
+                send_question(config, run, sends, &question, false).await;
+            })
+            .await;
+        Vec::new()
+    };
+    let ((), (), mut batch) = tokio::join!(fetch_stage, parse_stage, send_stage);
+    progress.finish_and_clear();
+
+    let filtering = config.exam_filter.is_active() || config.language_filter.is_active();
+    let (extracted, filtered) = run.with(|_, stats| (stats.extracted, stats.filtered));
+    if filtering && extracted > 0 && filtered == extracted {
+        warn!(
+            "none of the {} questions is of `filter_years`, `filter_seasons` and `filter_languages`",
+            extracted
+        );
+    }
+    sort_questions(&mut batch);
+    for question in batch.iter() {
+        send_question(config, run, sends, question, true).await;
     }
 
-    None
+    let stats = run.with(|_, stats| std::mem::take(stats));
+    report(args, config, &stats).await;
+    stats
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+// the items sent to the channel as a stream
+fn received<T>(mut rx: mpsc::Receiver<T>) -> impl futures_util::Stream<Item = T> {
+    futures_util::stream::poll_fn(move |cx| rx.poll_recv(cx))
+}
+
+// `--html-file` paired with `--source-url`
+fn html_files(args: &Args) -> Result<Vec<(Url, PathBuf)>, String> {
+    if args.html_file.len() != args.source_url.len() {
+        return Err(format!(
+            "{} --html-file but {} --source-url, every file needs its url",
+            args.html_file.len(),
+            args.source_url.len()
+        ));
+    }
+    let mut files: Vec<(Url, PathBuf)> = Vec::new();
+    for (url, path) in args.source_url.iter().zip(args.html_file.iter()) {
+        if files.iter().any(|(other, _)| other == url) {
+            return Err(format!("--source-url {} is given more than once", url));
+        }
+        files.push((url.clone(), path.clone()));
+    }
+    Ok(files)
+}
+
+// an array of the questions or a single one, as saved from `Kakomon`
+fn load_questions(path: &std::path::Path) -> Result<Vec<Kakomon>, Box<dyn std::error::Error>> {
+    let json = std::fs::read_to_string(path)?;
+    match serde_json::from_str::<Vec<Kakomon>>(&json) {
+        Ok(questions) => Ok(questions),
+        Err(_) => Ok(vec![serde_json::from_str::<Kakomon>(&json)?]),
+    }
+}
+
+// Sends the saved questions like the fetched ones. The state is updated unless `--dry-run`.
+async fn replay(
+    args: &Args,
+    config: &Config,
+    state: &mut State,
+    input: &std::path::Path,
+    questions: &[Kakomon],
+    force: bool,
+) -> RunStats {
+    // the failures of the questions without their urls are reported with the input file
+    let input_url = std::fs::canonicalize(input)
+        .ok()
+        .and_then(|path| Url::from_file_path(path).ok())
+        .unwrap_or_else(|| Url::parse("file:///").unwrap());
+    for target in config.all_targets() {
+        target.destination.start_run();
+    }
+    let mut confirm = Confirm::new(args.interactive && !args.dry_run);
+    let run = SharedRun::new(state, RunStats::default());
+    for kakomon in questions.iter() {
+        let url = kakomon.answer_url.as_ref().unwrap_or(&input_url);
+        let key = config.dedup_key.of(kakomon);
+        let seen =
+            !force && config.state_file.is_some() && run.with(|state, _| state.is_seen(&key));
+        if seen && !config.update_sent_messages {
+            info!("{} has already been sent", kakomon.key());
+            continue;
+        }
+        if args.dry_run {
+            output::print_kakomon(kakomon);
+            continue;
+        }
+        if !confirm.approve(|| preview(config, url, kakomon, seen, None)) {
+            info!("{} is not sent as answered", kakomon.key());
+            continue;
+        }
+        // not queued, as the same file can be replayed again
+        let _ = deliver(config, &run, url, kakomon, seen, None, false).await;
+    }
+
+    let stats = run.into_stats();
+    report(args, config, &stats).await;
+    stats
+}
+
+async fn report(args: &Args, config: &Config, stats: &RunStats) {
+    match args.format {
+        Format::Text => print!("{}", stats.summary_text()),
+        Format::Json => println!("{}", stats.summary_json()),
+    }
+    let kinds = config
+        .destinations
+        .iter()
+        .map(|target| target.destination.kind())
+        .collect::<Vec<_>>();
+    github::report(stats, &kinds);
+    // nothing is sent in a dry run
+    if let Some(webhook) = config.report_webhook.as_ref().filter(|_| !args.dry_run) {
+        post_report(&config.http_client, webhook, stats).await;
+    }
+}
+
+// the exit code of a run, failing on the extraction failures with `--fail-on-extract-error`
+fn run_exit_code(args: &Args, stats: &RunStats) -> u8 {
+    match stats.exit_code() {
+        EXIT_SUCCESS if args.fail_on_extract_error && !stats.extract_failures.is_empty() => {
+            EXIT_FAILURE
+        }
+        code => code,
+    }
+}
+
+// `--offset` and `--limit`, which apply to the urls rather than the questions sent
+fn limit_urls(urls: Vec<Url>, offset: usize, limit: Option<usize>) -> Vec<Url> {
+    if offset == 0 && limit.is_none() {
+        return urls;
+    }
+    let total = urls.len();
+    let urls = urls
+        .into_iter()
+        .skip(offset)
+        .take(limit.unwrap_or(usize::MAX))
+        .collect::<Vec<_>>();
+    info!("fetch {} of the {} urls", urls.len(), total);
+    urls
+}
+
+// The templates in `fetch_urls` are expanded on every run to follow the new exams.
+async fn expand_fetch_urls<F: Fetcher>(
+    fetcher: &F,
+    args: &Args,
+    config: &Config,
+    urls: &[Url],
+) -> Vec<Url> {
+    let mut fetch_urls = Vec::new();
+    for url in urls.iter() {
+        let listing = match listing_url(url).filter(|_| is_url_template(url)) {
+            Some(listing) => listing,
+            None => {
+                fetch_urls.push(url.clone());
+                continue;
+            }
+        };
+        let expanded = match fetch_html(fetcher, &listing, config, !args.no_cache).await {
+            Ok(html) => expand_url_template(url, &html),
+            Err(e) => {
+                warn!("failed to fetch {} to expand {}: {}", listing, url, e);
+                vec![url.clone()]
+            }
+        };
+        info!("{} is expanded to {} urls", url, expanded.len());
+        for expanded_url in expanded {
+            debug!("  {}", expanded_url);
+            if !fetch_urls.contains(&expanded_url) {
+                fetch_urls.push(expanded_url);
+            }
+        }
+    }
+    fetch_urls
+}
+
+// pages taking longer than this to parse are skipped unless `parse_timeout_secs` is configured
+const DEFAULT_PARSE_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Parse a page on a blocking thread not to hold up the other stages, with or without `timeout`.
+// None if it takes longer than `timeout`, though the parse cannot be stopped and keeps the
+// blocking thread until it finishes by itself.
+async fn with_parse_timeout<T: Send + 'static>(
+    timeout: Option<Duration>,
+    parse: impl FnOnce() -> T + Send + 'static,
+) -> Option<T> {
+    let parsing = tokio::task::spawn_blocking(parse);
+    let parsed = match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, parsing).await.ok()?,
+        None => parsing.await,
+    };
+    match parsed {
+        Ok(parsed) => Some(parsed),
+        // a panic of the parser, as it would be without the thread
+        Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+        // cancelled on the shutdown of the runtime
+        Err(_) => None,
+    }
+}
+
+// the question to be sent, if it is new and nothing else is done with it
+async fn process_url<F: Fetcher>(
+    fetcher: &F,
+    args: &Args,
+    config: &Config,
+    run: &SharedRun<'_>,
+    fetched: Fetched,
+    progress: &ProgressBar,
+) -> Option<QueuedQuestion> {
+    let listing = matches!(args.command, Some(Command::List));
+
+    let Fetched {
+        url,
+        text,
+        fetched_at,
+    } = fetched;
+    let url = &url;
+    let text = match text {
+        Ok(text) => text,
+        Err(e) if e.is::<ResponseTooLarge>() => {
+            warn!("{}: skipped, {}", url, e);
+            return None;
+        }
+        Err(e) => {
+            error!("failed to fetch {}: {}", url, e);
+            let reason = describe_error(e.as_ref());
+            run.with(|state, stats| {
+                stats.fetch_failed += 1;
+                stats.add_failure(url, reason.clone());
+                state.record_fetch(url, fetched_at, Err(reason));
+                save_state(config, state, url, stats);
+            });
+            return None;
+        }
+    };
+    run.with(|state, stats| {
+        stats.fetched += 1;
+        state.record_fetch(url, fetched_at, Ok(()));
+        save_state(config, state, url, stats);
+    });
+    if let Err(reason) = validate_response(&text, &config.fetch.check) {
+        warn!("{}: skipped, {}", url, reason);
+        return None;
+    }
+
+    if args.debug_extract {
+        let dump = extract::debug_dump(&text);
+        progress.suspend(|| eprint!("{}:\n{}", url, dump));
+    }
+    let parser = site::parser_for(url);
+    debug!("parse {} with the {} parser", url, parser.name());
+    let options = config.extract_options(url).clone();
+    let page_url = url.clone();
+    let extracted = with_parse_timeout(config.parse_timeout, move || {
+        match parser.extract(&text, page_url.clone(), &options) {
+            Some(kako) => Ok(kako),
+            None => Err(parser.failure_reason(&text, &page_url, &options)),
+        }
+    })
+    .await;
+    let mut kakomon = match extracted {
+        Some(Ok(kako)) => kako,
+        Some(Err(reason)) => {
+            run.with(|_, stats| stats.extract_failures.push((url.clone(), reason)));
+            return None;
+        }
+        None => {
+            let timeout = config.parse_timeout.unwrap_or_default();
+            warn!(
+                "{}: skipped, parsing it took more than {}s",
+                url,
+                timeout.as_secs_f64()
+            );
+            let reason = format!("parse timed out after {}s", timeout.as_secs_f64());
+            run.with(|_, stats| stats.extract_failures.push((url.clone(), reason)));
+            return None;
+        }
+    };
+    debug!("extracted {}", kakomon);
+    // the same content hash as the last run means the same question, and the key decides the duplicates
+    debug!(
+        "{}: content hash {}, {:?} dedup key {}",
+        kakomon.key(),
+        DedupKey::Content.short_of(&kakomon),
+        config.dedup_key,
+        config.dedup_key.short_of(&kakomon)
+    );
+    run.with(|_, stats| stats.extracted += 1);
+    if !config.exam_filter.matches(kakomon.exam_meta().as_ref()) {
+        info!("{} is filtered out by its exam", kakomon.key());
+        run.with(|_, stats| stats.filtered += 1);
+        return None;
+    }
+    if !config.language_filter.matches(kakomon.language.as_deref()) {
+        info!(
+            "{} is filtered out by its language {:?}",
+            kakomon.key(),
+            kakomon.language
+        );
+        run.with(|_, stats| stats.filtered += 1);
+        return None;
+    }
+    if listing {
+        progress.suspend(|| output::print_kakomon(&kakomon));
+        return None;
+    }
+    let (seen, update) = match run.with(|state, _| sending_of(config, state, &kakomon)) {
+        Sending::Skip(reason) => {
+            info!("{} {}", kakomon.key(), reason);
+            return None;
+        }
+        Sending::Send { seen, update } => (seen, update),
+    };
+
+    if config.format.include_explanation {
+        fetch_explanation(fetcher, args, config, &mut kakomon).await;
+    }
+
+    if args.print_body {
+        for (_, body) in bodies(config, url, &kakomon, seen, update.as_deref()).iter() {
+            if let Ok(pretty) = serde_json::to_string_pretty(body) {
+                progress.suspend(|| eprintln!("{}", pretty));
+            }
+        }
+    }
+    if args.dry_run {
+        if !args.print_body {
+            progress.suspend(|| output::print_kakomon(&kakomon));
+        }
+        // what would fail to send
+        for (target, body) in bodies(config, url, &kakomon, seen, update.as_deref()).iter() {
+            if let Err(e) = target.destination.validate(body) {
+                warn!(
+                    "{} would not be sent to {}: {}",
+                    kakomon.key(),
+                    target.destination.kind(),
+                    e
+                );
+            }
+        }
+        return None;
+    }
+
+    // decided once here, as `sending_of` records the contents of the questions
+    let question = QueuedQuestion {
+        url: url.clone(),
+        kakomon,
+        seen,
+        update,
+    };
+    if let Some(quiet_hours) = &config.quiet_hours {
+        if quiet_hours.contains(Utc::now()) {
+            match quiet_hours.mode {
+                // not marked as sent, to be sent on a run after the quiet hours
+                QuietMode::Skip => {
+                    info!("{} is not sent in the quiet hours", question.kakomon.key())
+                }
+                QuietMode::Hold => {
+                    info!(
+                        "{} is held until the quiet hours end",
+                        question.kakomon.key()
+                    );
+                    run.with(|state, _| state.held.push(question));
+                }
+            }
+            return None;
+        }
+    }
+
+    Some(question)
+}
+
+// how a fetched question is sent
+#[derive(Debug, PartialEq, Eq)]
+enum Sending {
+    // not sent, for the reason
+    Skip(&'static str),
+    // `seen` updates the messages sent before with `update_sent_messages`,
+    // and `update` is what changed since it was sent with `notify_updates`
+    Send { seen: bool, update: Option<String> },
+}
+
+// With `notify_updates`, a question sent before is sent again only if its contents changed.
+fn sending_of(config: &Config, state: &mut State, kakomon: &Kakomon) -> Sending {
+    let seen = config.state_file.is_some() && state.is_seen(&config.dedup_key.of(kakomon));
+    if config.notify_updates && config.state_file.is_some() {
+        match state.content_change(kakomon) {
+            ContentChange::Unchanged => return Sending::Skip("has not changed since it was sent"),
+            ContentChange::Updated(parts) => {
+                return Sending::Send {
+                    seen: seen && config.update_sent_messages,
+                    update: Some(parts.join("、")),
+                }
+            }
+            // the ones sent before `notify_updates` are compared from now on
+            ContentChange::New if seen => state.record_content(kakomon),
+            ContentChange::New => {}
+        }
+    }
+    if seen && !config.update_sent_messages {
+        return Sending::Skip("has already been sent");
+    }
+    Sending::Send { seen, update: None }
+}
+
+// The questions without the exam go last, and the ones of the same exam and number
+// stay in the order they were fetched in.
+fn sort_questions(questions: &mut [QueuedQuestion]) {
+    questions.sort_by_key(|question| {
+        let meta = question.kakomon.exam_meta();
+        (meta.is_none(), meta)
+    });
+}
+
+// Send the question unless it has been sent, e.g. by another fetch url of the same run.
+// One failed to send is not sent again in the run either, but on the next run.
+async fn send_question(
+    config: &Config,
+    run: &SharedRun<'_>,
+    sends: &Sends,
+    question: &QueuedQuestion,
+    batch: bool,
+) {
+    let QueuedQuestion {
+        url,
+        kakomon,
+        seen,
+        update,
+    } = question;
+    let (seen, update) = (*seen, update.clone());
+    let key = config.dedup_key.of(kakomon);
+    if !sends.run_keys.lock().unwrap().insert(key.clone()) {
+        info!("{} of {} has been sent in this run", kakomon.key(), url);
+        run.with(|_, stats| stats.duplicates += 1);
+        return;
+    }
+    let approved = sends
+        .confirm
+        .lock()
+        .unwrap()
+        .approve(|| preview(config, url, kakomon, seen, update.as_deref()));
+    if !approved {
+        // not marked as sent, to be asked again when it is fetched next
+        info!("{} is not sent as answered", kakomon.key());
+        return;
+    }
+    if let Err(reason) = deliver(config, run, url, kakomon, seen, update.as_deref(), batch).await {
+        if config.state_file.is_some() {
+            info!("{} is sent again on the next run", kakomon.key());
+            run.with(|state, stats| {
+                state.pending.push(PendingQuestion {
+                    url: url.clone(),
+                    kakomon: kakomon.clone(),
+                    attempts: 1,
+                    last_error: reason,
+                    update,
+                });
+                save_state(config, state, url, stats);
+            });
+        }
+    }
+}
+
+// Send the questions failed to send on the last runs before anything else.
+// One failed `pending_max_attempts` times is given up and moved to `dead_letter_file`.
+async fn send_pending(config: &Config, run: &SharedRun<'_>, sends: &Sends) {
+    let pending = run.with(|state, _| std::mem::take(&mut state.pending));
+    if !pending.is_empty() {
+        info!("send {} questions failed to send before", pending.len());
+    }
+    for mut question in pending {
+        let key = config.dedup_key.of(&question.kakomon);
+        // not found and sent again in this run
+        sends.run_keys.lock().unwrap().insert(key.clone());
+        if run.with(|state, _| state.is_seen(&key)) {
+            continue;
+        }
+        let url = question.url.clone();
+        let update = question.update.clone();
+        match deliver(
+            config,
+            run,
+            &url,
+            &question.kakomon,
+            false,
+            update.as_deref(),
+            true,
+        )
+        .await
+        {
+            Ok(()) => info!(
+                "sent {} on the attempt {}",
+                question.kakomon.key(),
+                question.attempts + 1
+            ),
+            Err(reason) => {
+                question.attempts += 1;
+                question.last_error = reason;
+                run.with(|state, stats| {
+                    if question.attempts < config.pending_max_attempts {
+                        state.pending.push(question);
+                    } else {
+                        give_up(config, &question, stats);
+                    }
+                });
+            }
+        }
+        run.with(|state, stats| save_state(config, state, &url, stats));
+    }
+}
+
+// append the question to `dead_letter_file` as a json line, to be sent by hand e.g. with `replay`
+fn give_up(config: &Config, question: &PendingQuestion, stats: &mut RunStats) {
+    error!(
+        "gave up sending {} after {} attempts: {}",
+        question.kakomon.key(),
+        question.attempts,
+        question.last_error
+    );
+    let path = match &config.dead_letter_file {
+        Some(path) => path,
+        None => return,
+    };
+    let appended = serde_json::to_string(question)
+        .map_err(Into::into)
+        .and_then(|json| -> Result<(), Box<dyn std::error::Error>> {
+            let mut file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?;
+            writeln!(file, "{}", json)?;
+            Ok(())
+        });
+    if let Err(e) = appended {
+        error!("failed to write to {}: {}", path.display(), e);
+        stats.errors += 1;
+    }
+}
+
+// send the held questions together once the quiet hours are over
+async fn send_held(config: &Config, run: &SharedRun<'_>, sends: &Sends) {
+    if config
+        .quiet_hours
+        .as_ref()
+        .is_some_and(|quiet_hours| quiet_hours.contains(Utc::now()))
+    {
+        return;
+    }
+    let held = run.with(|state, _| std::mem::take(&mut state.held));
+    if !held.is_empty() {
+        info!("send {} questions held in the quiet hours", held.len());
+    }
+    for question in held.iter() {
+        send_question(config, run, sends, question, true).await;
+    }
+}
+
+// `batch` is for the questions sent together, the sorted, held and pending ones, which do not wait
+// `per_message_delay` between them
+async fn deliver(
+    config: &Config,
+    run: &SharedRun<'_>,
+    url: &Url,
+    kakomon: &Kakomon,
+    seen: bool,
+    update: Option<&str>,
+    batch: bool,
+) -> Result<(), String> {
+    let key = config.dedup_key.of(kakomon);
+    let question = Question {
+        kakomon,
+        fetch_url: url,
+        key: &key,
+        seen,
+    };
+
+    // once for a question, however many destinations it is sent to
+    if !batch && run.with(|_, stats| stats.sent + stats.send_failed > 0) {
+        tokio::time::sleep(config.per_message_delay).await;
+    }
+    // send to webhook urls.
+    let mut delivered = false;
+    let mut failure = None;
+    for (target, body) in bodies(config, url, kakomon, seen, update).iter() {
+        match target
+            .dispatch(&config.http_client, &question, body, run)
+            .await
+        {
+            Ok(_) => delivered = true,
+            Err(reason) => failure = Some(reason),
+        }
+    }
+    // not sent again to the destinations which got it, even if the others failed
+    if delivered {
+        run.with(|state, stats| mark_seen(config, state, url, kakomon, stats));
+    }
+    match failure {
+        Some(reason) if !delivered => Err(reason),
+        _ => Ok(()),
+    }
+}
+
+// the question and the bodies sent to each destination, shown by `--interactive`
+fn preview(
+    config: &Config,
+    url: &Url,
+    kakomon: &Kakomon,
+    seen: bool,
+    update: Option<&str>,
+) -> String {
+    let mut text = format!("{}\n", kakomon);
+    for (target, body) in bodies(config, url, kakomon, seen, update).iter() {
+        let pretty = serde_json::to_string_pretty(body).unwrap_or_default();
+        text += &format!("{}:\n{}\n", target.destination.kind(), pretty);
+    }
+    text
+}
+
+fn bodies<'a>(
+    config: &'a Config,
+    url: &Url,
+    kakomon: &Kakomon,
+    seen: bool,
+    update: Option<&str>,
+) -> Vec<(&'a Target, serde_json::Value)> {
+    let mut kakomon = Cow::Borrowed(kakomon);
+    if let Some(max_chars) = config.format.summary_max_chars {
+        match format::summarize(&kakomon, max_chars) {
+            Some(summarized) => {
+                info!("{}: summarized the statement", kakomon.key());
+                kakomon = Cow::Owned(summarized);
+            }
+            None => debug!("{}: the statement is sent as it is", kakomon.key()),
+        }
+    }
+    let mut notes = Vec::new();
+    if let Some((limited, note)) = format::limit_lines(&kakomon, &config.format) {
+        info!("{}: {}", kakomon.key(), note);
+        kakomon = Cow::Owned(limited);
+        notes.push(note);
+    }
+    if let Some(update) = update {
+        let title = format!("(更新) {}", kakomon.title);
+        kakomon.to_mut().title = title;
+        notes.push(format!("更新: {}", update));
+    }
+    let note = (!notes.is_empty()).then(|| notes.join("\n"));
+    let kakomon = kakomon.as_ref();
+    config
+        .targets_of(kakomon)
+        .iter()
+        // only the messages which can be updated are sent again, but for an update
+        // with `notify_updates`, which the others get as a new message
+        .filter(|target| !seen || update.is_some() || target.destination.can_update())
+        .map(|target| {
+            let body = target.body(
+                &config.templates,
+                kakomon,
+                url,
+                &config.format,
+                note.as_deref(),
+            );
+            (target, body)
+        })
+        .collect()
+}
+
+fn mark_seen(
+    config: &Config,
+    state: &mut State,
+    url: &Url,
+    kakomon: &Kakomon,
+    stats: &mut RunStats,
+) {
+    if config.state_file.is_some() {
+        state.insert_seen(config.dedup_key.of(kakomon));
+        if config.notify_updates {
+            state.record_content(kakomon);
+        }
+        save_state(config, state, url, stats);
+    }
+}
+
+fn save_state(config: &Config, state: &State, url: &Url, stats: &mut RunStats) {
+    if let Some(path) = &config.state_file {
+        if let Err(e) = state.save(path, config.state_compress) {
+            error!("failed to save the state to {}: {}", path.display(), e);
+            stats.errors += 1;
+            stats.add_failure(url, format!("failed to save the state: {}", e));
+        }
+    }
+}
+
+// fetch the explanation from the answer page if the fetched page does not have it, e.g. the top page
+async fn fetch_explanation<F: Fetcher>(
+    fetcher: &F,
+    args: &Args,
+    config: &Config,
+    kakomon: &mut Kakomon,
+) {
+    let answer_url = match (&kakomon.explanation, &kakomon.answer_url) {
+        (None, Some(answer_url)) => answer_url,
+        _ => return,
+    };
+    match fetch_html(fetcher, answer_url, config, !args.no_cache).await {
+        Ok(text) => {
+            kakomon.explanation = extract_explanation(&text, config.extract_options(answer_url))
+        }
+        // the question is still sent without the explanation
+        Err(e) => warn!("failed to fetch the explanation from {}: {}", answer_url, e),
+    }
+}
+
+// fetch the html of the url, reusing the cached one in memory or `html_cache_dir` if `use_cache` is set
+async fn fetch_html<F: Fetcher>(
+    fetcher: &F,
+    url: &Url,
+    config: &Config,
+    use_cache: bool,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let memory_cache = config.memory_cache.as_ref();
+    if let Some(html) = memory_cache
+        .filter(|_| use_cache)
+        .and_then(|cache| cache.get(url))
+    {
+        debug!("use the html of {} cached in memory", url);
+        return Ok(html);
+    }
+    let cache = config.html_cache.as_ref();
+    if let Some(html) = cache.filter(|_| use_cache).and_then(|cache| cache.get(url)) {
+        debug!("use the cached html of {}", url);
+        return Ok(html);
+    }
+
+    let delay = config.fetch.delay.next();
+    if !delay.is_zero() {
+        tokio::time::sleep(delay).await;
+    }
+    let text = fetcher.fetch(url).await?;
+    // an error page is fetched again rather than cached
+    if validate_response(&text, &config.fetch.check).is_err() {
+        return Ok(text);
+    }
+    if let Some(cache) = cache {
+        if let Err(e) = cache.put(url, &text) {
+            warn!("failed to cache the html of {}: {}", url, e);
+        }
+    }
+    if let Some(memory_cache) = memory_cache {
+        memory_cache.put(url, &text);
+    }
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "slack")]
+    use chrono::TimeZone;
+    #[cfg(feature = "slack")]
+    use fetch::MockFetcher;
+    #[cfg(feature = "slack")]
+    use std::time::Instant;
+
+    #[cfg(feature = "slack")]
+    fn try_config_of(json: &str) -> Result<Config, Box<dyn std::error::Error>> {
+        serde_json::from_str::<RawConfig>(json).unwrap().parse()
+    }
+
+    #[cfg(feature = "slack")]
+    fn config_of(json: &str) -> Config {
+        try_config_of(json).unwrap()
+    }
+
+    // a question page of ap-siken.com with the title and the statement
+    #[cfg(feature = "slack")]
+    fn question_page(title: &str, mondai: &str) -> String {
+        format!(
+            r#"<html><body><h3 class="qno">{}</h3><div>{}</div>
+            <div id="ans"><ul class="selectList"><li><span>ア</span></li></ul></div></body></html>"#,
+            title, mondai
+        )
+    }
+
+    #[cfg(feature = "slack")]
+    #[tokio::test]
+    async fn test_run_with_mock_fetcher() {
+        let fetcher = MockFetcher::default().with_page(
+            "https://www.ap-siken.com/",
+            include_str!("../testdata/home.html"),
+        );
+        let args = Args::parse_from(["ipa-shiken-fetcher", "--dry-run", "--quiet"]);
+        let config = config_of(
+            r#"{
+                "webhook_url": "https://hooks.slack.com/services/T/B/X",
+                "fetch_urls": ["https://www.ap-siken.com/", "https://www.nw-siken.com/"]
+            }"#,
+        );
+
+        let mut state = State::default();
+        let stats = run(&fetcher, &args, &config, &mut state).await;
+        assert_eq!(stats.fetched, 1);
+        assert_eq!(stats.fetch_failed, 1);
+        assert_eq!(stats.sent, 0);
+        assert_eq!(stats.failures.len(), 1);
+        assert_eq!(stats.failures[0].0.as_str(), "https://www.nw-siken.com/");
+
+        let history = &state.fetch_history;
+        assert_eq!(history["https://www.ap-siken.com/"].consecutive_failures, 0);
+        assert_eq!(history["https://www.nw-siken.com/"].consecutive_failures, 1);
+        assert!(history["https://www.nw-siken.com/"]
+            .last_success_at
+            .is_none());
+    }
+
+    #[cfg(feature = "slack")]
+    #[test]
+    fn test_url_intervals() {
+        let config = config_of(
+            r#"{
+                "webhook_url": "https://hooks.slack.com/services/T/B/X",
+                "interval_secs": 600,
+                "fetch_urls": [
+                    "https://www.ap-siken.com/",
+                    {"url": "https://www.nw-siken.com/", "interval_secs": 60}
+                ]
+            }"#,
+        );
+        let schedule = config.schedule.clone().unwrap();
+        let (ap, nw) = (&config.fetch_urls[0], &config.fetch_urls[1]);
+        let at =
+            |secs| Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap() + Duration::from_secs(secs);
+
+        // all of them on the start
+        let mut next_runs = BTreeMap::new();
+        assert_eq!(
+            due_fetch_urls(&config, &next_runs, at(0)),
+            config.fetch_urls
+        );
+        for url in config.fetch_urls.iter() {
+            next_runs.insert(url.clone(), next_run_of(&config, &schedule, url, at(0)));
+        }
+        assert_eq!(next_runs[ap], at(600));
+        assert_eq!(next_runs[nw], at(60));
+        assert!(due_fetch_urls(&config, &next_runs, at(30)).is_empty());
+        assert_eq!(
+            due_fetch_urls(&config, &next_runs, at(60)),
+            vec![nw.clone()]
+        );
+
+        let mut state = State::default();
+        state.record_next_run(nw, at(120));
+        assert_eq!(state.fetch_history[nw.as_str()].next_run_at, Some(at(120)));
+
+        assert!(try_config_of(
+            r#"{
+                "webhook_url": "https://hooks.slack.com/services/T/B/X",
+                "fetch_urls": [{"url": "https://www.nw-siken.com/", "interval_secs": 0}]
+            }"#,
+        )
+        .is_err());
+    }
+
+    #[cfg(feature = "slack")]
+    #[tokio::test]
+    async fn test_run_template() {
+        let url = "https://www.ap-siken.com/kakomon/05_haru/q1.html";
+        let fetcher =
+            MockFetcher::default().with_page(url, &question_page("令和5年春期 問1", "問題文"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out.jsonl");
+        let config = config_of(&format!(
+            r#"{{
+                "webhook_url": {{"url": "{}", "template": "short"}},
+                "templates": {{"short": "{{{{title}}}}: {{{{mondai}}}}"}},
+                "fetch_urls": ["{}"],
+                "per_message_delay_ms": 0
+            }}"#,
+            Url::from_file_path(&out).unwrap(),
+            url
+        ));
+        let args = Args::parse_from(["ipa-shiken-fetcher", "--quiet"]);
+        let stats = run(&fetcher, &args, &config, &mut State::default()).await;
+        assert_eq!(stats.sent, 1);
+        let body: serde_json::Value =
+            serde_json::from_str(std::fs::read_to_string(&out).unwrap().trim()).unwrap();
+        assert_eq!(body, serde_json::json!({"text": "令和5年春期 問1: 問題文"}));
+    }
+
+    #[cfg(feature = "slack")]
+    #[tokio::test]
+    async fn test_run_html_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let html = dir.path().join("q1.html");
+        std::fs::write(&html, question_page("令和5年春期 問1", "問題文")).unwrap();
+        let url = "https://www.ap-siken.com/kakomon/05_haru/q1.html";
+        let args = Args::parse_from([
+            "ipa-shiken-fetcher",
+            "--quiet",
+            "--html-file",
+            html.to_str().unwrap(),
+            "--source-url",
+            url,
+        ]);
+        let files = html_files(&args).unwrap();
+        assert_eq!(files, [(Url::parse(url).unwrap(), html.clone())]);
+
+        let out = dir.path().join("out.jsonl");
+        let config = config_of(&format!(
+            r#"{{"webhook_url": "{}", "fetch_urls": [], "per_message_delay_ms": 0}}"#,
+            Url::from_file_path(&out).unwrap()
+        ));
+        // nothing is fetched from the network
+        let network = MockFetcher::default();
+        let fetcher = FileFetcher::new(files, &network);
+        let urls = [Url::parse(url).unwrap()];
+        let stats = run_urls(&fetcher, &args, &config, &mut State::default(), &urls).await;
+        assert_eq!((stats.fetched, stats.sent), (1, 1));
+        assert!(std::fs::read_to_string(&out).unwrap().contains("問題文"));
+
+        let args = Args::parse_from([
+            "ipa-shiken-fetcher",
+            "--html-file",
+            "a.html",
+            "--html-file",
+            "b.html",
+            "--source-url",
+            url,
+        ]);
+        assert!(html_files(&args).is_err());
+        let args = Args::parse_from([
+            "ipa-shiken-fetcher",
+            "--html-file",
+            "a.html",
+            "--source-url",
+            url,
+            "--html-file",
+            "b.html",
+            "--source-url",
+            url,
+        ]);
+        assert!(html_files(&args).is_err());
+        assert!(Args::try_parse_from(["ipa-shiken-fetcher", "--html-file", "a.html"]).is_err());
+    }
+
+    #[cfg(feature = "slack")]
+    #[tokio::test]
+    async fn test_run_extract_failures() {
+        let url = "https://www.ap-siken.com/kakomon/05_haru/q1.html";
+        let fetcher = MockFetcher::default().with_page(
+            url,
+            r#"<html><body><h1>メンテナンス中です</h1><p>しばらくお待ちください。</p></body></html>"#,
+        );
+        let config = config_of(&format!(
+            r#"{{"webhook_url": "https://hooks.slack.com/services/T/B/X", "fetch_urls": ["{}"]}}"#,
+            url
+        ));
+        let args = Args::parse_from(["ipa-shiken-fetcher", "--dry-run", "--quiet"]);
+        let stats = run(&fetcher, &args, &config, &mut State::default()).await;
+        assert_eq!(
+            stats.extract_failures,
+            [(
+                Url::parse(url).unwrap(),
+                "no div.kako nor question page".to_string()
+            )]
+        );
+        assert_eq!(run_exit_code(&args, &stats), EXIT_SUCCESS);
+        let args = Args::parse_from([
+            "ipa-shiken-fetcher",
+            "--dry-run",
+            "--quiet",
+            "--fail-on-extract-error",
+        ]);
+        assert_eq!(run_exit_code(&args, &stats), EXIT_FAILURE);
+    }
+
+    #[cfg(feature = "slack")]
+    #[tokio::test]
+    async fn test_run_limit() {
+        let urls = (1..=4)
+            .map(|num| format!("https://www.ap-siken.com/kakomon/05_haru/q{}.html", num))
+            .collect::<Vec<_>>();
+        let fetcher = urls.iter().fold(MockFetcher::default(), |fetcher, url| {
+            fetcher.with_page(url, &question_page(url, "問題文"))
+        });
+        let config = config_of(&format!(
+            r#"{{"webhook_url": "https://hooks.slack.com/services/T/B/X", "fetch_urls": {}}}"#,
+            serde_json::to_string(&urls).unwrap()
+        ));
+        let mut state = State::default();
+        let args = Args::parse_from([
+            "ipa-shiken-fetcher",
+            "--dry-run",
+            "--quiet",
+            "--offset",
+            "1",
+            "--limit",
+            "2",
+        ]);
+        let stats = run(&fetcher, &args, &config, &mut state).await;
+        assert_eq!(stats.fetched, 2);
+        let fetched = state.fetch_history.keys().cloned().collect::<Vec<_>>();
+        assert_eq!(fetched, &urls[1..3]);
+
+        let args = Args::parse_from([
+            "ipa-shiken-fetcher",
+            "--dry-run",
+            "--quiet",
+            "--offset",
+            "3",
+        ]);
+        let stats = run(&fetcher, &args, &config, &mut State::default()).await;
+        assert_eq!(stats.fetched, 1);
+        let args = Args::parse_from([
+            "ipa-shiken-fetcher",
+            "--dry-run",
+            "--quiet",
+            "--offset",
+            "9",
+        ]);
+        let stats = run(&fetcher, &args, &config, &mut State::default()).await;
+        assert_eq!(stats.fetched, 0);
+    }
+
+    #[cfg(feature = "slack")]
+    #[tokio::test]
+    async fn test_run_exam_filter() {
+        let urls = [
+            "https://www.ap-siken.com/kakomon/05_haru/q1.html",
+            "https://www.ap-siken.com/kakomon/04_aki/q1.html",
+            "https://www.ap-siken.com/kakomon/sample.html",
+        ];
+        let fetcher = urls.iter().fold(MockFetcher::default(), |fetcher, url| {
+            fetcher.with_page(url, &question_page("問1", "問題文"))
+        });
+        let run_with = |filter: &str| {
+            let config = config_of(&format!(
+                r#"{{"webhook_url": "https://hooks.slack.com/services/T/B/X", "fetch_urls": {}, {}}}"#,
+                serde_json::to_string(&urls).unwrap(),
+                filter
+            ));
+            let fetcher = &fetcher;
+            async move {
+                let args = Args::parse_from(["ipa-shiken-fetcher", "--dry-run", "--quiet"]);
+                run(fetcher, &args, &config, &mut State::default()).await
+            }
+        };
+
+        let stats = run_with(r#""filter_years": [2023]"#).await;
+        assert_eq!((stats.extracted, stats.filtered), (3, 2));
+        let stats = run_with(r#""filter_seasons": ["autumn"], "filter_unknown": "include""#).await;
+        assert_eq!((stats.extracted, stats.filtered), (3, 1));
+        let stats = run_with(r#""filter_years": [2020]"#).await;
+        assert_eq!((stats.extracted, stats.filtered), (3, 3));
+        let stats = run_with(r#""filter_years": []"#).await;
+        assert_eq!((stats.extracted, stats.filtered), (3, 0));
+        // the statements are too short to detect the language of
+        let stats = run_with(r#""filter_languages": ["jpn"]"#).await;
+        assert_eq!((stats.extracted, stats.filtered), (3, 3));
+        let stats = run_with(r#""filter_languages": ["jpn"], "filter_unknown": "include""#).await;
+        assert_eq!((stats.extracted, stats.filtered), (3, 0));
+    }
+
+    #[cfg(feature = "slack")]
+    #[tokio::test]
+    async fn test_run_category_routes() {
+        let page = |category: &str| {
+            // with the category under the choices
+            question_page("令和5年春期 問1", "問題文").replace(
+                "</body>",
+                &format!(
+                    r#"<div class="grayBox">分類<br>{} » 開発技術</div></body>"#,
+                    category
+                ),
+            )
+        };
+        let urls = [
+            "https://www.ap-siken.com/kakomon/05_haru/q1.html",
+            "https://www.ap-siken.com/kakomon/05_haru/q51.html",
+            "https://www.ap-siken.com/kakomon/05_haru/q71.html",
+        ];
+        let fetcher = MockFetcher::default()
+            .with_page(urls[0], &page("テクノロジ系"))
+            .with_page(urls[1], &page("マネジメント系"))
+            .with_page(urls[2], &page("ストラテジ系"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let out = |name: &str| Url::from_file_path(dir.path().join(name)).unwrap();
+        let config = config_of(&format!(
+            r#"{{
+                "webhook_url": "{}",
+                "category_routes": {{
+                    "テクノロジ系": "{}",
+                    "マネジメント系": {{"url": "{}", "enabled": false}}
+                }},
+                "fetch_urls": {},
+                "per_message_delay_ms": 0
+            }}"#,
+            out("default.jsonl"),
+            out("technology.jsonl"),
+            out("management.jsonl"),
+            serde_json::to_string(&urls).unwrap()
+        ));
+        let args = Args::parse_from(["ipa-shiken-fetcher", "--quiet"]);
+        let stats = run(&fetcher, &args, &config, &mut State::default()).await;
+        assert_eq!(stats.sent, 3);
+        let lines = |name: &str| {
+            std::fs::read_to_string(dir.path().join(name))
+                .unwrap()
+                .lines()
+                .count()
+        };
+        assert_eq!(lines("technology.jsonl"), 1);
+        // the unmatched and the disabled ones
+        assert_eq!(lines("default.jsonl"), 2);
+        assert!(!dir.path().join("management.jsonl").exists());
+    }
+
+    #[cfg(feature = "slack")]
+    #[tokio::test]
+    async fn test_run_pipeline() {
+        // the later urls are fetched faster, counting the fetches at the same time
+        struct SlowFetcher {
+            pages: MockFetcher,
+            in_flight: std::sync::atomic::AtomicUsize,
+            max_in_flight: std::sync::atomic::AtomicUsize,
+        }
+        impl Fetcher for SlowFetcher {
+            async fn fetch(&self, url: &Url) -> Result<String, Box<dyn std::error::Error>> {
+                use std::sync::atomic::Ordering;
+                let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                self.max_in_flight.fetch_max(now, Ordering::SeqCst);
+                let number = url.path().trim_end_matches(".html").rsplit('q').next();
+                let wait = 50 - number.and_then(|n| n.parse::<u64>().ok()).unwrap_or(0) * 10;
+                tokio::time::sleep(Duration::from_millis(wait)).await;
+                self.in_flight.fetch_sub(1, Ordering::SeqCst);
+                self.pages.fetch(url).await
+            }
+        }
+        let urls = (1..=4)
+            .map(|num| format!("https://www.ap-siken.com/kakomon/05_haru/q{}.html", num))
+            .collect::<Vec<_>>();
+        let pages = urls.iter().fold(MockFetcher::default(), |fetcher, url| {
+            fetcher.with_page(url, &question_page(url, "問題文"))
+        });
+        let fetcher = SlowFetcher {
+            pages,
+            in_flight: Default::default(),
+            max_in_flight: Default::default(),
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out.jsonl");
+        let config = config_of(&format!(
+            r#"{{
+                "webhook_url": "{}",
+                "fetch_urls": {},
+                "pipeline": {{"fetch_concurrency": 3, "buffer": 1}},
+                "per_message_delay_ms": 0
+            }}"#,
+            Url::from_file_path(&out).unwrap(),
+            serde_json::to_string(&urls).unwrap()
+        ));
+        let args = Args::parse_from(["ipa-shiken-fetcher", "--quiet"]);
+        let stats = run(&fetcher, &args, &config, &mut State::default()).await;
+        assert_eq!(stats.sent, 4);
+        assert_eq!(
+            fetcher
+                .max_in_flight
+                .load(std::sync::atomic::Ordering::SeqCst),
+            3
+        );
+        // sent in the order of the urls
+        let titles = std::fs::read_to_string(&out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["text"].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(titles, urls);
+    }
+
+    #[cfg(feature = "slack")]
+    #[tokio::test]
+    async fn test_run_parse_concurrency() {
+        // the explanation is fetched again from the question page, counting those at the same time
+        #[derive(Default)]
+        struct ExplanationFetcher {
+            pages: MockFetcher,
+            fetched: Mutex<HashSet<Url>>,
+            in_flight: std::sync::atomic::AtomicUsize,
+            max_in_flight: std::sync::atomic::AtomicUsize,
+        }
+        impl Fetcher for ExplanationFetcher {
+            async fn fetch(&self, url: &Url) -> Result<String, Box<dyn std::error::Error>> {
+                use std::sync::atomic::Ordering;
+                if self.fetched.lock().unwrap().insert(url.clone()) {
+                    return self.pages.fetch(url).await;
+                }
+                let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                self.max_in_flight.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                self.in_flight.fetch_sub(1, Ordering::SeqCst);
+                self.pages.fetch(url).await
+            }
+        }
+        let urls = (1..=4)
+            .map(|num| format!("https://www.ap-siken.com/kakomon/05_haru/q{}.html", num))
+            .collect::<Vec<_>>();
+        let pages = urls.iter().fold(MockFetcher::default(), |fetcher, url| {
+            fetcher.with_page(url, &question_page(url, "問題文"))
+        });
+        let fetcher = ExplanationFetcher {
+            pages,
+            ..Default::default()
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out.jsonl");
+        let config = config_of(&format!(
+            r#"{{
+                "webhook_url": "{}",
+                "fetch_urls": {},
+                "include_explanation": true,
+                "pipeline": {{"parse_concurrency": 3, "send_concurrency": 2}},
+                "per_message_delay_ms": 0
+            }}"#,
+            Url::from_file_path(&out).unwrap(),
+            serde_json::to_string(&urls).unwrap()
+        ));
+        let args = Args::parse_from(["ipa-shiken-fetcher", "--quiet", "--no-cache"]);
+        let stats = run(&fetcher, &args, &config, &mut State::default()).await;
+        assert_eq!(stats.sent, 4);
+        assert_eq!(
+            fetcher
+                .max_in_flight
+                .load(std::sync::atomic::Ordering::SeqCst),
+            3
+        );
+        // sent out of the order of the urls at times
+        let mut titles = std::fs::read_to_string(&out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["text"].clone())
+            .collect::<Vec<_>>();
+        titles.sort_by_key(|title| title.to_string());
+        assert_eq!(titles, urls);
+    }
+
+    #[cfg(feature = "slack")]
+    #[tokio::test]
+    async fn test_run_sorted() {
+        let page = |title: &str| question_page(title, "問題文");
+        let urls = [
+            "https://www.ap-siken.com/kakomon/05_haru/q2.html",
+            "https://www.ap-siken.com/kakomon/today/q.html",
+            "https://www.ap-siken.com/kakomon/05_haru/q1.html",
+            "https://www.ap-siken.com/kakomon/21_aki/q80.html",
+        ];
+        let fetcher = MockFetcher::default()
+            .with_page(urls[0], &page("令和5年春期 問2"))
+            .with_page(urls[1], &page("今日の一問"))
+            .with_page(urls[2], &page("令和5年春期 問1"))
+            .with_page(urls[3], &page("平成21年秋期 問80"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out.jsonl");
+        let config = config_of(&format!(
+            r#"{{
+                "webhook_url": "{}",
+                "fetch_urls": {},
+                "per_message_delay_ms": 0
+            }}"#,
+            Url::from_file_path(&out).unwrap(),
+            serde_json::json!(urls)
+        ));
+        let args = Args::parse_from(["ipa-shiken-fetcher", "--sort", "--quiet"]);
+        let mut state = State::default();
+        let stats = run(&fetcher, &args, &config, &mut state).await;
+        assert_eq!(stats.sent, 4);
+
+        let titles = std::fs::read_to_string(&out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["text"].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            titles,
+            [
+                "平成21年秋期 問80",
+                "令和5年春期 問1",
+                "令和5年春期 問2",
+                "今日の一問"
+            ]
+        );
+    }
+
+    #[cfg(feature = "slack")]
+    #[tokio::test]
+    async fn test_run_duplicates() {
+        let html = include_str!("../testdata/home.html");
+        let fetcher = MockFetcher::default()
+            .with_page("https://www.ap-siken.com/", html)
+            .with_page("https://www.ap-siken.com/index.html", html);
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out.jsonl");
+        let config = config_of(&format!(
+            r#"{{
+                "webhook_url": "{}",
+                "fetch_urls": ["https://www.ap-siken.com/", "https://www.ap-siken.com/index.html"],
+                "per_message_delay_ms": 0
+            }}"#,
+            Url::from_file_path(&out).unwrap(),
+        ));
+        let args = Args::parse_from(["ipa-shiken-fetcher", "--quiet"]);
+        let mut state = State::default();
+
+        // without `state_file`
+        let stats = run(&fetcher, &args, &config, &mut state).await;
+        assert_eq!(stats.fetched, 2);
+        assert_eq!(stats.sent, 1);
+        assert_eq!(stats.duplicates, 1);
+        // sent again in the next run, which is another run
+        let stats = run(&fetcher, &args, &config, &mut state).await;
+        assert_eq!(stats.sent, 1);
+        assert_eq!(std::fs::read_to_string(&out).unwrap().lines().count(), 2);
+    }
+
+    #[cfg(feature = "slack")]
+    #[tokio::test]
+    async fn test_run_pending() {
+        let dir = tempfile::tempdir().unwrap();
+        let state_file = dir.path().join("state.json");
+        let dead_letter_file = dir.path().join("state.dead_letter.jsonl");
+        let out = dir.path().join("out.jsonl");
+        let config = |webhook: &std::path::Path| {
+            config_of(&format!(
+                r#"{{
+                    "webhook_url": "{}",
+                    "fetch_urls": ["https://www.ap-siken.com/"],
+                    "state_file": "{}",
+                    "pending_max_attempts": 2,
+                    "per_message_delay_ms": 0
+                }}"#,
+                Url::from_file_path(webhook).unwrap(),
+                state_file.display()
+            ))
+        };
+        // a webhook which always fails, as its directory does not exist
+        let failing = config(&dir.path().join("missing").join("out.jsonl"));
+        assert_eq!(failing.dead_letter_file.as_ref(), Some(&dead_letter_file));
+        let args = Args::parse_from(["ipa-shiken-fetcher", "--quiet"]);
+        let home = MockFetcher::default().with_page(
+            "https://www.ap-siken.com/",
+            include_str!("../testdata/home.html"),
+        );
+        // the top page has another question the next day
+        let nothing = MockFetcher::default();
+
+        let mut state = State::default();
+        let stats = run(&home, &args, &failing, &mut state).await;
+        assert_eq!(stats.send_failed, 1);
+        assert_eq!(state.pending.len(), 1);
+        assert_eq!(State::load(&state_file).unwrap().pending, state.pending);
+
+        // sent on the next run
+        let mut sent = State::load(&state_file).unwrap();
+        let stats = run(&nothing, &args, &config(&out), &mut sent).await;
+        assert_eq!(stats.sent, 1);
+        assert!(sent.pending.is_empty());
+        assert_eq!(sent.seen.len(), 1);
+
+        // or given up on the second failure
+        let stats = run(&nothing, &args, &failing, &mut state).await;
+        assert_eq!(stats.send_failed, 1);
+        assert!(state.pending.is_empty());
+        let dead_letter = std::fs::read_to_string(&dead_letter_file).unwrap();
+        let question = serde_json::from_str::<PendingQuestion>(dead_letter.trim()).unwrap();
+        assert_eq!(question.attempts, 2);
+        assert!(question.kakomon.key().ends_with("q31.html"));
+    }
+
+    #[cfg(feature = "slack")]
+    #[tokio::test]
+    async fn test_with_parse_timeout() {
+        assert_eq!(with_parse_timeout(None, || 1).await, Some(1));
+        // off the thread of the runtime even without the timeout
+        let runtime_thread = std::thread::current().id();
+        assert_ne!(
+            with_parse_timeout(None, || std::thread::current().id()).await,
+            Some(runtime_thread)
+        );
+        assert_eq!(
+            with_parse_timeout(Some(Duration::from_secs(10)), || 2).await,
+            Some(2)
+        );
+        let slow = || std::thread::sleep(Duration::from_millis(200));
+        assert_eq!(
+            with_parse_timeout(Some(Duration::from_millis(10)), slow).await,
+            None
+        );
+
+        let raw = |json: &str| {
+            config_of(&format!(
+                r#"{{"webhook_url": "https://hooks.slack.com/services/T/B/X", "fetch_urls": []{}}}"#,
+                json
+            ))
+            .parse_timeout
+        };
+        assert_eq!(raw(""), Some(DEFAULT_PARSE_TIMEOUT));
+        assert_eq!(
+            raw(r#", "parse_timeout_secs": 5"#),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(raw(r#", "parse_timeout_secs": 0"#), None);
+    }
+
+    #[cfg(feature = "slack")]
+    #[tokio::test]
+    async fn test_run_circuit_breaker() {
+        let dir = tempfile::tempdir().unwrap();
+        let urls = [
+            "https://www.ap-siken.com/kakomon/05_haru/q1.html",
+            "https://www.ap-siken.com/kakomon/05_haru/q2.html",
+            "https://www.ap-siken.com/kakomon/05_haru/q3.html",
+        ];
+        let fetcher = urls.iter().fold(MockFetcher::default(), |fetcher, url| {
+            fetcher.with_page(url, &question_page(url, "問題文"))
+        });
+        // a webhook which always fails, as its directory does not exist
+        let config = config_of(&format!(
+            r#"{{
+                "webhook_url": "{}",
+                "fetch_urls": {},
+                "circuit_breaker": {{"failure_threshold": 2}},
+                "per_message_delay_ms": 0
+            }}"#,
+            Url::from_file_path(dir.path().join("missing").join("out.jsonl")).unwrap(),
+            serde_json::to_string(&urls).unwrap()
+        ));
+        let args = Args::parse_from(["ipa-shiken-fetcher", "--quiet"]);
+
+        let stats = run(&fetcher, &args, &config, &mut State::default()).await;
+        assert_eq!(stats.send_failed, 3);
+        let reasons: Vec<&str> = stats.failures.iter().map(|(_, r)| r.as_str()).collect();
+        assert!(reasons[0].starts_with("send to slack failed"));
+        assert!(reasons[1].starts_with("send to slack failed"));
+        assert_eq!(
+            reasons[2],
+            "send to slack skipped after 2 failures in a row"
+        );
+        // still open on the next run of the daemon
+        let stats = run(&fetcher, &args, &config, &mut State::default()).await;
+        assert!(stats.failures.iter().all(|(_, r)| r.contains("skipped")));
+    }
+
+    #[cfg(feature = "slack")]
+    #[test]
+    fn test_config_debug() {
+        // logged with `RUST_LOG=debug`
+        let config = config_of(
+            r#"{
+                "webhook_url": "https://hooks.slack.com/services/T000/B000/XXXX",
+                "slack_api": {"token": "xoxb-token", "channel": "C0123"},
+                "report_webhook": "https://hooks.slack.com/services/T000/B000/YYYY",
+                "fetch_urls": []
+            }"#,
+        );
+        let debug = format!("{:?}", config);
+        assert!(debug.contains("https://hooks.slack.com/***"));
+        for secret in ["T000", "XXXX", "YYYY", "xoxb-token"] {
+            assert!(!debug.contains(secret), "{}", secret);
+        }
+    }
+
+    #[cfg(feature = "slack")]
+    #[tokio::test]
+    async fn test_run_quiet_hours_skip() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out.jsonl");
+        let url = "https://www.ap-siken.com/kakomon/05_haru/q1.html";
+        let fetcher =
+            MockFetcher::default().with_page(url, &question_page("令和5年春期 問1", "問題文"));
+        let config_with = |quiet_hours: Option<String>| {
+            let mut json = serde_json::json!({
+                "webhook_url": Url::from_file_path(&out).unwrap(),
+                "fetch_urls": [url],
+                "state_file": dir.path().join("state.json"),
+                "per_message_delay_ms": 0
+            });
+            if let Some(quiet_hours) = quiet_hours {
+                json["quiet_hours"] = quiet_hours.into();
+            }
+            config_of(&json.to_string())
+        };
+        // the hours around now
+        let now = Utc::now();
+        let quiet_hours = format!(
+            "{}-{}",
+            (now - chrono::Duration::hours(1)).format("%H:%M"),
+            (now + chrono::Duration::hours(1)).format("%H:%M")
+        );
+        let args = Args::parse_from(["ipa-shiken-fetcher", "--quiet"]);
+        let mut state = State::default();
+
+        let stats = run(&fetcher, &args, &config_with(Some(quiet_hours)), &mut state).await;
+        assert_eq!(stats.sent, 0);
+        assert!(state.seen.is_empty());
+        // found again after the quiet hours
+        let stats = run(&fetcher, &args, &config_with(None), &mut state).await;
+        assert_eq!(stats.sent, 1);
+        assert_eq!(std::fs::read_to_string(&out).unwrap().lines().count(), 1);
+    }
+
+    #[cfg(feature = "slack")]
+    #[tokio::test]
+    async fn test_run_urls_update_sent_messages() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out.jsonl");
+        let url = "https://www.ap-siken.com/kakomon/05_haru/q1.html";
+        let page = |mondai: &str| {
+            MockFetcher::default().with_page(url, &question_page("令和5年春期 問1", mondai))
+        };
+        let config = config_of(&format!(
+            r#"{{
+                "webhook_url": "{}",
+                "fetch_urls": ["{}"],
+                "state_file": "{}",
+                "notify_updates": true,
+                "update_sent_messages": true,
+                "per_message_delay_ms": 0
+            }}"#,
+            Url::from_file_path(&out).unwrap(),
+            url,
+            dir.path().join("state.json").display()
+        ));
+        let args = Args::parse_from(["ipa-shiken-fetcher", "--quiet"]);
+        let mut state = State::default();
+        let urls = &config.fetch_urls;
+
+        let fetcher = page("問題文");
+        let stats = run_urls(&fetcher, &args, &config, &mut state, urls).await;
+        assert_eq!(stats.sent, 1);
+        let fetcher = page("直された問題文");
+        let stats = run_urls(&fetcher, &args, &config, &mut state, urls).await;
+        assert_eq!(stats.sent, 1);
+        let stats = run_urls(&fetcher, &args, &config, &mut state, urls).await;
+        assert_eq!(stats.sent, 0);
+
+        let texts = std::fs::read_to_string(&out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["text"].clone())
+            .collect::<Vec<_>>();
+        // the webhook cannot update the message but gets the update
+        assert_eq!(texts, ["令和5年春期 問1", "(更新) 令和5年春期 問1"]);
+    }
+
+    #[cfg(feature = "slack")]
+    #[tokio::test]
+    async fn test_run_updates() {
+        let dir = tempfile::tempdir().unwrap();
+        let state_file = dir.path().join("state.json");
+        let out = dir.path().join("out.jsonl");
+        let url = "https://www.ap-siken.com/kakomon/05_haru/q1.html";
+        let page = |mondai: &str| {
+            MockFetcher::default().with_page(url, &question_page("令和5年春期 問1", mondai))
+        };
+        let config = config_of(&format!(
+            r#"{{
+                "webhook_url": "{}",
+                "fetch_urls": ["{}"],
+                "state_file": "{}",
+                "notify_updates": true,
+                "per_message_delay_ms": 0
+            }}"#,
+            Url::from_file_path(&out).unwrap(),
+            url,
+            state_file.display()
+        ));
+        let args = Args::parse_from(["ipa-shiken-fetcher", "--quiet"]);
+        let mut state = State::default();
+
+        // new, unchanged and then updated
+        assert_eq!(
+            run(&page("問題文"), &args, &config, &mut state).await.sent,
+            1
+        );
+        assert_eq!(
+            run(&page("問題文"), &args, &config, &mut state).await.sent,
+            0
+        );
+        assert_eq!(
+            run(&page("直された問題文"), &args, &config, &mut state)
+                .await
+                .sent,
+            1
+        );
+        assert_eq!(
+            run(&page("直された問題文"), &args, &config, &mut state)
+                .await
+                .sent,
+            0
+        );
+        assert_eq!(State::load(&state_file).unwrap().contents.len(), 1);
+
+        let bodies = std::fs::read_to_string(&out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(bodies.len(), 2);
+        assert_eq!(bodies[0]["text"], "令和5年春期 問1");
+        assert_eq!(bodies[1]["text"], "(更新) 令和5年春期 問1");
+        let blocks = bodies[1]["attachments"][0]["blocks"].as_array().unwrap();
+        assert_eq!(blocks.last().unwrap()["type"], "context");
+        assert_eq!(
+            blocks.last().unwrap()["elements"][0]["text"],
+            "更新: 問題文"
+        );
+    }
+
+    #[cfg(feature = "slack")]
+    #[tokio::test]
+    async fn test_deliver_per_message_delay() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = Url::from_file_path(dir.path().join("out.jsonl")).unwrap();
+        let url = Url::parse("https://www.ap-siken.com/").unwrap();
+        let questions = ["問1", "問2"].map(|title| Kakomon {
+            title: title.to_string(),
+            mondai: "問題文".to_string(),
+            ..Default::default()
+        });
+        // a batch does not wait whatever the delay
+        for (delay_ms, batch) in [(200, false), (0, false), (200, true)] {
+            // the two questions are sent to the two destinations
+            let config = config_of(&format!(
+                r#"{{
+                    "webhook_url": "{}",
+                    "webhooks": {{"slack": "{}"}},
+                    "fetch_urls": [],
+                    "per_message_delay_ms": {}
+                }}"#,
+                out, out, delay_ms
+            ));
+            let mut state = State::default();
+            let run = SharedRun::new(&mut state, RunStats::default());
+            let started = Instant::now();
+            for kakomon in questions.iter() {
+                deliver(&config, &run, &url, kakomon, false, None, batch)
+                    .await
+                    .unwrap();
+            }
+            let elapsed = started.elapsed();
+            assert_eq!(run.into_stats().sent, 4);
+            // waited once before the second question, not before every message
+            let delay = Duration::from_millis(if batch { 0 } else { delay_ms });
+            assert!(elapsed >= delay, "{:?}", elapsed);
+            assert!(
+                elapsed < delay + Duration::from_millis(150),
+                "{:?}",
+                elapsed
+            );
+        }
+    }
+
+    #[cfg(feature = "slack")]
+    #[tokio::test]
+    async fn test_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out.jsonl");
+        let input = dir.path().join("saved.json");
+        let kakomon = Kakomon {
+            title: "問1".to_string(),
+            answer_url: Some(
+                Url::parse("https://www.ap-siken.com/kakomon/05_aki/q1.html").unwrap(),
+            ),
+            mondai: "問題文".to_string(),
+            choices: vec!["ア".to_string(), "イ".to_string()],
+            ..Default::default()
+        };
+        std::fs::write(&input, serde_json::to_string(&[&kakomon]).unwrap()).unwrap();
+        let questions = load_questions(&input).unwrap();
+        assert_eq!(questions, vec![kakomon]);
+
+        let config = config_of(&format!(
+            r#"{{
+                "webhook_url": "{}",
+                "fetch_urls": [],
+                "state_file": "{}",
+                "per_message_delay_ms": 0
+            }}"#,
+            Url::from_file_path(&out).unwrap(),
+            dir.path().join("state.json").display()
+        ));
+        let args = Args::parse_from(["ipa-shiken-fetcher", "--quiet"]);
+        let mut state = State::default();
+
+        let stats = replay(&args, &config, &mut state, &input, &questions, false).await;
+        assert_eq!(stats.sent, 1);
+        // not sent again unless forced
+        let stats = replay(&args, &config, &mut state, &input, &questions, false).await;
+        assert_eq!(stats.sent, 0);
+        let stats = replay(&args, &config, &mut state, &input, &questions, true).await;
+        assert_eq!(stats.sent, 1);
+        assert_eq!(std::fs::read_to_string(&out).unwrap().lines().count(), 2);
+    }
+
+    #[cfg(feature = "slack")]
+    #[test]
+    fn test_parse_config_str() {
+        let json = r#"{"webhook_url": "https://hooks.slack.com/services/T/B/X", "fetch_urls": ["https://www.ap-siken.com/"]}"#;
+        let toml = r#"
+            # the destinations
+            webhook_url = "https://hooks.slack.com/services/T/B/X"
+            fetch_urls = ["https://www.ap-siken.com/"]
+
+            [webhooks]
+            slack = "https://hooks.slack.com/services/T/B/Y"
+        "#;
+        let yaml = "
+webhook_url: https://hooks.slack.com/services/T/B/X
+fetch_urls:
+  - https://www.ap-siken.com/
+webhooks:
+  slack: https://hooks.slack.com/services/T/B/Y
+";
+        for (text, destinations) in [(json, 1), (toml, 2), (yaml, 2)] {
+            let config = parse_config_str(text, None).unwrap().parse().unwrap();
+            assert_eq!(config.destinations.len(), destinations);
+            assert_eq!(config.fetch_urls[0].as_str(), "https://www.ap-siken.com/");
+        }
+        // the leading spaces do not matter
+        assert!(parse_config_value(r#" {"fetch_urls": []}"#).is_ok());
+
+        let err = parse_config_value("webhook_url = [").unwrap_err();
+        assert!(
+            err.starts_with("not json, toml nor yaml (toml: "),
+            "{}",
+            err
+        );
+        assert!(
+            err.contains("; yaml: ") && err.contains("; json: "),
+            "{}",
+            err
+        );
+        let err = parse_config_value("just a text").unwrap_err();
+        assert!(err.contains("yaml: not a table"), "{}", err);
+    }
+
+    #[test]
+    fn test_merge_config() {
+        let base = serde_json::json!({
+            "webhook_url": "https://hooks.slack.com/services/T/B/X",
+            "fetch_urls": ["https://www.ap-siken.com/", "https://www.nw-siken.com/"],
+            "sites": {
+                "www.ap-siken.com": {"exclude_selectors": ["ad"], "title_selectors": ["h2"]}
+            },
+            "per_message_delay_ms": 300
+        });
+        let overrides = serde_json::json!({
+            "fetch_urls": ["https://www.db-siken.com/"],
+            "sites": {"www.ap-siken.com": {"exclude_selectors": ["nav"]}},
+            "state_file": "state.json"
+        });
+        let merged = merge_config(base.clone(), overrides.clone(), None).unwrap();
+        // the arrays are replaced and the objects are merged
+        assert_eq!(
+            serde_json::to_value(&merged.fetch_urls).unwrap(),
+            overrides["fetch_urls"]
+        );
+        let site = serde_json::to_value(&merged.sites["www.ap-siken.com"]).unwrap();
+        assert_eq!(site["exclude_selectors"], serde_json::json!(["nav"]));
+        assert_eq!(site["title_selectors"], serde_json::json!(["h2"]));
+        assert_eq!(merged.per_message_delay_ms, Some(300));
+        assert_eq!(merged.state_file, Some(PathBuf::from("state.json")));
+
+        // either of them alone
+        let empty = serde_json::json!({});
+        let config = merge_config(base.clone(), empty.clone(), None).unwrap();
+        assert_eq!(config.fetch_urls.len(), 2);
+        let config = merge_config(empty.clone(), base.clone(), None).unwrap();
+        assert_eq!(config.fetch_urls.len(), 2);
+        // without any destination
+        assert!(merge_config(empty, overrides, None)
+            .unwrap()
+            .parse()
+            .is_err());
+    }
+
+    #[cfg(feature = "slack")]
+    #[test]
+    fn test_config_includes() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        std::fs::write(
+            dir.path().join("urls1.toml"),
+            "include = [\"sub/urls2.yaml\"]\nfetch_urls = [\"https://www.ap-siken.com/\"]\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("sub/urls2.yaml"),
+            "fetch_urls:\n  - https://www.nw-siken.com/\n  - https://www.sc-siken.com/\n",
+        )
+        .unwrap();
+        let config = parse_config_str(
+            &format!(
+                r#"{{"webhook_url": "https://hooks.slack.com/services/T/B/X", "fetch_urls": ["https://www.fe-siken.com/"], "include": [{:?}]}}"#,
+                dir.path().join("urls1.toml")
+            ),
+            None,
+        )
+        .unwrap()
+        .parse()
+        .unwrap();
+        assert_eq!(
+            config
+                .fetch_urls
+                .iter()
+                .map(Url::as_str)
+                .collect::<Vec<_>>(),
+            [
+                "https://www.fe-siken.com/",
+                "https://www.ap-siken.com/",
+                "https://www.nw-siken.com/",
+                "https://www.sc-siken.com/",
+            ]
+        );
+
+        std::fs::write(dir.path().join("a.json"), r#"{"include": ["b.json"]}"#).unwrap();
+        std::fs::write(dir.path().join("b.json"), r#"{"include": ["./a.json"]}"#).unwrap();
+        let include = |file: &str| {
+            parse_config_str(
+                &format!(
+                    r#"{{"fetch_urls": [], "include": [{:?}]}}"#,
+                    dir.path().join(file)
+                ),
+                None,
+            )
+        };
+        let err = include("a.json").unwrap_err().to_string();
+        assert!(err.starts_with("circular include: "), "{}", err);
+        assert!(err.ends_with("a.json"), "{}", err);
+        assert!(include("missing.toml").is_err());
+    }
+
+    #[test]
+    fn test_config_profiles() {
+        let config = serde_json::json!({
+            "webhook_url": "https://hooks.slack.com/services/T/B/DEV",
+            "fetch_urls": ["https://www.ap-siken.com/"],
+            "profiles": {
+                "prod": {"webhook_url": "https://hooks.slack.com/services/T/B/PROD"},
+                "stg": {"fetch_urls": []}
+            }
+        });
+        let profile = |name: &str, from_env: bool| Profile {
+            name: name.to_string(),
+            from_env,
+        };
+        let webhook_url = |profile: Option<&Profile>| {
+            let config = merge_config(config.clone(), serde_json::json!({}), profile).unwrap();
+            config.webhook_url.unwrap().url().unwrap().to_string()
+        };
+        // the top level without a profile
+        assert_eq!(
+            webhook_url(None),
+            "https://hooks.slack.com/services/T/B/DEV"
+        );
+        assert_eq!(
+            webhook_url(Some(&profile("prod", false))),
+            "https://hooks.slack.com/services/T/B/PROD"
+        );
+        let stg = merge_config(
+            config.clone(),
+            serde_json::json!({}),
+            Some(&profile("stg", false)),
+        );
+        assert!(stg.unwrap().fetch_urls.is_empty());
+
+        let err = merge_config(
+            config.clone(),
+            serde_json::json!({}),
+            Some(&profile("qa", false)),
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "the profile `qa` is not in `profiles`");
+        // APP_ENV of the other tools
+        assert_eq!(
+            webhook_url(Some(&profile("qa", true))),
+            "https://hooks.slack.com/services/T/B/DEV"
+        );
+
+        let args = Args::parse_from(["ipa-shiken-fetcher", "--profile", "prod"]);
+        assert_eq!(Profile::of(&args), Some(profile("prod", false)));
+    }
+
+    #[cfg(feature = "slack")]
+    #[test]
+    fn test_config_webhooks() {
+        let config = config_of(
+            r#"{
+                "webhook_url": "https://hooks.slack.com/services/T/B/X",
+                "webhooks": {"slack": "https://hooks.slack.com/services/T/B/Y"},
+                "fetch_urls": []
+            }"#,
+        );
+        assert_eq!(config.destinations.len(), 2);
+        assert!(try_config_of(r#"{"fetch_urls": []}"#).is_err());
+        assert!(
+            try_config_of(r#"{"webhooks": {"slack": "not a url"}, "fetch_urls": []}"#).is_err()
+        );
+    }
+
+    #[cfg(feature = "slack")]
+    #[test]
+    fn test_config_disabled_destinations() {
+        let config = config_of(
+            r#"{
+                "webhook_url": {"url": "https://hooks.slack.com/services/T/B/X", "enabled": false},
+                "webhooks": {"slack": {"url": "https://hooks.slack.com/services/T/B/Y", "enabled": true}},
+                "fetch_urls": []
+            }"#,
+        );
+        assert_eq!(config.destinations.len(), 1);
+        assert_eq!(config.disabled_destinations, 1);
+    }
+
+    #[cfg(feature = "slack")]
+    #[test]
+    fn test_config_templates() {
+        let config = config_of(
+            r#"{
+                "webhooks": {"slack": {"url": "https://hooks.slack.com/services/T/B/Y", "template": "short"}},
+                "templates": {"short": "{{title}}"},
+                "fetch_urls": []
+            }"#,
+        );
+        assert_eq!(config.destinations[0].template.as_deref(), Some("short"));
+        // the templates are checked on start
+        assert!(try_config_of(
+            r#"{
+                "webhook_url": {"url": "https://hooks.slack.com/services/T/B/X", "template": "long"},
+                "templates": {"short": "{{title}}"},
+                "fetch_urls": []
+            }"#
+        )
+        .is_err());
+        assert!(try_config_of(
+            r#"{
+                "webhook_url": "https://hooks.slack.com/services/T/B/X",
+                "templates": {"broken": "{{#if title}}"},
+                "fetch_urls": []
+            }"#
+        )
+        .is_err());
+    }
+
+    #[cfg(feature = "slack")]
+    #[test]
+    fn test_config_basic_auth() {
+        let config = config_of(
+            r#"{
+                "webhook_url": "https://hooks.slack.com/services/T/B/X",
+                "fetch_urls": [
+                    "https://www.ap-siken.com/",
+                    {"url": "https://mirror.example.com/", "username": "user", "password": "pass"}
+                ]
+            }"#,
+        );
+        assert_eq!(config.fetch_urls.len(), 2);
+        assert_eq!(config.fetch.basic_auth.len(), 1);
+        assert_eq!(
+            config.fetch.basic_auth[0].0.as_str(),
+            "https://mirror.example.com/"
+        );
+        assert_eq!(config.fetch.basic_auth[0].1.username, "user");
+    }
+
+    #[cfg(feature = "slack")]
+    #[test]
+    fn test_config_headers() {
+        let config = config_of(
+            r#"{
+                "webhook_url": "https://hooks.slack.com/services/T/B/X",
+                "headers": {"Accept-Language": "ja"},
+                "fetch_urls": [
+                    {"url": "https://www.ap-siken.com/", "headers": {"Referer": "https://www.ap-siken.com/"}},
+                    {"url": "https://mirror.example.com/", "username": "user", "headers": {}}
+                ]
+            }"#,
+        );
+        assert_eq!(config.fetch.headers.len(), 1);
+        assert_eq!(config.fetch.url_headers.len(), 1);
+        assert_eq!(
+            config.fetch.url_headers[0].1["referer"],
+            "https://www.ap-siken.com/"
+        );
+        assert_eq!(config.fetch.basic_auth.len(), 1);
+        assert!(try_config_of(
+            r#"{
+                "webhook_url": "https://hooks.slack.com/services/T/B/X",
+                "fetch_urls": [{"url": "https://www.ap-siken.com/", "headers": {"bad name": "1"}}]
+            }"#
+        )
+        .is_err());
+    }
+
+    #[cfg(feature = "slack")]
+    #[test]
+    fn test_config_retry() {
+        let config = config_of(
+            r#"{
+                "retry": {"max_attempts": 3, "base_delay_ms": 500},
+                "webhook_url": {"url": "https://hooks.slack.com/services/T/B/X", "retry": {"max_attempts": 0}},
+                "webhooks": {"slack": "https://hooks.slack.com/services/T/B/Y"},
+                "fetch_urls": [
+                    "https://www.ap-siken.com/",
+                    {"url": "https://www.nw-siken.com/", "retry": {"max_delay_ms": 1000}}
+                ]
+            }"#,
+        );
+        let base = RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            ..Default::default()
+        };
+        assert_eq!(config.retry, base);
+        assert_eq!(config.destinations[0].retry.max_attempts, 0);
+        assert_eq!(config.destinations[0].retry.base_delay, base.base_delay);
+        assert_eq!(config.destinations[1].retry, base);
+        assert_eq!(config.fetch.retry, base);
+        assert_eq!(
+            config.fetch.url_retries[0].1,
+            RetryPolicy {
+                max_delay: Duration::from_secs(1),
+                ..base
+            }
+        );
+    }
+
+    #[cfg(feature = "slack")]
+    #[test]
+    fn test_config_duplicate_fetch_urls() {
+        let config = config_of(
+            r#"{
+                "webhook_url": "https://hooks.slack.com/services/T/B/X",
+                "fetch_urls": [
+                    "https://www.ap-siken.com/s?a=1&b=2",
+                    "https://www.nw-siken.com/",
+                    "https://www.ap-siken.com/s/?b=2&a=1"
+                ]
+            }"#,
+        );
+        assert_eq!(config.fetch_urls.len(), 2);
+        assert_eq!(
+            config.fetch_urls[0].as_str(),
+            "https://www.ap-siken.com/s?a=1&b=2"
+        );
+        assert_eq!(config.duplicate_fetch_urls.len(), 1);
+    }
+
+    #[cfg(feature = "slack")]
+    #[test]
+    fn test_config_slack_colors() {
+        assert!(try_config_of(
+            r#"{
+                "webhook_url": "https://hooks.slack.com/services/T/B/X",
+                "slack_colors": {"www.ap-siken.com": "green"},
+                "fetch_urls": []
+            }"#
+        )
+        .is_err());
+    }
+
+    #[cfg(feature = "slack")]
+    #[test]
+    fn test_config_max_connections_per_host() {
+        let with_connections = |max: &str| {
+            try_config_of(&format!(
+                r#"{{"webhook_url": "https://hooks.slack.com/services/T/B/X", "max_connections_per_host": {}, "fetch_urls": []}}"#,
+                max
+            ))
+        };
+        assert_eq!(
+            with_connections("4")
+                .unwrap()
+                .fetch
+                .http
+                .max_connections_per_host,
+            4
+        );
+        assert!(with_connections("0").is_err());
+        let config = config_of(
+            r#"{"webhook_url": "https://hooks.slack.com/services/T/B/X", "fetch_urls": []}"#,
+        );
+        assert_eq!(
+            config.fetch.http.max_connections_per_host,
+            DEFAULT_MAX_CONNECTIONS_PER_HOST
+        );
+    }
 
+    #[cfg(feature = "slack")]
     #[test]
-    fn test_extract_kakomon_url_from_home() {
-        let html_text = include_str!("../testdata/home.html");
-        let url = Url::parse("https://www.ap-siken.com/").expect("invalid url");
-        let kakomon = extract_kakomon(html_text, url).unwrap();
-
-        // trim
-        let left = kakomon
-            .text
-            .split("\n")
-            .map(|s| s.trim())
-            .collect::<Vec<_>>()
-            .join("\n");
-        let right = r#"https://www.ap-siken.com/kakomon/21_haru/q31.html
-クライアントサーバシステムにおけるストアドプロシージャに関する記述のうち，誤っているものはどれか。
-1. 機密性の高いデータに対する処理を特定のプロシージャ呼出しに限定することによって，セキュリティを向上させることができる。
-2. システム全体に共通な処理をプロシージャとして格納することによって，処理の標準化を行うことができる。
-3. データベースへのアクセスを細かい単位でプロシージャ化することによって，処理性能(スループット)を向上させることができる。
-4. 複数のSQL文から成る手続を1回の呼出しで実行できるので，クライアントとサーバ間の通信回数を減らすことができる。
-"#;
-        println!("{}", &left);
-        println!("{}", &right);
-        assert_eq!(left.as_str(), right);
+    fn test_config_report_webhook() {
+        let config = config_of(
+            r#"{"webhook_url": "https://hooks.slack.com/services/T/B/X", "fetch_urls": []}"#,
+        );
+        assert_eq!(config.report_webhook, None);
+        let config = config_of(
+            r#"{
+                "webhook_url": "https://hooks.slack.com/services/T/B/X",
+                "report_webhook": "https://monitor.example.com/hooks/run",
+                "report_on_failure_only": true,
+                "fetch_urls": []
+            }"#,
+        );
+        assert_eq!(
+            config.report_webhook,
+            Some(ReportWebhook {
+                url: Url::parse("https://monitor.example.com/hooks/run").unwrap(),
+                on_failure_only: true,
+            })
+        );
     }
 }
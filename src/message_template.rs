@@ -0,0 +1,99 @@
+use crate::extract::Kakomon;
+use crate::format::{format_text, FormatOptions};
+use handlebars::{no_escape, Handlebars};
+use serde_json::json;
+use std::collections::BTreeMap;
+use url::Url;
+
+// The handlebars templates of `templates` in CONFIG, all compiled on start.
+// The text is not escaped, which is left to the destinations.
+#[derive(Debug, Default)]
+pub struct Templates(Handlebars<'static>);
+
+impl Templates {
+    pub fn compile(
+        sources: &BTreeMap<String, String>,
+    ) -> Result<Templates, Box<dyn std::error::Error>> {
+        let mut registry = Handlebars::new();
+        registry.register_escape_fn(no_escape);
+        for (name, source) in sources.iter() {
+            registry
+                .register_template_string(name, source)
+                .map_err(|e| format!("invalid template `{}`: {}", name, e))?;
+        }
+        Ok(Templates(registry))
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.0.has_template(name)
+    }
+
+    // The fields of `Kakomon` can be used, e.g. `{{title}}` and `{{#each choices}}`, and
+    // `fetch_url` and `text`, the question formatted as the destinations other than slack do.
+    pub fn render(
+        &self,
+        name: &str,
+        kakomon: &Kakomon,
+        fetch_url: &Url,
+        options: &FormatOptions,
+    ) -> Result<String, handlebars::RenderError> {
+        let mut data = json!(kakomon);
+        data["fetch_url"] = json!(fetch_url);
+        data["text"] = json!(format_text(kakomon, options));
+        self.0.render(name, &data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_templates() {
+        let sources = BTreeMap::from([
+            (
+                "short".to_string(),
+                "*{{title}}* <{{answer_url}}>\n{{#each choices}}{{@index}}: {{this}}\n{{/each}}"
+                    .to_string(),
+            ),
+            (
+                "text".to_string(),
+                "{{title}} ({{fetch_url}})\n{{text}}".to_string(),
+            ),
+        ]);
+        let templates = Templates::compile(&sources).unwrap();
+        assert!(templates.contains("short"));
+        assert!(!templates.contains("long"));
+
+        let kakomon = Kakomon {
+            title: "問1 <A & B>".to_string(),
+            answer_url: Some(
+                Url::parse("https://www.ap-siken.com/kakomon/05_aki/q1.html").unwrap(),
+            ),
+            mondai: "どれか。".to_string(),
+            choices: vec!["ア".to_string(), "イ".to_string()],
+            ..Default::default()
+        };
+        let fetch_url = Url::parse("https://www.ap-siken.com/").unwrap();
+        let options = FormatOptions::default();
+        assert_eq!(
+            templates
+                .render("short", &kakomon, &fetch_url, &options)
+                .unwrap(),
+            "*問1 <A & B>* <https://www.ap-siken.com/kakomon/05_aki/q1.html>\n0: ア\n1: イ\n"
+        );
+        assert_eq!(
+            templates
+                .render("text", &kakomon, &fetch_url, &options)
+                .unwrap(),
+            format!(
+                "問1 <A & B> (https://www.ap-siken.com/)\n{}",
+                format_text(&kakomon, &options)
+            )
+        );
+
+        let sources = BTreeMap::from([("broken".to_string(), "{{#each choices}}".to_string())]);
+        let err = Templates::compile(&sources).unwrap_err();
+        assert!(err.to_string().starts_with("invalid template `broken`"));
+    }
+}
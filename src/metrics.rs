@@ -0,0 +1,206 @@
+use crate::stats::RunStats;
+use chrono::{DateTime, Utc};
+use log::{debug, error, info};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+const PREFIX: &str = "ipa_shiken_fetcher";
+
+pub const OPENMETRICS_CONTENT_TYPE: &str =
+    "application/openmetrics-text; version=1.0.0; charset=utf-8";
+pub const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+// The counts of all the runs of the daemon, served on `metrics_addr`.
+#[derive(Debug, Default, Clone)]
+pub struct Metrics {
+    pub runs: u64,
+    pub fetched: u64,
+    pub fetch_failed: u64,
+    pub sent: u64,
+    pub send_failed: u64,
+    pub errors: u64,
+    pub last_run_at: Option<DateTime<Utc>>,
+}
+
+impl Metrics {
+    pub fn record(&mut self, stats: &RunStats, at: DateTime<Utc>) {
+        self.runs += 1;
+        self.fetched += stats.fetched as u64;
+        self.fetch_failed += stats.fetch_failed as u64;
+        self.sent += stats.sent as u64;
+        self.send_failed += stats.send_failed as u64;
+        self.errors += stats.errors as u64;
+        self.last_run_at = Some(at);
+    }
+
+    fn counters(&self) -> [(&'static str, &'static str, u64); 6] {
+        [
+            ("runs", "the runs done", self.runs),
+            ("fetched", "the pages fetched", self.fetched),
+            (
+                "fetch_failed",
+                "the pages failed to fetch",
+                self.fetch_failed,
+            ),
+            ("sent", "the messages sent", self.sent),
+            (
+                "send_failed",
+                "the messages failed to send",
+                self.send_failed,
+            ),
+            (
+                "errors",
+                "the other errors such as saving the state",
+                self.errors,
+            ),
+        ]
+    }
+
+    fn last_run_seconds(&self) -> f64 {
+        self.last_run_at
+            .map(|at| at.timestamp_millis() as f64 / 1000.0)
+            .unwrap_or(0.0)
+    }
+
+    // The counter families are named without `_total` and have units, ending with `# EOF`.
+    pub fn openmetrics_text(&self) -> String {
+        let mut text = String::new();
+        for (name, help, value) in self.counters() {
+            text += &format!("# TYPE {PREFIX}_{name} counter\n");
+            text += &format!("# HELP {PREFIX}_{name} The number of {help}.\n");
+            text += &format!("{PREFIX}_{name}_total {value}\n");
+        }
+        let name = format!("{PREFIX}_last_run_timestamp_seconds");
+        text += &format!("# TYPE {name} gauge\n");
+        text += &format!("# UNIT {name} seconds\n");
+        text += &format!("# HELP {name} When the last run finished.\n");
+        text += &format!("{name} {}\n", self.last_run_seconds());
+        text += "# EOF\n";
+        text
+    }
+
+    // the text format of prometheus 0.0.4
+    pub fn prometheus_text(&self) -> String {
+        let mut text = String::new();
+        for (name, help, value) in self.counters() {
+            text += &format!("# HELP {PREFIX}_{name}_total The number of {help}.\n");
+            text += &format!("# TYPE {PREFIX}_{name}_total counter\n");
+            text += &format!("{PREFIX}_{name}_total {value}\n");
+        }
+        let name = format!("{PREFIX}_last_run_timestamp_seconds");
+        text += &format!("# HELP {name} When the last run finished.\n");
+        text += &format!("# TYPE {name} gauge\n");
+        text += &format!("{name} {}\n", self.last_run_seconds());
+        text
+    }
+}
+
+// the http response to the request, OpenMetrics if it is accepted and prometheus otherwise
+pub fn response(request: &str, metrics: &Metrics) -> String {
+    let mut lines = request.lines();
+    let target = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .unwrap_or_default();
+    if target != "/metrics" {
+        return "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            .to_string();
+    }
+    let openmetrics = lines.any(|line| {
+        line.split_once(':').is_some_and(|(name, value)| {
+            name.eq_ignore_ascii_case("accept") && value.contains("application/openmetrics-text")
+        })
+    });
+    let (content_type, body) = if openmetrics {
+        (OPENMETRICS_CONTENT_TYPE, metrics.openmetrics_text())
+    } else {
+        (PROMETHEUS_CONTENT_TYPE, metrics.prometheus_text())
+    };
+    format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+// Serves `/metrics` until the process exits. The requests are small enough to be read at once.
+pub async fn serve(addr: SocketAddr, metrics: Arc<Mutex<Metrics>>) {
+    let listener = match TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("failed to listen on {} for the metrics: {}", addr, e);
+            return;
+        }
+    };
+    info!("serve the metrics on http://{}/metrics", addr);
+    loop {
+        let (mut stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                debug!("failed to accept a metrics request: {}", e);
+                continue;
+            }
+        };
+        let metrics = metrics.clone();
+        tokio::spawn(async move {
+            let mut buf = vec![0; 8192];
+            let len = match stream.read(&mut buf).await {
+                Ok(len) => len,
+                Err(e) => {
+                    debug!("failed to read the metrics request from {}: {}", peer, e);
+                    return;
+                }
+            };
+            let request = String::from_utf8_lossy(&buf[..len]);
+            let response = response(&request, &metrics.lock().unwrap());
+            if let Err(e) = stream.write_all(response.as_bytes()).await {
+                debug!("failed to write the metrics to {}: {}", peer, e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_metrics_response() {
+        let mut metrics = Metrics::default();
+        let stats = RunStats {
+            fetched: 2,
+            sent: 1,
+            send_failed: 1,
+            ..Default::default()
+        };
+        metrics.record(&stats, Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap());
+        metrics.record(&stats, Utc.with_ymd_and_hms(2024, 1, 1, 1, 0, 0).unwrap());
+
+        let openmetrics = response(
+            "GET /metrics HTTP/1.1\r\nHost: localhost\r\nAccept: application/openmetrics-text; version=1.0.0\r\n\r\n",
+            &metrics,
+        );
+        assert!(openmetrics.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(openmetrics.contains(&format!("Content-Type: {}\r\n", OPENMETRICS_CONTENT_TYPE)));
+        assert!(openmetrics.contains("# TYPE ipa_shiken_fetcher_fetched counter\n"));
+        assert!(openmetrics.contains("ipa_shiken_fetcher_fetched_total 4\n"));
+        assert!(openmetrics.contains("ipa_shiken_fetcher_runs_total 2\n"));
+        assert!(
+            openmetrics.contains("# UNIT ipa_shiken_fetcher_last_run_timestamp_seconds seconds\n")
+        );
+        assert!(openmetrics.contains("ipa_shiken_fetcher_last_run_timestamp_seconds 1704070800\n"));
+        assert!(openmetrics.ends_with("# EOF\n"));
+
+        let prometheus = response("GET /metrics HTTP/1.1\r\nAccept: */*\r\n\r\n", &metrics);
+        assert!(prometheus.contains(&format!("Content-Type: {}\r\n", PROMETHEUS_CONTENT_TYPE)));
+        assert!(prometheus.contains("# TYPE ipa_shiken_fetcher_send_failed_total counter\n"));
+        assert!(!prometheus.contains("# EOF"));
+
+        let not_found = response("GET / HTTP/1.1\r\n\r\n", &metrics);
+        assert!(not_found.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
@@ -0,0 +1,97 @@
+use crate::extract::Kakomon;
+use crate::state::{FetchHistory, PendingQuestion};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use indicatif::{ProgressBar, ProgressStyle};
+use owo_colors::{OwoColorize, Stream};
+use std::io::IsTerminal;
+
+// Colors are only used for what people read in the terminal (`list`, `--dry-run`),
+// and disabled by `--no-color`, `NO_COLOR` or when stdout is not a tty.
+pub fn disable_colors() {
+    owo_colors::set_override(false);
+}
+
+// the `Display` of the question with the title colored
+pub fn print_kakomon(kakomon: &Kakomon) {
+    let text = kakomon.to_string();
+    let (title, rest) = text.split_once('\n').unwrap_or((&text, ""));
+    println!(
+        "{}",
+        title.if_supports_color(Stream::Stdout, |title| title.bold().yellow().to_string())
+    );
+    if !rest.is_empty() {
+        println!("{}", rest);
+    }
+}
+
+// when and how the url was fetched last, for `status`
+pub fn print_fetch_history(url: &str, history: Option<&FetchHistory>, timezone: Tz) {
+    println!(
+        "{}",
+        url.if_supports_color(Stream::Stdout, |url| url.cyan().to_string())
+    );
+    let history = match history {
+        Some(history) => history,
+        None => {
+            println!("  not fetched yet");
+            return;
+        }
+    };
+    let format_time = |time: Option<DateTime<Utc>>| match time {
+        Some(time) => time
+            .with_timezone(&timezone)
+            .format("%Y-%m-%d %H:%M:%S (%Z)")
+            .to_string(),
+        None => "-".to_string(),
+    };
+    println!("  last fetched: {}", format_time(history.last_fetched_at));
+    println!("  last success: {}", format_time(history.last_success_at));
+    if history.next_run_at.is_some() {
+        println!("  next run:     {}", format_time(history.next_run_at));
+    }
+    if let Some(error) = &history.last_error {
+        println!(
+            "  last error:   {} ({} in a row)",
+            error.if_supports_color(Stream::Stdout, |error| error.red().to_string()),
+            history.consecutive_failures
+        );
+    }
+}
+
+// the questions to be sent again on the next run
+pub fn print_pending(pending: &[PendingQuestion], max_attempts: u32) {
+    if pending.is_empty() {
+        return;
+    }
+    println!(
+        "{}",
+        "pending".if_supports_color(Stream::Stdout, |title| title.cyan().to_string())
+    );
+    for question in pending.iter() {
+        println!(
+            "  {} ({}/{} attempts): {}",
+            question.kakomon.key(),
+            question.attempts,
+            max_attempts,
+            question
+                .last_error
+                .if_supports_color(Stream::Stdout, |error| error.red().to_string())
+        );
+    }
+}
+
+// The bar counts the urls done and shows the one in progress on stderr.
+// It is hidden with `--quiet` or when stderr is not a tty.
+pub fn progress_bar(len: usize, quiet: bool) -> ProgressBar {
+    if quiet || !std::io::stderr().is_terminal() {
+        return ProgressBar::hidden();
+    }
+    let bar = ProgressBar::new(len as u64);
+    bar.set_style(
+        ProgressStyle::with_template("{spinner} [{bar:30}] {pos}/{len} {wide_msg}")
+            .expect("invalid progress bar template")
+            .progress_chars("=> "),
+    );
+    bar
+}
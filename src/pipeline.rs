@@ -0,0 +1,139 @@
+use crate::state::State;
+use crate::stats::RunStats;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+// the pages fetched at the same time unless `pipeline.fetch_concurrency` is configured
+pub const DEFAULT_FETCH_CONCURRENCY: usize = 1;
+// the pages parsed at the same time unless `pipeline.parse_concurrency` is configured
+pub const DEFAULT_PARSE_CONCURRENCY: usize = 1;
+// the questions sent at the same time unless `pipeline.send_concurrency` is configured
+pub const DEFAULT_SEND_CONCURRENCY: usize = 1;
+// the pages and the questions waiting for the next stage unless `pipeline.buffer` is configured
+pub const DEFAULT_PIPELINE_BUFFER: usize = 4;
+
+// How the urls go through the fetch, parse and send stages of a run.
+// A stage waits when the next one has `buffer` of them waiting, so a slow send holds the fetches back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PipelineOptions {
+    pub fetch_concurrency: usize,
+    pub parse_concurrency: usize,
+    pub send_concurrency: usize,
+    pub buffer: usize,
+}
+
+impl Default for PipelineOptions {
+    fn default() -> Self {
+        PipelineOptions {
+            fetch_concurrency: DEFAULT_FETCH_CONCURRENCY,
+            parse_concurrency: DEFAULT_PARSE_CONCURRENCY,
+            send_concurrency: DEFAULT_SEND_CONCURRENCY,
+            buffer: DEFAULT_PIPELINE_BUFFER,
+        }
+    }
+}
+
+// `pipeline` in CONFIG
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct RawPipeline {
+    #[serde(default)]
+    pub fetch_concurrency: Option<usize>,
+    #[serde(default)]
+    pub parse_concurrency: Option<usize>,
+    #[serde(default)]
+    pub send_concurrency: Option<usize>,
+    #[serde(default)]
+    pub buffer: Option<usize>,
+}
+
+impl RawPipeline {
+    pub fn parse(&self) -> Result<PipelineOptions, String> {
+        let positive = |value: Option<usize>, default: usize, name: &str| match value {
+            Some(0) => Err(format!("`pipeline.{}` must be 1 or more", name)),
+            value => Ok(value.unwrap_or(default)),
+        };
+        Ok(PipelineOptions {
+            fetch_concurrency: positive(
+                self.fetch_concurrency,
+                DEFAULT_FETCH_CONCURRENCY,
+                "fetch_concurrency",
+            )?,
+            parse_concurrency: positive(
+                self.parse_concurrency,
+                DEFAULT_PARSE_CONCURRENCY,
+                "parse_concurrency",
+            )?,
+            send_concurrency: positive(
+                self.send_concurrency,
+                DEFAULT_SEND_CONCURRENCY,
+                "send_concurrency",
+            )?,
+            buffer: positive(self.buffer, DEFAULT_PIPELINE_BUFFER, "buffer")?,
+        })
+    }
+}
+
+// The state and the stats of a run, shared by the stages and the questions handled at the same time.
+// They are locked only while read or written, never while a page is fetched or a message sent.
+pub struct SharedRun<'a> {
+    inner: Mutex<(&'a mut State, RunStats)>,
+}
+
+impl<'a> SharedRun<'a> {
+    pub fn new(state: &'a mut State, stats: RunStats) -> Self {
+        SharedRun {
+            inner: Mutex::new((state, stats)),
+        }
+    }
+
+    pub fn with<T>(&self, f: impl FnOnce(&mut State, &mut RunStats) -> T) -> T {
+        let mut guard = self.inner.lock().unwrap();
+        let (state, stats) = &mut *guard;
+        f(state, stats)
+    }
+
+    pub fn into_stats(self) -> RunStats {
+        self.inner.into_inner().unwrap().1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_raw_pipeline() {
+        let raw: RawPipeline = serde_json::from_str(r#"{"fetch_concurrency": 4}"#).unwrap();
+        assert_eq!(
+            raw.parse(),
+            Ok(PipelineOptions {
+                fetch_concurrency: 4,
+                ..PipelineOptions::default()
+            })
+        );
+        assert_eq!(
+            RawPipeline::default().parse(),
+            Ok(PipelineOptions::default())
+        );
+        let raw: RawPipeline =
+            serde_json::from_str(r#"{"parse_concurrency": 2, "send_concurrency": 3}"#).unwrap();
+        assert_eq!(
+            raw.parse(),
+            Ok(PipelineOptions {
+                parse_concurrency: 2,
+                send_concurrency: 3,
+                ..PipelineOptions::default()
+            })
+        );
+        let raw: RawPipeline = serde_json::from_str(r#"{"buffer": 0}"#).unwrap();
+        assert_eq!(
+            raw.parse(),
+            Err("`pipeline.buffer` must be 1 or more".to_string())
+        );
+        let raw: RawPipeline = serde_json::from_str(r#"{"send_concurrency": 0}"#).unwrap();
+        assert_eq!(
+            raw.parse(),
+            Err("`pipeline.send_concurrency` must be 1 or more".to_string())
+        );
+    }
+}
@@ -0,0 +1,110 @@
+use chrono::{DateTime, Duration as ChronoDuration, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+// what happens to the questions found in the quiet hours
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum QuietMode {
+    // not sent now, but on a run after the quiet hours finding it again
+    #[default]
+    Skip,
+    // sent together when the quiet hours end
+    Hold,
+}
+
+// The time of day (e.g. `22:00-07:00` in `timezone`) when nothing is sent.
+#[derive(Debug, Clone)]
+pub struct QuietHours {
+    start: NaiveTime,
+    end: NaiveTime,
+    timezone: Tz,
+    pub mode: QuietMode,
+}
+
+impl QuietHours {
+    pub fn parse(
+        spec: &str,
+        timezone: Tz,
+        mode: QuietMode,
+    ) -> Result<QuietHours, Box<dyn std::error::Error>> {
+        let invalid = || {
+            format!(
+                "invalid `quiet_hours` {:?}, expected e.g. `22:00-07:00`",
+                spec
+            )
+        };
+        let (start, end) = spec.split_once('-').ok_or_else(invalid)?;
+        let parse_time = |time: &str| NaiveTime::parse_from_str(time.trim(), "%H:%M");
+        let (start, end) = match (parse_time(start), parse_time(end)) {
+            (Ok(start), Ok(end)) if start != end => (start, end),
+            _ => return Err(invalid().into()),
+        };
+        Ok(QuietHours {
+            start,
+            end,
+            timezone,
+            mode,
+        })
+    }
+
+    pub fn contains(&self, now: DateTime<Utc>) -> bool {
+        let time = now.with_timezone(&self.timezone).time();
+        if self.start < self.end {
+            self.start <= time && time < self.end
+        } else {
+            // over midnight
+            self.start <= time || time < self.end
+        }
+    }
+
+    // how long from `now` until the quiet hours end, zero outside of them
+    pub fn wait_until_end(&self, now: DateTime<Utc>) -> Duration {
+        if !self.contains(now) {
+            return Duration::ZERO;
+        }
+        let local = now.with_timezone(&self.timezone);
+        let mut date = local.date_naive();
+        if local.time() >= self.end {
+            date += ChronoDuration::days(1);
+        }
+        // the earliest one if the end is ambiguous or skipped by a DST change
+        let end = self
+            .timezone
+            .from_local_datetime(&date.and_time(self.end))
+            .earliest()
+            .unwrap_or_else(|| self.timezone.from_utc_datetime(&date.and_time(self.end)));
+        (end.with_timezone(&Utc) - now)
+            .to_std()
+            .unwrap_or(Duration::ZERO)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quiet_hours() {
+        let quiet = QuietHours::parse("22:00-07:00", Tz::Asia__Tokyo, QuietMode::Hold).unwrap();
+        // 23:30 and 06:00 in Tokyo
+        let night = Utc.with_ymd_and_hms(2024, 1, 1, 14, 30, 0).unwrap();
+        let morning = Utc.with_ymd_and_hms(2024, 1, 1, 21, 0, 0).unwrap();
+        // 12:00 in Tokyo
+        let noon = Utc.with_ymd_and_hms(2024, 1, 1, 3, 0, 0).unwrap();
+        assert!(quiet.contains(night));
+        assert!(quiet.contains(morning));
+        assert!(!quiet.contains(noon));
+        assert_eq!(quiet.wait_until_end(night), Duration::from_secs(450 * 60));
+        assert_eq!(quiet.wait_until_end(morning), Duration::from_secs(60 * 60));
+        assert_eq!(quiet.wait_until_end(noon), Duration::ZERO);
+
+        let quiet = QuietHours::parse("12:00-13:00", Tz::UTC, QuietMode::Skip).unwrap();
+        assert!(quiet.contains(Utc.with_ymd_and_hms(2024, 1, 1, 12, 30, 0).unwrap()));
+        assert!(!quiet.contains(Utc.with_ymd_and_hms(2024, 1, 1, 13, 0, 0).unwrap()));
+
+        assert!(QuietHours::parse("22:00", Tz::UTC, QuietMode::Skip).is_err());
+        assert!(QuietHours::parse("25:00-07:00", Tz::UTC, QuietMode::Skip).is_err());
+    }
+}
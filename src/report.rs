@@ -0,0 +1,111 @@
+use crate::destination::{append_json_line, redact_webhook_url};
+use crate::stats::RunStats;
+use log::{debug, info, warn};
+use std::fmt;
+use url::Url;
+
+// `report_webhook` in CONFIG, which gets the summary of every run. The url is not printed by `Debug`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ReportWebhook {
+    pub url: Url,
+    // `report_on_failure_only`
+    pub on_failure_only: bool,
+}
+
+impl fmt::Debug for ReportWebhook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReportWebhook")
+            .field("url", &redact_webhook_url(&self.url))
+            .field("on_failure_only", &self.on_failure_only)
+            .finish()
+    }
+}
+
+// Posts the summary of the run in json (see `RunStats::summary_json`) to the report webhook.
+// A failure of it is only logged, not to change the result of the run.
+pub async fn post_report(client: &reqwest::Client, webhook: &ReportWebhook, stats: &RunStats) {
+    if webhook.on_failure_only && stats.failed() == 0 {
+        debug!("the report is not posted as nothing failed");
+        return;
+    }
+    let body = stats.summary_json();
+    // a `file://` url gets the reports appended as json lines like the destinations
+    let posted = if webhook.url.scheme() == "file" {
+        append_json_line(&webhook.url, &body)
+    } else {
+        post(client, &webhook.url, &body).await
+    };
+    match posted {
+        Ok(()) => info!("posted the report of the run to `report_webhook`"),
+        Err(e) => warn!("failed to post the report to `report_webhook`: {}", e),
+    }
+}
+
+async fn post(
+    client: &reqwest::Client,
+    url: &Url,
+    body: &serde_json::Value,
+) -> Result<(), Box<dyn std::error::Error>> {
+    client
+        .post(url.to_string())
+        .json(body)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_post_report() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("report.jsonl");
+        let mut webhook = ReportWebhook {
+            url: Url::from_file_path(&path).unwrap(),
+            on_failure_only: true,
+        };
+        let client = reqwest::Client::new();
+        let mut stats = RunStats {
+            fetched: 2,
+            sent: 2,
+            ..RunStats::default()
+        };
+
+        post_report(&client, &webhook, &stats).await;
+        assert!(!path.exists());
+        stats.send_failed = 1;
+        post_report(&client, &webhook, &stats).await;
+        webhook.on_failure_only = false;
+        stats.send_failed = 0;
+        post_report(&client, &webhook, &stats).await;
+        let reports = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0]["send_failed"], 1);
+        assert_eq!(reports[1]["sent"], 2);
+
+        // only logged
+        let missing = ReportWebhook {
+            url: Url::from_file_path(dir.path().join("missing").join("report.jsonl")).unwrap(),
+            on_failure_only: false,
+        };
+        post_report(&client, &missing, &stats).await;
+    }
+
+    #[test]
+    fn test_report_webhook_debug() {
+        let webhook = ReportWebhook {
+            url: Url::parse("https://hooks.slack.com/services/T000/B000/XXXX").unwrap(),
+            on_failure_only: true,
+        };
+        let debug = format!("{:?}", webhook);
+        assert!(debug.contains("https://hooks.slack.com/***"));
+        assert!(!debug.contains("XXXX"));
+    }
+}
@@ -0,0 +1,166 @@
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::time::Duration;
+
+// a retryable failure is retried this many times unless `retry.max_attempts` is configured
+pub const DEFAULT_RETRY_MAX_ATTEMPTS: u32 = 2;
+// the first wait before a retry, doubled for every retry
+pub const DEFAULT_RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+// the longest wait between the retries
+pub const DEFAULT_RETRY_MAX_DELAY: Duration = Duration::from_secs(30);
+
+// How the retryable failures of the fetches and the webhooks are retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    // the retries after the first attempt. 0 does not retry at all.
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: DEFAULT_RETRY_MAX_ATTEMPTS,
+            base_delay: DEFAULT_RETRY_BASE_DELAY,
+            max_delay: DEFAULT_RETRY_MAX_DELAY,
+        }
+    }
+}
+
+// `retry` in CONFIG, in the config and in a destination or a fetch url overriding some of it
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct RawRetry {
+    #[serde(default)]
+    pub max_attempts: Option<u32>,
+    #[serde(default)]
+    pub base_delay_ms: Option<u64>,
+    #[serde(default)]
+    pub max_delay_ms: Option<u64>,
+}
+
+impl RawRetry {
+    // the ones not given are taken from `base`
+    pub fn over(&self, base: &RetryPolicy) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: self.max_attempts.unwrap_or(base.max_attempts),
+            base_delay: self
+                .base_delay_ms
+                .map(Duration::from_millis)
+                .unwrap_or(base.base_delay),
+            max_delay: self
+                .max_delay_ms
+                .map(Duration::from_millis)
+                .unwrap_or(base.max_delay),
+        }
+    }
+}
+
+// the wait before the retry after `attempt` failures (from 0), doubled every time up to `max`
+pub fn backoff_delay(attempt: u32, base: Duration, max: Duration) -> Duration {
+    2u32.checked_pow(attempt)
+        .and_then(|factor| base.checked_mul(factor))
+        .map_or(max, |delay| delay.min(max))
+}
+
+// Calls `f` until it succeeds, fails with an error not `retryable` or is retried `max_attempts` times.
+// The last result is returned with the number of the retries made.
+pub async fn with_retry<T, E, Fut>(
+    policy: &RetryPolicy,
+    retryable: impl Fn(&E) -> bool,
+    mut f: impl FnMut() -> Fut,
+) -> (Result<T, E>, u32)
+where
+    E: std::fmt::Display,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut attempt = 0;
+    loop {
+        match f().await {
+            Err(e) if retryable(&e) && attempt < policy.max_attempts => {
+                let delay = backoff_delay(attempt, policy.base_delay, policy.max_delay);
+                debug!("retry in {:?} after {}", delay, e);
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            result => return (result, attempt),
+        }
+    }
+}
+
+// a timeout, a failed connection, a rate limit or an error of the server
+pub fn is_retryable_request(e: &reqwest::Error) -> bool {
+    e.is_timeout()
+        || e.is_connect()
+        || e.status().is_some_and(|status| {
+            status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_backoff_delay() {
+        let (base, max) = (Duration::from_millis(100), Duration::from_millis(1000));
+        assert_eq!(backoff_delay(0, base, max), Duration::from_millis(100));
+        assert_eq!(backoff_delay(1, base, max), Duration::from_millis(200));
+        assert_eq!(backoff_delay(3, base, max), Duration::from_millis(800));
+        assert_eq!(backoff_delay(4, base, max), max);
+        assert_eq!(backoff_delay(100, base, max), max);
+
+        let raw: RawRetry = serde_json::from_str(r#"{"max_attempts": 0}"#).unwrap();
+        let policy = raw.over(&RetryPolicy::default());
+        assert_eq!(policy.max_attempts, 0);
+        assert_eq!(policy.base_delay, DEFAULT_RETRY_BASE_DELAY);
+    }
+
+    #[tokio::test]
+    async fn test_with_retry() {
+        let policy = RetryPolicy {
+            max_attempts: 2,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(1),
+        };
+        let calls = Cell::new(0);
+        let call = || {
+            calls.set(calls.get() + 1);
+            async { Err::<(), _>("retryable") }
+        };
+        let (result, retried) = with_retry(&policy, |_| true, call).await;
+        assert!(result.is_err());
+        assert_eq!((calls.get(), retried), (3, 2));
+
+        calls.set(0);
+        let (result, retried) = with_retry(&policy, |_| false, call).await;
+        assert!(result.is_err());
+        assert_eq!((calls.get(), retried), (1, 0));
+
+        // 0 does not retry
+        calls.set(0);
+        let none = RetryPolicy {
+            max_attempts: 0,
+            ..policy
+        };
+        assert!(with_retry(&none, |_| true, call).await.0.is_err());
+        assert_eq!(calls.get(), 1);
+
+        calls.set(0);
+        let succeed_second = || {
+            calls.set(calls.get() + 1);
+            let result = if calls.get() < 2 {
+                Err("once")
+            } else {
+                Ok(calls.get())
+            };
+            async move { result }
+        };
+        assert_eq!(
+            with_retry(&policy, |_| true, succeed_second).await,
+            (Ok(2), 1)
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use std::str::FromStr;
+use std::time::Duration;
+
+// When the daemon runs next.
+#[derive(Debug, Clone)]
+pub enum Schedule {
+    // every `interval_secs` after the previous run
+    Interval(Duration),
+    // at the times of the `cron` expression in the timezone
+    Cron(Box<cron::Schedule>, Tz),
+}
+
+impl Schedule {
+    pub fn parse(
+        interval_secs: Option<u64>,
+        cron: Option<&str>,
+        timezone: Tz,
+    ) -> Result<Option<Schedule>, Box<dyn std::error::Error>> {
+        match (interval_secs, cron) {
+            (Some(_), Some(_)) => Err("specify either `interval_secs` or `cron`, not both".into()),
+            (Some(0), None) => Err("`interval_secs` must be positive".into()),
+            (Some(secs), None) => Ok(Some(Schedule::Interval(Duration::from_secs(secs)))),
+            (None, Some(expr)) => Ok(Some(Schedule::Cron(Box::new(parse_cron(expr)?), timezone))),
+            (None, None) => Ok(None),
+        }
+    }
+
+    // how long to wait from `now` until the next run
+    pub fn wait_from(&self, now: DateTime<Utc>) -> Duration {
+        match self {
+            Schedule::Interval(interval) => *interval,
+            Schedule::Cron(schedule, timezone) => next_run_time(schedule, *timezone, now)
+                .and_then(|next| (next.with_timezone(&Utc) - now).to_std().ok())
+                .unwrap_or(Duration::ZERO),
+        }
+    }
+}
+
+// The times of the day in `cron` are in `timezone`, so "0 8 * * *" stays at 08:00
+// across the daylight saving time changes. None if the expression never matches again.
+pub fn next_run_time(
+    cron: &cron::Schedule,
+    timezone: Tz,
+    now: DateTime<Utc>,
+) -> Option<DateTime<Tz>> {
+    cron.after(&now.with_timezone(&timezone)).next()
+}
+
+// The usual 5 fields (`min hour day month weekday`) are accepted as well as
+// the 6 or 7 fields of the `cron` crate starting with seconds.
+pub fn parse_cron(expr: &str) -> Result<cron::Schedule, Box<dyn std::error::Error>> {
+    let expr = expr.trim();
+    let expr = if expr.split_whitespace().count() == 5 {
+        format!("0 {}", expr)
+    } else {
+        expr.to_string()
+    };
+    cron::Schedule::from_str(&expr).map_err(|e| format!("invalid cron {:?}: {}", expr, e).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_schedule_wait_from() {
+        let now = Utc.with_ymd_and_hms(2024, 1, 1, 22, 30, 0).unwrap();
+
+        let interval = Schedule::parse(Some(600), None, Tz::UTC).unwrap().unwrap();
+        assert_eq!(interval.wait_from(now), Duration::from_secs(600));
+
+        let cron = Schedule::parse(None, Some("0 23 * * *"), Tz::UTC)
+            .unwrap()
+            .unwrap();
+        assert_eq!(cron.wait_from(now), Duration::from_secs(30 * 60));
+
+        assert!(Schedule::parse(None, None, Tz::UTC).unwrap().is_none());
+        assert!(Schedule::parse(Some(600), Some("0 23 * * *"), Tz::UTC).is_err());
+        assert!(Schedule::parse(None, Some("every day"), Tz::UTC).is_err());
+    }
+
+    #[test]
+    fn test_next_run_time() {
+        let cron = parse_cron("0 8 * * *").unwrap();
+        let at = |tz: Tz, y, m, d, h, min| tz.with_ymd_and_hms(y, m, d, h, min, 0).unwrap();
+
+        // 08:00 in Tokyo is 23:00 of the previous day in UTC
+        let now = Utc.with_ymd_and_hms(2024, 1, 1, 23, 30, 0).unwrap();
+        let next = next_run_time(&cron, Tz::Asia__Tokyo, now).unwrap();
+        assert_eq!(next, at(Tz::Asia__Tokyo, 2024, 1, 3, 8, 0));
+        assert_eq!(
+            next.format("%Y-%m-%d %H:%M (%Z)").to_string(),
+            "2024-01-03 08:00 (JST)"
+        );
+
+        // still 08:00 after the daylight saving time starts
+        let now = Utc.with_ymd_and_hms(2024, 3, 9, 14, 0, 0).unwrap();
+        let next = next_run_time(&cron, Tz::America__New_York, now).unwrap();
+        assert_eq!(next, at(Tz::America__New_York, 2024, 3, 10, 8, 0));
+        assert_eq!(
+            next.with_timezone(&Utc),
+            Utc.with_ymd_and_hms(2024, 3, 10, 12, 0, 0).unwrap()
+        );
+
+        // 02:30 does not exist on the day it starts
+        let cron = parse_cron("30 2 * * *").unwrap();
+        let next = next_run_time(&cron, Tz::America__New_York, now).unwrap();
+        // 02:30 does not exist on the day it starts, so it is skipped
+        assert_eq!(next, at(Tz::America__New_York, 2024, 3, 11, 2, 30));
+
+        // 01:30 happens twice on the day it ends, and the first one is used
+        let cron = parse_cron("30 1 * * *").unwrap();
+        let now = Utc.with_ymd_and_hms(2024, 11, 3, 4, 0, 0).unwrap();
+        let next = next_run_time(&cron, Tz::America__New_York, now).unwrap();
+        assert_eq!(
+            next.with_timezone(&Utc),
+            Utc.with_ymd_and_hms(2024, 11, 3, 5, 30, 0).unwrap()
+        );
+    }
+}
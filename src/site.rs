@@ -0,0 +1,85 @@
+use crate::extract::{extract_failure_reason, extract_kakomon, ExtractOptions, Kakomon};
+use log::warn;
+use url::Url;
+
+// Extracts the question from the pages of a site.
+pub trait SiteParser: Sync {
+    fn name(&self) -> &'static str;
+
+    fn extract(&self, html_text: &str, url: Url, options: &ExtractOptions) -> Option<Kakomon>;
+
+    // why `extract` found no question in the page
+    fn failure_reason(&self, html_text: &str, url: &Url, options: &ExtractOptions) -> String;
+}
+
+// the sites of the "過去問道場" family, e.g. www.ap-siken.com and www.nw-siken.com
+pub struct SikenParser;
+
+impl SiteParser for SikenParser {
+    fn name(&self) -> &'static str {
+        "siken"
+    }
+
+    fn extract(&self, html_text: &str, url: Url, options: &ExtractOptions) -> Option<Kakomon> {
+        extract_kakomon(html_text, url, options)
+    }
+
+    fn failure_reason(&self, html_text: &str, url: &Url, options: &ExtractOptions) -> String {
+        extract_failure_reason(html_text, url, options)
+    }
+}
+
+// the parsers and the hosts they handle, including the subdomains.
+// a new site only needs its parser registered here.
+const PARSERS: &[(&str, &dyn SiteParser)] = &[
+    ("ap-siken.com", &SikenParser),
+    ("nw-siken.com", &SikenParser),
+    ("db-siken.com", &SikenParser),
+    ("fe-siken.com", &SikenParser),
+    ("sc-siken.com", &SikenParser),
+    ("sm-siken.com", &SikenParser),
+    ("pm-siken.com", &SikenParser),
+    ("st-siken.com", &SikenParser),
+    ("au-siken.com", &SikenParser),
+    ("es-siken.com", &SikenParser),
+    ("sa-siken.com", &SikenParser),
+    ("itpassportsiken.com", &SikenParser),
+];
+
+// the parser registered for the host of the url
+fn registered_parser(url: &Url) -> Option<&'static dyn SiteParser> {
+    let host = url.host_str()?;
+    PARSERS
+        .iter()
+        .find(|(domain, _)| host == *domain || host.ends_with(format!(".{}", domain).as_str()))
+        .map(|(_, parser)| *parser)
+}
+
+// the parser for the url, or the default one with a warning for an unknown host
+pub fn parser_for(url: &Url) -> &'static dyn SiteParser {
+    registered_parser(url).unwrap_or_else(|| {
+        warn!(
+            "no parser is registered for {}, trying the default one",
+            url.host_str().unwrap_or_default()
+        );
+        &SikenParser
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registered_parser() {
+        let parser = |url: &str| registered_parser(&Url::parse(url).unwrap()).map(|p| p.name());
+        assert_eq!(parser("https://www.ap-siken.com/"), Some("siken"));
+        assert_eq!(parser("https://nw-siken.com/kakomon/"), Some("siken"));
+        assert_eq!(parser("https://example.com/"), None);
+        assert_eq!(parser("https://ap-siken.com.example.com/"), None);
+        assert_eq!(
+            parser_for(&Url::parse("https://example.com/").unwrap()).name(),
+            "siken"
+        );
+    }
+}
@@ -0,0 +1,802 @@
+use crate::destination::SendOutcome;
+use crate::extract::{Kakomon, Link, TextMode};
+use crate::format::{format_choices_with_answer, format_explanation, format_images, FormatOptions};
+use crate::state::SentMessage;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use url::Url;
+
+const SLACK_API_URL: &str = "https://slack.com/api/";
+
+// escape the characters having special meanings in mrkdwn
+pub fn escape_mrkdwn(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+// the texts of the links in the escaped statement replaced with `<url|text>`, looked for in the order.
+// a text with `|`, which cannot be in the label, is left as it is.
+fn link_mrkdwn(mondai: &str, links: &[Link]) -> String {
+    let mut text = String::new();
+    let mut rest = mondai;
+    for link in links {
+        let label = escape_mrkdwn(&link.text);
+        if label.is_empty() || label.contains('|') || link.url.as_str().contains('|') {
+            continue;
+        }
+        if let Some(idx) = rest.find(&label) {
+            text += &rest[..idx];
+            text += &format!("<{}|{}>", escape_mrkdwn(link.url.as_str()), label);
+            rest = &rest[idx + label.len()..];
+        }
+    }
+    text + rest
+}
+
+// The statement extracted in markdown into mrkdwn, which has `*bold*` and `<url|text>` and no escaping with `\`.
+// `_italic_` and the lists are the same in both.
+fn markdown_to_mrkdwn(markdown: &str) -> String {
+    let mut text = String::new();
+    let mut rest = markdown;
+    while let Some(c) = rest.chars().next() {
+        if let Some(escaped) = rest.strip_prefix('\\').and_then(|r| r.chars().next()) {
+            text += escape_mrkdwn(escaped.encode_utf8(&mut [0; 4])).as_str();
+            rest = &rest[1 + escaped.len_utf8()..];
+        } else if let Some(r) = rest.strip_prefix("**") {
+            text.push('*');
+            rest = r;
+        } else if let Some((label, url, r)) = markdown_link(rest) {
+            // `|` cannot be in the label nor the url of mrkdwn
+            text += &match label.contains('|') || url.contains('|') {
+                true => label,
+                false => format!("<{}|{}>", escape_mrkdwn(url), label),
+            };
+            rest = r;
+        } else {
+            text += escape_mrkdwn(c.encode_utf8(&mut [0; 4])).as_str();
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    text
+}
+
+// the label in mrkdwn, the url and the rest of `[label](url)...`
+fn markdown_link(text: &str) -> Option<(String, &str, &str)> {
+    let inner = text.strip_prefix('[')?;
+    let mut end = None;
+    let mut chars = inner.char_indices();
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            ']' => {
+                end = Some(idx);
+                break;
+            }
+            _ => {}
+        }
+    }
+    let end = end?;
+    let (url, rest) = inner[end + 1..].strip_prefix('(')?.split_once(')')?;
+    Some((markdown_to_mrkdwn(&inner[..end]), url, rest))
+}
+
+// the question in mrkdwn: the answer url, the statement, the numbered choices, the explanation and the image urls
+fn to_mrkdwn(kakomon: &Kakomon, options: &FormatOptions) -> String {
+    let mut text = String::new();
+    if let Some(url) = &kakomon.answer_url {
+        text += escape_mrkdwn(url.as_str()).as_str();
+        text += "\n";
+    }
+
+    let mondai = escape_mrkdwn(&kakomon.mondai);
+    if options.wrap_mondai_in_codeblock {
+        // "```" in the statement would close the block early
+        text += format!("```\n{}\n```\n", mondai.replace("```", "'''")).as_str();
+    } else {
+        text += match options.text_mode {
+            TextMode::Plain => link_mrkdwn(&mondai, &kakomon.links),
+            // the links are in the markdown
+            TextMode::Markdown => markdown_to_mrkdwn(&kakomon.mondai),
+        }
+        .as_str();
+        text += "\n";
+    }
+
+    text += escape_mrkdwn(&format_choices_with_answer(kakomon, options)).as_str();
+    text += escape_mrkdwn(&format_explanation(kakomon, options)).as_str();
+    text += escape_mrkdwn(&format_images(&kakomon.images, options)).as_str();
+    text
+}
+
+// whether slack takes it as the color of an attachment
+pub fn is_slack_color(color: &str) -> bool {
+    match color.strip_prefix('#') {
+        Some(hex) => hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()),
+        None => ["good", "warning", "danger"].contains(&color),
+    }
+}
+
+// the configured color of the host, or one derived from the hash of the host not to change between the runs
+pub fn host_color(host: &str, colors: &BTreeMap<String, String>) -> String {
+    if let Some(color) = colors.get(host) {
+        return color.clone();
+    }
+    let hash = Sha256::digest(host.as_bytes());
+    format!("#{:02x}{:02x}{:02x}", hash[0], hash[1], hash[2])
+}
+
+// Build the Block Kit payload of the question, with the difficulty and the `note` on what is trimmed in a context block.
+// The blocks are in an attachment colored by the host of the fetch url, and the title is the notification text.
+pub fn to_slack_body(
+    kakomon: &Kakomon,
+    fetch_url: &Url,
+    options: &FormatOptions,
+    note: Option<&str>,
+) -> Value {
+    let mut blocks = json!([
+            {
+                "type": "header",
+                "text": {
+                    "type": "plain_text",
+                    "text": kakomon.title,
+                    "emoji": true
+                }
+            },
+            {
+                "type": "divider",
+            },
+            {
+                "type": "section",
+                "text": {
+                    "type": "mrkdwn",
+                    "text": to_mrkdwn(kakomon, options),
+                }
+            }
+    ]);
+    let difficulty = kakomon
+        .difficulty
+        .as_ref()
+        .map(|difficulty| format!("難易度: {}", difficulty));
+    let elements: Vec<Value> = difficulty
+        .as_deref()
+        .into_iter()
+        .chain(note)
+        .map(|text| json!({ "type": "mrkdwn", "text": escape_mrkdwn(text) }))
+        .collect();
+    if let Some(blocks) = blocks.as_array_mut().filter(|_| !elements.is_empty()) {
+        blocks.push(json!({
+            "type": "context",
+            "elements": elements,
+        }));
+    }
+    let host = fetch_url.host_str().unwrap_or_default();
+    let mut body = json!({
+        "text": kakomon.title,
+        "attachments": [
+            {
+                "color": host_color(host, &options.slack_colors),
+                "blocks": blocks,
+            }
+        ]
+    });
+    if options.disable_unfurl {
+        body["unfurl_links"] = json!(false);
+        body["unfurl_media"] = json!(false);
+    }
+    body
+}
+
+// the limits of Block Kit checked before sending
+const MAX_BLOCKS: usize = 50;
+const MAX_SECTION_TEXT_CHARS: usize = 3000;
+const MAX_HEADER_TEXT_CHARS: usize = 150;
+
+// Checks the blocks of the body, in `blocks` and in the attachments, against the basic limits of Block Kit,
+// to find what slack rejects with `invalid_blocks` before sending it. Every violation is returned.
+pub fn validate_slack_blocks(body: &Value) -> Result<(), Vec<String>> {
+    let mut lists = vec![("blocks".to_string(), &body["blocks"])];
+    if let Some(attachments) = body["attachments"].as_array() {
+        for (i, attachment) in attachments.iter().enumerate() {
+            lists.push((format!("attachments[{}].blocks", i), &attachment["blocks"]));
+        }
+    }
+    let mut violations = Vec::new();
+    for (path, blocks) in lists {
+        let blocks = match blocks.as_array() {
+            Some(blocks) => blocks,
+            None => continue,
+        };
+        if blocks.len() > MAX_BLOCKS {
+            violations.push(format!(
+                "{} has {} blocks, more than {}",
+                path,
+                blocks.len(),
+                MAX_BLOCKS
+            ));
+        }
+        for (i, block) in blocks.iter().enumerate() {
+            let max = match block["type"].as_str() {
+                Some("section") => MAX_SECTION_TEXT_CHARS,
+                Some("header") => MAX_HEADER_TEXT_CHARS,
+                _ => continue,
+            };
+            let chars = block["text"]["text"]
+                .as_str()
+                .unwrap_or_default()
+                .chars()
+                .count();
+            if chars > max {
+                violations.push(format!(
+                    "the text of the {} {}[{}] has {} characters, more than {}",
+                    block["type"].as_str().unwrap_or_default(),
+                    path,
+                    i,
+                    chars,
+                    max
+                ));
+            }
+        }
+    }
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(violations)
+    }
+}
+
+// An error returned by slack, e.g. `invalid_blocks` of a webhook or `{"ok":false,"error":"..."}` of the web api.
+#[derive(Debug)]
+pub enum SlackError {
+    InvalidBlocks,
+    InvalidPayload,
+    ChannelNotFound,
+    ChannelIsArchived,
+    // the webhook was removed
+    NoService,
+    InvalidToken,
+    NotInChannel,
+    MessageNotFound,
+    RateLimited,
+    // an error code not listed above
+    Other(String),
+    // a status without an error code
+    Status(reqwest::StatusCode),
+    // the request itself failed
+    Request(reqwest::Error),
+}
+
+impl SlackError {
+    pub fn from_code(code: &str) -> Self {
+        match code {
+            "invalid_blocks" | "invalid_blocks_format" => SlackError::InvalidBlocks,
+            "invalid_payload" | "no_text" => SlackError::InvalidPayload,
+            "channel_not_found" => SlackError::ChannelNotFound,
+            "channel_is_archived" => SlackError::ChannelIsArchived,
+            "no_service" | "no_service_id" => SlackError::NoService,
+            "invalid_auth" | "invalid_token" | "not_authed" | "token_revoked" => {
+                SlackError::InvalidToken
+            }
+            "not_in_channel" => SlackError::NotInChannel,
+            "message_not_found" => SlackError::MessageNotFound,
+            "ratelimited" | "rate_limited" => SlackError::RateLimited,
+            _ => SlackError::Other(code.to_string()),
+        }
+    }
+
+    // the error of a response which is not ok. the body is the error code of a webhook,
+    // or the json of the web api.
+    fn from_response(status: reqwest::StatusCode, body: &str) -> Self {
+        if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            return SlackError::RateLimited;
+        }
+        let code = match serde_json::from_str::<Value>(body) {
+            Ok(json) => json["error"].as_str().unwrap_or_default().to_string(),
+            Err(_) => body.trim().to_string(),
+        };
+        match code.as_str() {
+            "" => SlackError::Status(status),
+            code => SlackError::from_code(code),
+        }
+    }
+
+    // whether sending it again may succeed
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            SlackError::RateLimited => true,
+            SlackError::Status(status) => status.is_server_error(),
+            SlackError::Request(e) => crate::retry::is_retryable_request(e),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for SlackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SlackError::InvalidBlocks => write!(f, "invalid_blocks: the blocks were rejected"),
+            SlackError::InvalidPayload => write!(f, "invalid_payload: the body was rejected"),
+            SlackError::ChannelNotFound => write!(f, "channel_not_found"),
+            SlackError::ChannelIsArchived => write!(f, "channel_is_archived"),
+            SlackError::NoService => write!(f, "no_service: the webhook is disabled or removed"),
+            SlackError::InvalidToken => write!(f, "invalid_auth: check the token"),
+            SlackError::NotInChannel => write!(f, "not_in_channel: invite the app to the channel"),
+            SlackError::MessageNotFound => write!(f, "message_not_found"),
+            SlackError::RateLimited => write!(f, "rate limited"),
+            SlackError::Other(code) => write!(f, "{}", code),
+            SlackError::Status(status) => write!(f, "HTTP {}", status),
+            SlackError::Request(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for SlackError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SlackError::Request(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for SlackError {
+    fn from(e: reqwest::Error) -> Self {
+        SlackError::Request(e)
+    }
+}
+
+// post the body to the webhook. the result is left to the caller to log, and to retry.
+pub async fn send_to_slack_webhook(
+    client: &reqwest::Client,
+    webhook: &Url,
+    body: String,
+) -> Result<SendOutcome, SlackError> {
+    let (status, body) = post_to_slack_webhook(client, webhook, body).await?;
+    Ok(SendOutcome {
+        status: Some(status),
+        body,
+        ..SendOutcome::default()
+    })
+}
+
+async fn post_to_slack_webhook(
+    client: &reqwest::Client,
+    webhook: &Url,
+    body: String,
+) -> Result<(reqwest::StatusCode, Option<String>), SlackError> {
+    let res = client
+        .post(webhook.to_string())
+        .header("Content-type", "application/json")
+        .body(body)
+        .send()
+        .await?;
+    let status = res.status();
+    let body = res.text().await.ok();
+    if !status.is_success() {
+        return Err(SlackError::from_response(
+            status,
+            body.as_deref().unwrap_or_default(),
+        ));
+    }
+    Ok((status, body))
+}
+
+// groups the questions into a thread, whose parent is the first question of the group
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ThreadBy {
+    // the questions fetched from the same url
+    #[default]
+    FetchUrl,
+    // the questions sent in a run
+    Run,
+}
+
+// `slack_api` in CONFIG. The token is not printed by `Debug`.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SlackApiConfig {
+    // a bot token with the `chat:write` scope
+    pub token: String,
+    pub channel: String,
+    #[serde(default)]
+    pub thread_by: ThreadBy,
+    // the name of the template in `templates` rendering the messages
+    #[serde(default)]
+    pub template: Option<String>,
+    // `false` not to post without removing it. defaults to `true`.
+    #[serde(default)]
+    pub enabled: Option<bool>,
+    // overrides `retry` of CONFIG
+    #[serde(default)]
+    pub retry: Option<crate::retry::RawRetry>,
+}
+
+impl fmt::Debug for SlackApiConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SlackApiConfig")
+            .field("token", &"***")
+            .field("channel", &self.channel)
+            .field("thread_by", &self.thread_by)
+            .field("template", &self.template)
+            .field("enabled", &self.enabled)
+            .field("retry", &self.retry)
+            .finish()
+    }
+}
+
+// Posts with `chat.postMessage` instead of an incoming webhook to reply in threads.
+#[derive(Debug)]
+pub struct SlackApi {
+    config: SlackApiConfig,
+    // the thread key to the `ts` of the parent message
+    threads: Mutex<HashMap<String, String>>,
+}
+
+impl SlackApi {
+    pub fn new(config: SlackApiConfig) -> Self {
+        SlackApi {
+            config,
+            threads: Mutex::default(),
+        }
+    }
+
+    // forget the threads of the last run
+    pub fn start_run(&self) {
+        if self.config.thread_by == ThreadBy::Run {
+            self.threads.lock().unwrap().clear();
+        }
+    }
+
+    fn thread_key(&self, fetch_url: &Url) -> String {
+        match self.config.thread_by {
+            ThreadBy::FetchUrl => fetch_url.to_string(),
+            ThreadBy::Run => String::new(),
+        }
+    }
+
+    // the `chat.postMessage` payload of the webhook `body`, replying to `thread_ts` if any
+    fn post_message_body(&self, body: &Value, thread_ts: Option<&str>) -> Value {
+        let mut payload = body.clone();
+        payload["channel"] = json!(self.config.channel);
+        if let Some(ts) = thread_ts {
+            payload["thread_ts"] = json!(ts);
+        }
+        payload
+    }
+
+    // call the web api method with the payload and return the response if it is ok
+    async fn call(
+        &self,
+        client: &reqwest::Client,
+        method: &str,
+        payload: &Value,
+    ) -> Result<Value, SlackError> {
+        let res = client
+            .post(format!("{}{}", SLACK_API_URL, method))
+            .bearer_auth(&self.config.token)
+            .json(payload)
+            .send()
+            .await?;
+        let status = res.status();
+        let body = res.text().await?;
+        debug!("{}: {}", status, body);
+        let json = match serde_json::from_str::<Value>(&body) {
+            Ok(json) if status.is_success() && json["ok"] == json!(true) => json,
+            _ => return Err(SlackError::from_response(status, &body)),
+        };
+        Ok(json)
+    }
+
+    pub async fn post_message(
+        &self,
+        client: &reqwest::Client,
+        body: &Value,
+        fetch_url: &Url,
+    ) -> Result<SentMessage, SlackError> {
+        let key = self.thread_key(fetch_url);
+        let thread_ts = self.threads.lock().unwrap().get(&key).cloned();
+        let payload = self.post_message_body(body, thread_ts.as_deref());
+
+        let res = self.call(client, "chat.postMessage", &payload).await?;
+        let message = SentMessage {
+            channel: res["channel"]
+                .as_str()
+                .unwrap_or(&self.config.channel)
+                .to_string(),
+            ts: res["ts"].as_str().unwrap_or_default().to_string(),
+        };
+        if thread_ts.is_none() {
+            self.threads.lock().unwrap().insert(key, message.ts.clone());
+        }
+        Ok(message)
+    }
+
+    // update the message posted before with `chat.update`, or post a new one if it is gone
+    pub async fn update_message(
+        &self,
+        client: &reqwest::Client,
+        body: &Value,
+        fetch_url: &Url,
+        message: &SentMessage,
+    ) -> Result<SentMessage, SlackError> {
+        let mut payload = self.post_message_body(body, None);
+        payload["channel"] = json!(message.channel);
+        payload["ts"] = json!(message.ts);
+        match self.call(client, "chat.update", &payload).await {
+            Ok(_) => Ok(message.clone()),
+            Err(SlackError::MessageNotFound) => {
+                log::warn!("{} is not found, posting a new message", message.ts);
+                self.post_message(client, body, fetch_url).await
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Review the diff of `src/snapshots` with `cargo insta review` when the format changes.
+    #[test]
+    fn test_to_slack_body_snapshot() {
+        let html_text = include_str!("../testdata/home.html");
+        let url = Url::parse("https://www.ap-siken.com/").unwrap();
+        let kakomon = crate::extract::extract_kakomon(
+            html_text,
+            url.clone(),
+            &crate::extract::ExtractOptions::default(),
+        )
+        .unwrap();
+
+        insta::assert_json_snapshot!(to_slack_body(
+            &kakomon,
+            &url,
+            &FormatOptions::default(),
+            None
+        ));
+        let options = FormatOptions {
+            wrap_mondai_in_codeblock: true,
+            ..Default::default()
+        };
+        insta::assert_json_snapshot!(
+            "to_slack_body_with_note",
+            to_slack_body(&kakomon, &url, &options, Some("画像を省略しました"))
+        );
+
+        let options = FormatOptions {
+            disable_unfurl: true,
+            ..Default::default()
+        };
+        let body = to_slack_body(&kakomon, &url, &options, None);
+        assert_eq!(body["unfurl_links"], json!(false));
+        assert_eq!(body["unfurl_media"], json!(false));
+        let body = to_slack_body(&kakomon, &url, &FormatOptions::default(), None);
+        assert!(body.get("unfurl_links").is_none());
+
+        // the difficulty is in the context block before the note
+        let kakomon = Kakomon {
+            difficulty: Some("★★★".to_string()),
+            ..kakomon
+        };
+        let body = to_slack_body(&kakomon, &url, &FormatOptions::default(), None);
+        assert_eq!(
+            body["attachments"][0]["blocks"][3],
+            json!({"type": "context", "elements": [{"type": "mrkdwn", "text": "難易度: ★★★"}]})
+        );
+        let body = to_slack_body(&kakomon, &url, &FormatOptions::default(), Some("a & b"));
+        assert_eq!(
+            body["attachments"][0]["blocks"][3]["elements"][1],
+            json!({"type": "mrkdwn", "text": "a &amp; b"})
+        );
+    }
+
+    #[test]
+    fn test_validate_slack_blocks() {
+        let url = Url::parse("https://www.ap-siken.com/").unwrap();
+        let kakomon = Kakomon {
+            title: "問1".to_string(),
+            mondai: "問題文".to_string(),
+            ..Default::default()
+        };
+        let body = to_slack_body(&kakomon, &url, &FormatOptions::default(), Some("note"));
+        assert_eq!(validate_slack_blocks(&body), Ok(()));
+        assert_eq!(validate_slack_blocks(&json!({"text": "問1"})), Ok(()));
+
+        let long = Kakomon {
+            title: "題".repeat(151),
+            mondai: "あ".repeat(3000),
+            ..kakomon
+        };
+        let body = to_slack_body(&long, &url, &FormatOptions::default(), None);
+        assert_eq!(
+            validate_slack_blocks(&body),
+            Err(vec![
+                "the text of the header attachments[0].blocks[0] has 151 characters, more than 150"
+                    .to_string(),
+                // with the line break after the statement
+                "the text of the section attachments[0].blocks[2] has 3001 characters, more than 3000"
+                    .to_string(),
+            ])
+        );
+
+        let blocks = vec![json!({"type": "divider"}); 51];
+        assert_eq!(
+            validate_slack_blocks(&json!({ "blocks": blocks })),
+            Err(vec!["blocks has 51 blocks, more than 50".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_host_color() {
+        let colors = BTreeMap::from([("www.ap-siken.com".to_string(), "#36a64f".to_string())]);
+        assert_eq!(host_color("www.ap-siken.com", &colors), "#36a64f");
+        let color = host_color("www.nw-siken.com", &colors);
+        assert!(is_slack_color(&color));
+        assert_eq!(host_color("www.nw-siken.com", &BTreeMap::new()), color);
+        assert_ne!(host_color("www.db-siken.com", &colors), color);
+
+        assert!(is_slack_color("good"));
+        assert!(!is_slack_color("#36a64"));
+        assert!(!is_slack_color("green"));
+    }
+
+    #[test]
+    fn test_to_mrkdwn_wrap_mondai_in_codeblock() {
+        let kakomon = Kakomon {
+            title: "問1".to_string(),
+            answer_url: Some(
+                Url::parse("https://www.ap-siken.com/kakomon/21_haru/q1.html").unwrap(),
+            ),
+            mondai: "`cat a.txt | grep -v x > b.txt` の結果はどれか。\n```".to_string(),
+            choices: vec!["a & b".to_string(), "<c>".to_string()],
+            ..Default::default()
+        };
+
+        let mut options = FormatOptions::default();
+        assert_eq!(
+            to_mrkdwn(&kakomon, &options),
+            "https://www.ap-siken.com/kakomon/21_haru/q1.html\n`cat a.txt | grep -v x &gt; b.txt` の結果はどれか。\n```\n1. a &amp; b\n2. &lt;c&gt;\n"
+        );
+
+        options.wrap_mondai_in_codeblock = true;
+        assert_eq!(
+            to_mrkdwn(&kakomon, &options),
+            "https://www.ap-siken.com/kakomon/21_haru/q1.html\n```\n`cat a.txt | grep -v x &gt; b.txt` の結果はどれか。\n'''\n```\n1. a &amp; b\n2. &lt;c&gt;\n"
+        );
+    }
+
+    #[test]
+    fn test_to_mrkdwn_links() {
+        let link = |url: &str, text: &str| Link {
+            url: Url::parse(url).unwrap(),
+            text: text.to_string(),
+        };
+        let mut kakomon = Kakomon {
+            title: "問1".to_string(),
+            mondai: "出典: IPA と R&D <資料> と IPA と a|b".to_string(),
+            links: vec![
+                link("https://www.ipa.go.jp/", "IPA"),
+                link("https://example.com/?a=1&b=2", "R&D <資料>"),
+                link("https://example.com/", "見つからない"),
+                link("https://example.com/", "IPA"),
+                link("https://example.com/", "a|b"),
+            ],
+            ..Default::default()
+        };
+
+        let mut options = FormatOptions::default();
+        assert_eq!(
+            to_mrkdwn(&kakomon, &options),
+            "出典: <https://www.ipa.go.jp/|IPA> と <https://example.com/?a=1&amp;b=2|R&amp;D &lt;資料&gt;> と <https://example.com/|IPA> と a|b\n"
+        );
+
+        // no link in the code block
+        options.wrap_mondai_in_codeblock = true;
+        assert_eq!(
+            to_mrkdwn(&kakomon, &options),
+            "```\n出典: IPA と R&amp;D &lt;資料&gt; と IPA と a|b\n```\n"
+        );
+        kakomon.links.clear();
+        options.wrap_mondai_in_codeblock = false;
+        assert_eq!(
+            to_mrkdwn(&kakomon, &options),
+            "出典: IPA と R&amp;D &lt;資料&gt; と IPA と a|b\n"
+        );
+    }
+
+    #[test]
+    fn test_markdown_to_mrkdwn() {
+        assert_eq!(
+            markdown_to_mrkdwn(
+                r"**重要** な _語_ は [IPA](https://www.ipa.go.jp/?a=1&b=2) と a\*b\_c <d>"
+            ),
+            "*重要* な _語_ は <https://www.ipa.go.jp/?a=1&amp;b=2|IPA> と a*b_c &lt;d&gt;"
+        );
+        assert_eq!(
+            markdown_to_mrkdwn(
+                r"[**a\]b**](https://example.com/) [a|b](https://example.com/) [x] ["
+            ),
+            "<https://example.com/|*a]b*> a|b [x] ["
+        );
+        assert_eq!(
+            markdown_to_mrkdwn("- 一つ目\n1. 二つ目"),
+            "- 一つ目\n1. 二つ目"
+        );
+
+        let kakomon = Kakomon {
+            title: "問1".to_string(),
+            mondai: "[出典](https://www.ipa.go.jp/)".to_string(),
+            ..Default::default()
+        };
+        let options = FormatOptions {
+            text_mode: TextMode::Markdown,
+            ..FormatOptions::default()
+        };
+        assert_eq!(
+            to_mrkdwn(&kakomon, &options),
+            "<https://www.ipa.go.jp/|出典>\n"
+        );
+    }
+
+    #[test]
+    fn test_slack_api_post_message_body() {
+        let api = SlackApi::new(SlackApiConfig {
+            token: "xoxb-token".to_string(),
+            channel: "C0123".to_string(),
+            thread_by: ThreadBy::Run,
+            template: None,
+            enabled: None,
+            retry: None,
+        });
+        let debug = format!("{:?}", api);
+        assert!(debug.contains("C0123"));
+        assert!(!debug.contains("xoxb-token"));
+        let kakomon = Kakomon {
+            title: "問1".to_string(),
+            mondai: "どれか。".to_string(),
+            ..Default::default()
+        };
+        let url = Url::parse("https://www.ap-siken.com/").unwrap();
+        let body = to_slack_body(&kakomon, &url, &FormatOptions::default(), None);
+
+        let payload = api.post_message_body(&body, None);
+        assert_eq!(payload["channel"], "C0123");
+        assert_eq!(payload["text"], "問1");
+        assert_eq!(payload["attachments"], body["attachments"]);
+        assert!(payload.get("thread_ts").is_none());
+        let payload = api.post_message_body(&body, Some("1700000000.000100"));
+        assert_eq!(payload["thread_ts"], "1700000000.000100");
+        assert_eq!(api.thread_key(&url), "");
+    }
+
+    #[test]
+    fn test_slack_error_from_response() {
+        use reqwest::StatusCode;
+
+        let error = SlackError::from_response(StatusCode::BAD_REQUEST, "invalid_blocks");
+        assert!(matches!(error, SlackError::InvalidBlocks));
+        assert!(!error.is_retryable());
+        let error = SlackError::from_response(
+            StatusCode::OK,
+            r#"{"ok":false,"error":"channel_not_found"}"#,
+        );
+        assert!(matches!(error, SlackError::ChannelNotFound));
+        let error = SlackError::from_response(StatusCode::NOT_FOUND, "no_such_error\n");
+        assert!(matches!(error, SlackError::Other(code) if code == "no_such_error"));
+
+        let error = SlackError::from_response(StatusCode::TOO_MANY_REQUESTS, "");
+        assert!(error.is_retryable());
+        let error = SlackError::from_response(StatusCode::SERVICE_UNAVAILABLE, "");
+        assert!(matches!(error, SlackError::Status(_)));
+        assert!(error.is_retryable());
+    }
+}
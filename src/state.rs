@@ -0,0 +1,522 @@
+use crate::bloom::BloomFilter;
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+use url::Url;
+
+use crate::extract::Kakomon;
+
+// the first bytes of a gzip stream
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+// What is kept between runs.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct State {
+    // keys of the questions already sent
+    #[serde(default)]
+    pub seen: BTreeSet<String>,
+    // keys of the questions already sent with `seen_store: bloom`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seen_bloom: Option<BloomFilter>,
+    // keys of the questions to the messages posted with `slack_api`, to update them later
+    #[serde(default)]
+    pub slack_messages: BTreeMap<String, SentMessage>,
+    // the fetch urls to when they were fetched last
+    #[serde(default)]
+    pub fetch_history: BTreeMap<String, FetchHistory>,
+    // the questions failed to send, sent first on the next run
+    #[serde(default)]
+    pub pending: Vec<PendingQuestion>,
+    // the url keys of the questions sent to the hashes of their contents with `notify_updates`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub contents: BTreeMap<String, ContentHashes>,
+    // the questions held in the quiet hours, which are only kept in memory
+    #[serde(skip)]
+    pub held: Vec<QueuedQuestion>,
+    #[serde(skip)]
+    store: SeenStore,
+}
+
+// a message posted with `chat.postMessage`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SentMessage {
+    pub channel: String,
+    pub ts: String,
+}
+
+// where the keys of the questions sent are kept
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum SeenStore {
+    // every key in `seen`
+    #[default]
+    Exact,
+    // a bloom filter of the size, which may take a few unseen questions as seen
+    Bloom {
+        capacity: usize,
+        false_positive_rate: f64,
+    },
+}
+
+// what makes two questions the same in `seen`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DedupKey {
+    // the sha256 of the question url (`Kakomon::key`)
+    #[default]
+    Url,
+    // the sha256 of the title and the text, so a changed question is sent again
+    Content,
+}
+
+impl DedupKey {
+    pub fn of(self, kakomon: &Kakomon) -> String {
+        let source = match self {
+            DedupKey::Url => kakomon.key(),
+            DedupKey::Content => format!("{}\n{}", kakomon.title, kakomon.text()),
+        };
+        format!("{:x}", Sha256::digest(source.as_bytes()))
+    }
+
+    // the head of the key, enough to tell the questions apart in the logs
+    pub fn short_of(self, kakomon: &Kakomon) -> String {
+        self.of(kakomon)[..SHORT_KEY_LEN].to_string()
+    }
+}
+
+// the hex digits of the keys shown in the logs
+const SHORT_KEY_LEN: usize = 12;
+
+fn is_hash(key: &str) -> bool {
+    key.len() == 64 && key.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+// how fetching a url went, updated on both success and failure
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct FetchHistory {
+    pub last_fetched_at: Option<DateTime<Utc>>,
+    pub last_success_at: Option<DateTime<Utc>>,
+    // the reason of the last failure, cleared by a success
+    pub last_error: Option<String>,
+    pub consecutive_failures: u32,
+    // when the daemon fetches it next
+    #[serde(default)]
+    pub next_run_at: Option<DateTime<Utc>>,
+}
+
+// a question failing to send is given up after this many attempts unless `pending_max_attempts` is configured
+pub const DEFAULT_PENDING_MAX_ATTEMPTS: u32 = 5;
+
+// a question to be sent, with how it was decided to be sent when it was fetched
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueuedQuestion {
+    // the fetch url it was found in
+    pub url: Url,
+    pub kakomon: Kakomon,
+    // sent before, to update the messages with `update_sent_messages`
+    pub seen: bool,
+    // the parts changed with `notify_updates`
+    pub update: Option<String>,
+}
+
+// a question failed to send to all the destinations
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PendingQuestion {
+    // the fetch url it was found in
+    pub url: Url,
+    pub kakomon: Kakomon,
+    // the times it failed to send, including the first one
+    pub attempts: u32,
+    pub last_error: String,
+    // the parts changed with `notify_updates`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub update: Option<String>,
+}
+
+// The short hashes of the parts of a question, telling which of them changed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ContentHashes {
+    pub title: String,
+    pub mondai: String,
+    pub choices: String,
+    pub images: String,
+    pub answer: String,
+    pub explanation: String,
+}
+
+impl ContentHashes {
+    pub fn of(kakomon: &Kakomon) -> Self {
+        let hash = |text: &str| format!("{:x}", Sha256::digest(text.as_bytes()))[..16].to_string();
+        let images = kakomon
+            .images
+            .iter()
+            .map(Url::as_str)
+            .collect::<Vec<_>>()
+            .join("\n");
+        ContentHashes {
+            title: hash(&kakomon.title),
+            mondai: hash(&kakomon.mondai),
+            choices: hash(&kakomon.choices.join("\n")),
+            images: hash(&images),
+            answer: hash(kakomon.answer.as_deref().unwrap_or_default()),
+            explanation: hash(kakomon.explanation.as_deref().unwrap_or_default()),
+        }
+    }
+
+    // the names of the parts differing from `previous`
+    pub fn changed_from(&self, previous: &ContentHashes) -> Vec<&'static str> {
+        [
+            ("タイトル", &self.title, &previous.title),
+            ("問題文", &self.mondai, &previous.mondai),
+            ("選択肢", &self.choices, &previous.choices),
+            ("画像", &self.images, &previous.images),
+            ("正解", &self.answer, &previous.answer),
+            ("解説", &self.explanation, &previous.explanation),
+        ]
+        .into_iter()
+        .filter(|(_, hash, previous)| hash != previous)
+        .map(|(name, _, _)| name)
+        .collect()
+    }
+}
+
+// how a question differs from the one sent last with the same url
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContentChange {
+    New,
+    // the names of the parts changed
+    Updated(Vec<&'static str>),
+    Unchanged,
+}
+
+// `*.gz` state files are compressed unless `state_compress` says otherwise
+pub fn is_compressed_path(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "gz")
+}
+
+impl State {
+    // Both compressed and plain json files are read regardless of the extension,
+    // so switching `state_compress` converts the file on the next save.
+    pub fn load(path: &Path) -> Result<State, Box<dyn std::error::Error>> {
+        if !path.exists() {
+            return Ok(State::default());
+        }
+
+        let bytes = fs::read(path)?;
+        let json = if bytes.starts_with(&GZIP_MAGIC) {
+            let mut json = String::new();
+            GzDecoder::new(bytes.as_slice()).read_to_string(&mut json)?;
+            json
+        } else {
+            String::from_utf8(bytes)?
+        };
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    // The states saved before `dedup_key` have the question urls as the keys, which
+    // are the same as the `url` keys once hashed. They cannot be `content` keys.
+    pub fn migrate_keys(&mut self) {
+        let hash = |key: String| {
+            if is_hash(&key) {
+                key
+            } else {
+                format!("{:x}", Sha256::digest(key.as_bytes()))
+            }
+        };
+        self.seen = std::mem::take(&mut self.seen)
+            .into_iter()
+            .map(hash)
+            .collect();
+        self.slack_messages = std::mem::take(&mut self.slack_messages)
+            .into_iter()
+            .map(|(key, message)| (hash(key), message))
+            .collect();
+    }
+
+    // Switching to `bloom` moves the keys into the filter. The filter is still checked
+    // after switching back to `exact`, as its keys cannot be listed.
+    pub fn use_seen_store(&mut self, store: SeenStore) {
+        if let SeenStore::Bloom {
+            capacity,
+            false_positive_rate,
+        } = store
+        {
+            let bloom = self
+                .seen_bloom
+                .get_or_insert_with(|| BloomFilter::new(capacity, false_positive_rate));
+            for key in std::mem::take(&mut self.seen) {
+                bloom.insert(&key);
+            }
+        }
+        self.store = store;
+    }
+
+    pub fn is_seen(&self, key: &str) -> bool {
+        self.seen.contains(key)
+            || self
+                .seen_bloom
+                .as_ref()
+                .is_some_and(|bloom| bloom.contains(key))
+    }
+
+    pub fn content_change(&self, kakomon: &Kakomon) -> ContentChange {
+        match self.contents.get(&DedupKey::Url.of(kakomon)) {
+            None => ContentChange::New,
+            Some(previous) => match ContentHashes::of(kakomon).changed_from(previous) {
+                changed if changed.is_empty() => ContentChange::Unchanged,
+                changed => ContentChange::Updated(changed),
+            },
+        }
+    }
+
+    pub fn record_content(&mut self, kakomon: &Kakomon) {
+        self.contents
+            .insert(DedupKey::Url.of(kakomon), ContentHashes::of(kakomon));
+    }
+
+    pub fn insert_seen(&mut self, key: String) {
+        match (self.store, self.seen_bloom.as_mut()) {
+            (SeenStore::Bloom { .. }, Some(bloom)) => {
+                bloom.insert(&key);
+                if bloom.len == bloom.capacity() + 1 {
+                    warn!(
+                        "the bloom filter has more than {} keys, increase `bloom_capacity`",
+                        bloom.capacity()
+                    );
+                }
+            }
+            _ => {
+                self.seen.insert(key);
+            }
+        }
+    }
+
+    pub fn record_fetch(&mut self, url: &Url, at: DateTime<Utc>, result: Result<(), String>) {
+        let history = self.fetch_history.entry(url.to_string()).or_default();
+        history.last_fetched_at = Some(at);
+        match result {
+            Ok(()) => {
+                history.last_success_at = Some(at);
+                history.last_error = None;
+                history.consecutive_failures = 0;
+            }
+            Err(reason) => {
+                history.last_error = Some(reason);
+                history.consecutive_failures += 1;
+            }
+        }
+    }
+
+    pub fn record_next_run(&mut self, url: &Url, at: DateTime<Utc>) {
+        self.fetch_history
+            .entry(url.to_string())
+            .or_default()
+            .next_run_at = Some(at);
+    }
+
+    pub fn save(&self, path: &Path, compress: bool) -> Result<(), Box<dyn std::error::Error>> {
+        let json = serde_json::to_string(self)?;
+        let bytes = if compress {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(json.as_bytes())?;
+            encoder.finish()?
+        } else {
+            json.into_bytes()
+        };
+
+        // write to a temporary file first not to break the state on failure
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, bytes)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_state_compress_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let plain = dir.path().join("state.json");
+        let compressed = dir.path().join("state.json.gz");
+        assert!(!is_compressed_path(&plain));
+        assert!(is_compressed_path(&compressed));
+
+        let mut state = State::load(&plain).unwrap();
+        assert!(state.seen.is_empty());
+        state
+            .seen
+            .insert("https://www.ap-siken.com/kakomon/21_haru/q31.html".to_string());
+
+        state.save(&compressed, true).unwrap();
+        assert!(fs::read(&compressed).unwrap().starts_with(&GZIP_MAGIC));
+        let loaded = State::load(&compressed).unwrap();
+        assert_eq!(loaded.seen, state.seen);
+
+        // convert the compressed file back to plain json
+        loaded.save(&plain, false).unwrap();
+        let json = fs::read_to_string(&plain).unwrap();
+        assert!(json.contains("q31.html"));
+        assert_eq!(State::load(&plain).unwrap().seen, state.seen);
+    }
+
+    #[test]
+    fn test_state_slack_messages() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        // the state files saved before `slack_messages` are still read
+        fs::write(&path, r#"{"seen": ["q31"]}"#).unwrap();
+        let mut state = State::load(&path).unwrap();
+        assert!(state.slack_messages.is_empty());
+
+        let message = SentMessage {
+            channel: "C0123".to_string(),
+            ts: "1700000000.000100".to_string(),
+        };
+        state
+            .slack_messages
+            .insert("q31".to_string(), message.clone());
+        state.save(&path, false).unwrap();
+        assert_eq!(State::load(&path).unwrap().slack_messages["q31"], message);
+    }
+
+    #[test]
+    fn test_state_fetch_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        let url = Url::parse("https://www.ap-siken.com/").unwrap();
+        let at = |hour| Utc.with_ymd_and_hms(2024, 1, 1, hour, 0, 0).unwrap();
+
+        let mut state = State::default();
+        state.record_fetch(&url, at(1), Ok(()));
+        state.record_fetch(&url, at(2), Err("timeout".to_string()));
+        state.record_fetch(&url, at(3), Err("HTTP 503 Service Unavailable".to_string()));
+        state.save(&path, false).unwrap();
+
+        let history = State::load(&path).unwrap().fetch_history[url.as_str()].clone();
+        assert_eq!(
+            history,
+            FetchHistory {
+                last_fetched_at: Some(at(3)),
+                last_success_at: Some(at(1)),
+                last_error: Some("HTTP 503 Service Unavailable".to_string()),
+                consecutive_failures: 2,
+                next_run_at: None,
+            }
+        );
+
+        state.record_fetch(&url, at(4), Ok(()));
+        let history = &state.fetch_history[url.as_str()];
+        assert_eq!(history.last_success_at, Some(at(4)));
+        assert_eq!(history.last_error, None);
+        assert_eq!(history.consecutive_failures, 0);
+    }
+
+    #[test]
+    fn test_state_migrate_keys() {
+        let kakomon = Kakomon {
+            title: "令和3年春期 問31".to_string(),
+            answer_url: Some(
+                Url::parse("https://www.ap-siken.com/kakomon/03_haru/q31.html").unwrap(),
+            ),
+            mondai: "問題文".to_string(),
+            choices: vec!["ア".to_string(), "イ".to_string()],
+            ..Default::default()
+        };
+        let url_key = DedupKey::Url.of(&kakomon);
+        let content_key = DedupKey::Content.of(&kakomon);
+        assert!(is_hash(&url_key));
+        assert!(is_hash(&content_key));
+        assert_ne!(url_key, content_key);
+
+        // the content key changes with the text, but the url key does not
+        let mut changed = kakomon.clone();
+        changed.mondai = "直された問題文".to_string();
+        assert_eq!(DedupKey::Url.of(&changed), url_key);
+        assert_ne!(DedupKey::Content.of(&changed), content_key);
+        assert_eq!(DedupKey::Content.short_of(&kakomon), content_key[..12]);
+        assert_ne!(DedupKey::Content.short_of(&changed), content_key[..12]);
+
+        let mut state: State = serde_json::from_str(&format!(
+            r#"{{"seen": ["{}", "{}"], "slack_messages": {{"{}": {{"channel": "C0123", "ts": "1.0"}}}}}}"#,
+            kakomon.key(),
+            content_key,
+            kakomon.key()
+        ))
+        .unwrap();
+        state.migrate_keys();
+        assert!(state.seen.contains(&url_key));
+        assert!(state.seen.contains(&content_key));
+        assert_eq!(state.seen.len(), 2);
+        assert!(state.slack_messages.contains_key(&url_key));
+    }
+
+    #[test]
+    fn test_state_content_change() {
+        let kakomon = Kakomon {
+            title: "令和3年春期 問31".to_string(),
+            answer_url: Some(
+                Url::parse("https://www.ap-siken.com/kakomon/03_haru/q31.html").unwrap(),
+            ),
+            mondai: "問題文".to_string(),
+            choices: vec!["ア".to_string(), "イ".to_string()],
+            ..Default::default()
+        };
+        let mut state = State::default();
+        assert_eq!(state.content_change(&kakomon), ContentChange::New);
+        state.record_content(&kakomon);
+        assert_eq!(state.content_change(&kakomon), ContentChange::Unchanged);
+
+        let mut changed = kakomon.clone();
+        changed.mondai = "直された問題文".to_string();
+        changed.answer = Some("イ".to_string());
+        assert_eq!(
+            state.content_change(&changed),
+            ContentChange::Updated(vec!["問題文", "正解"])
+        );
+
+        let json = serde_json::to_string(&state).unwrap();
+        let loaded: State = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.contents, state.contents);
+        assert!(!serde_json::to_string(&State::default())
+            .unwrap()
+            .contains("contents"));
+    }
+
+    #[test]
+    fn test_state_seen_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        let bloom = SeenStore::Bloom {
+            capacity: 100,
+            false_positive_rate: 0.01,
+        };
+
+        let mut state = State::default();
+        state.insert_seen("q1".to_string());
+        // the exact keys move into the filter
+        state.use_seen_store(bloom);
+        assert!(state.seen.is_empty());
+        state.insert_seen("q2".to_string());
+        assert!(state.is_seen("q1") && state.is_seen("q2"));
+        assert!(!state.is_seen("q3"));
+        state.save(&path, false).unwrap();
+
+        // the filter is still checked with `exact`
+        let mut state = State::load(&path).unwrap();
+        state.use_seen_store(SeenStore::Exact);
+        state.insert_seen("q3".to_string());
+        assert!(state.is_seen("q1") && state.is_seen("q3"));
+        assert_eq!(state.seen.len(), 1);
+    }
+}
@@ -0,0 +1,210 @@
+// exit codes of the process
+pub const EXIT_SUCCESS: u8 = 0;
+// something failed during the run
+pub const EXIT_FAILURE: u8 = 1;
+// the config (or the state file) could not be loaded
+pub const EXIT_CONFIG_ERROR: u8 = 2;
+// none of the urls could be fetched
+pub const EXIT_NETWORK_ERROR: u8 = 3;
+
+use serde_json::{json, Value};
+use url::Url;
+
+// What happened in a run.
+#[derive(Debug, Default, Clone)]
+pub struct RunStats {
+    pub fetched: usize,
+    pub fetch_failed: usize,
+    pub sent: usize,
+    pub send_failed: usize,
+    // the retries of the questions sent in the end
+    pub retried: usize,
+    // the questions sent once in the run though found more than once, e.g. in two fetch urls
+    pub duplicates: usize,
+    // the questions extracted from the fetched pages, including the ones not sent
+    pub extracted: usize,
+    // the questions left out by `filter_years` and `filter_seasons`
+    pub filtered: usize,
+    // e.g. failures to save the state file
+    pub errors: usize,
+    // the url failed and why
+    pub failures: Vec<(Url, String)>,
+    // the url fetched without a question extracted and why, not counted as failed
+    // unless `--fail-on-extract-error`
+    pub extract_failures: Vec<(Url, String)>,
+}
+
+impl RunStats {
+    pub fn add_failure(&mut self, url: &Url, reason: String) {
+        self.failures.push((url.clone(), reason));
+    }
+
+    // a table of the counts followed by the failed urls
+    pub fn summary_text(&self) -> String {
+        let mut text = format!(
+            "{:>8} {:>8} {:>8}\n{:>8} {:>8} {:>8}\n",
+            "fetched",
+            "sent",
+            "failed",
+            self.fetched,
+            self.sent,
+            self.failed()
+        );
+        if self.duplicates > 0 {
+            text += format!("\nskipped {} duplicates\n", self.duplicates).as_str();
+        }
+        if self.filtered > 0 {
+            text += format!("\nfiltered out {} questions\n", self.filtered).as_str();
+        }
+        if !self.failures.is_empty() {
+            text += "\nfailed urls:\n";
+            for (url, reason) in self.failures.iter() {
+                text += format!("  {} ({})\n", url, reason).as_str();
+            }
+        }
+        if !self.extract_failures.is_empty() {
+            text += "\nextract failed urls:\n";
+            for (url, reason) in self.extract_failures.iter() {
+                text += format!("  {} ({})\n", url, reason).as_str();
+            }
+        }
+        text
+    }
+
+    pub fn summary_json(&self) -> Value {
+        json!({
+            "fetched": self.fetched,
+            "fetch_failed": self.fetch_failed,
+            "sent": self.sent,
+            "send_failed": self.send_failed,
+            "retried": self.retried,
+            "duplicates": self.duplicates,
+            "extracted": self.extracted,
+            "filtered": self.filtered,
+            "errors": self.errors,
+            "failures": self
+                .failures
+                .iter()
+                .map(|(url, reason)| json!({"url": url.as_str(), "reason": reason}))
+                .collect::<Vec<_>>(),
+            "extract_failures": self
+                .extract_failures
+                .iter()
+                .map(|(url, reason)| json!({"url": url.as_str(), "reason": reason}))
+                .collect::<Vec<_>>(),
+        })
+    }
+
+    pub fn failed(&self) -> usize {
+        self.fetch_failed + self.send_failed + self.errors
+    }
+
+    pub fn exit_code(&self) -> u8 {
+        if self.fetched == 0 && self.fetch_failed > 0 {
+            EXIT_NETWORK_ERROR
+        } else if self.failed() > 0 {
+            EXIT_FAILURE
+        } else {
+            EXIT_SUCCESS
+        }
+    }
+}
+
+// a short reason of the failure such as "timeout" or "HTTP 404 Not Found"
+pub fn describe_error(e: &(dyn std::error::Error + 'static)) -> String {
+    // e.g. a reqwest error wrapped in `SlackError`
+    let reqwest_error = e
+        .downcast_ref::<reqwest::Error>()
+        .or_else(|| e.source()?.downcast_ref::<reqwest::Error>());
+    match reqwest_error {
+        Some(e) if e.is_timeout() => "timeout".to_string(),
+        Some(e) if e.status().is_some() => format!("HTTP {}", e.status().unwrap()),
+        Some(e) if e.is_connect() => "connection failed".to_string(),
+        _ => e.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_stats_exit_code() {
+        let stats = RunStats {
+            fetched: 2,
+            sent: 2,
+            ..Default::default()
+        };
+        assert_eq!(stats.exit_code(), EXIT_SUCCESS);
+        assert_eq!(RunStats::default().exit_code(), EXIT_SUCCESS);
+
+        let stats = RunStats {
+            fetched: 2,
+            sent: 1,
+            send_failed: 1,
+            ..Default::default()
+        };
+        assert_eq!(stats.exit_code(), EXIT_FAILURE);
+
+        let stats = RunStats {
+            fetched: 1,
+            fetch_failed: 1,
+            ..Default::default()
+        };
+        assert_eq!(stats.exit_code(), EXIT_FAILURE);
+
+        let stats = RunStats {
+            fetch_failed: 3,
+            ..Default::default()
+        };
+        assert_eq!(stats.exit_code(), EXIT_NETWORK_ERROR);
+    }
+
+    #[test]
+    fn test_run_stats_summary() {
+        let mut stats = RunStats {
+            fetched: 2,
+            sent: 1,
+            send_failed: 1,
+            fetch_failed: 1,
+            ..Default::default()
+        };
+        let url = Url::parse("https://www.nw-siken.com/").unwrap();
+        stats.add_failure(&url, "timeout".to_string());
+
+        assert_eq!(
+            stats.summary_text(),
+            " fetched     sent   failed\n       2        1        2\n\nfailed urls:\n  https://www.nw-siken.com/ (timeout)\n"
+        );
+        assert_eq!(
+            stats.summary_json()["failures"],
+            json!([{"url": "https://www.nw-siken.com/", "reason": "timeout"}])
+        );
+        assert_eq!(stats.summary_json()["fetched"], json!(2));
+        assert_eq!(stats.summary_json()["retried"], json!(0));
+
+        stats.duplicates = 3;
+        assert!(stats
+            .summary_text()
+            .contains("       2        1        2\n\nskipped 3 duplicates\n\nfailed urls:\n"));
+        assert_eq!(stats.summary_json()["duplicates"], json!(3));
+        stats.filtered = 2;
+        assert!(stats
+            .summary_text()
+            .contains("\nskipped 3 duplicates\n\nfiltered out 2 questions\n\nfailed urls:\n"));
+        assert_eq!(stats.summary_json()["filtered"], json!(2));
+
+        let url = Url::parse("https://www.ap-siken.com/").unwrap();
+        stats
+            .extract_failures
+            .push((url, "no div.kako nor question page".to_string()));
+        assert!(stats.summary_text().ends_with(
+            "\nextract failed urls:\n  https://www.ap-siken.com/ (no div.kako nor question page)\n"
+        ));
+        assert_eq!(
+            stats.summary_json()["extract_failures"][0]["reason"],
+            json!("no div.kako nor question page")
+        );
+        assert_eq!(stats.failed(), 2);
+    }
+}
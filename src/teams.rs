@@ -0,0 +1,19 @@
+use crate::extract::Kakomon;
+use crate::format::{format_text, FormatOptions};
+use serde_json::{json, Value};
+
+// build the MessageCard payload of an incoming webhook
+pub fn to_teams_body(kakomon: &Kakomon, options: &FormatOptions, note: Option<&str>) -> Value {
+    let mut text = format_text(kakomon, options);
+    if let Some(note) = note {
+        text += format!("_{}_\n", note).as_str();
+    }
+    json!({
+        "@type": "MessageCard",
+        "@context": "https://schema.org/extensions",
+        "summary": kakomon.title,
+        "title": kakomon.title,
+        // a single line break is ignored in the markdown of teams
+        "text": text.replace('\n', "\n\n"),
+    })
+}
@@ -0,0 +1,31 @@
+use crate::extract::Kakomon;
+use crate::format::{format_text, FormatOptions};
+use serde_json::{json, Value};
+use url::Url;
+
+// the chat is given as `?chat_id=...` of the webhook url
+pub fn chat_id(webhook: &Url) -> Option<String> {
+    webhook
+        .query_pairs()
+        .find(|(key, _)| key == "chat_id")
+        .map(|(_, value)| value.into_owned())
+}
+
+// build the sendMessage payload
+pub fn to_telegram_body(
+    webhook: &Url,
+    kakomon: &Kakomon,
+    options: &FormatOptions,
+    note: Option<&str>,
+) -> Value {
+    let chat_id = chat_id(webhook);
+    let mut text = format!("{}\n\n{}", kakomon.title, format_text(kakomon, options));
+    if let Some(note) = note {
+        text += format!("{}\n", note).as_str();
+    }
+    json!({
+        "chat_id": chat_id,
+        "text": text,
+        "disable_web_page_preview": true,
+    })
+}
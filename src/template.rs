@@ -0,0 +1,108 @@
+use crate::extract::selector;
+use scraper::Html;
+use url::Url;
+
+// A fetch url with a `*` in a path segment, e.g. `https://www.ap-siken.com/kakomon/*/index.html`.
+// The `*` is one path segment, or a part of it such as `*_haru`.
+pub fn is_url_template(url: &Url) -> bool {
+    url.path().contains('*')
+}
+
+// the page listing the links to expand the template with, e.g. `https://www.ap-siken.com/kakomon/`
+pub fn listing_url(template: &Url) -> Option<Url> {
+    let head = template.as_str().split_once('*')?.0;
+    Url::parse(&head[..head.rfind('/')? + 1]).ok()
+}
+
+// Replaces the `*` with each of the links in the listing page matching the template,
+// in the order of the page. A link to a directory matches `.../index.html` as well.
+// The template itself is returned if no link matches, to be fetched as a single url.
+pub fn expand_url_template(template: &Url, listing_html: &str) -> Vec<Url> {
+    let (head, tail) = match template.as_str().split_once('*') {
+        Some(parts) => parts,
+        None => return vec![template.clone()],
+    };
+    let base = listing_url(template).unwrap_or_else(|| template.clone());
+    let document = Html::parse_document(listing_html);
+    let mut urls: Vec<Url> = Vec::new();
+    for href in document
+        .select(selector!(r#"a[href]"#))
+        .filter_map(|elem| elem.value().attr("href"))
+    {
+        let link = match base.join(href) {
+            Ok(mut link) => {
+                link.set_fragment(None);
+                link.to_string()
+            }
+            Err(_) => continue,
+        };
+        let directory = tail
+            .strip_suffix("index.html")
+            .filter(|_| link.ends_with('/'));
+        let middle = link.strip_prefix(head).and_then(|rest| {
+            rest.strip_suffix(tail)
+                .or_else(|| directory.and_then(|tail| rest.strip_suffix(tail)))
+        });
+        let middle = match middle {
+            Some(middle) if !middle.is_empty() && !middle.contains(['/', '*', '?']) => middle,
+            _ => continue,
+        };
+        if let Ok(url) = Url::parse(&format!("{}{}{}", head, middle, tail)) {
+            if !urls.contains(&url) {
+                urls.push(url);
+            }
+        }
+    }
+    if urls.is_empty() {
+        return vec![template.clone()];
+    }
+    urls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_url_template() {
+        let template = Url::parse("https://www.ap-siken.com/kakomon/*/index.html").unwrap();
+        assert!(is_url_template(&template));
+        assert_eq!(
+            listing_url(&template).unwrap().as_str(),
+            "https://www.ap-siken.com/kakomon/"
+        );
+
+        let html = r#"<html><body>
+            <a href="05_aki/">令和5年秋期</a>
+            <a href="/kakomon/05_haru/index.html">令和5年春期</a>
+            <a href="https://www.ap-siken.com/kakomon/05_aki/#top">令和5年秋期</a>
+            <a href="05_aki/q1.html">問1</a>
+            <a href="04_haru/">令和4年春期</a>
+            <a href="/kakomonmondai/">過去問題</a>
+            <a href="https://www.nw-siken.com/kakomon/05_aki/">NW</a>
+        </body></html>"#;
+        let urls = expand_url_template(&template, html);
+        assert_eq!(
+            urls.iter().map(Url::as_str).collect::<Vec<_>>(),
+            [
+                "https://www.ap-siken.com/kakomon/05_aki/index.html",
+                "https://www.ap-siken.com/kakomon/05_haru/index.html",
+                "https://www.ap-siken.com/kakomon/04_haru/index.html",
+            ]
+        );
+
+        let template = Url::parse("https://www.ap-siken.com/kakomon/*_haru/").unwrap();
+        let urls = expand_url_template(&template, html);
+        assert_eq!(
+            urls.iter().map(Url::as_str).collect::<Vec<_>>(),
+            ["https://www.ap-siken.com/kakomon/04_haru/"]
+        );
+
+        // kept as it is when nothing matches
+        let html = r#"<html><body><a href="/kakomon/05_aki/q1.html">問1</a></body></html>"#;
+        assert_eq!(expand_url_template(&template, html), vec![template.clone()]);
+        let url = Url::parse("https://www.ap-siken.com/").unwrap();
+        assert!(!is_url_template(&url));
+        assert_eq!(expand_url_template(&url, html), vec![url.clone()]);
+    }
+}
@@ -0,0 +1,34 @@
+// e.g. "0.1.0 (0123456789ab 2024-01-01)", shown by `--version`
+pub const VERSION: &str = concat!(
+    env!("CARGO_PKG_VERSION"),
+    " (",
+    env!("GIT_COMMIT_HASH"),
+    " ",
+    env!("BUILD_DATE"),
+    ")"
+);
+
+// e.g. "ipa-shiken-fetcher/0.1.0 (0123456789ab)", sent with every request
+pub const USER_AGENT: &str = concat!(
+    env!("CARGO_PKG_NAME"),
+    "/",
+    env!("CARGO_PKG_VERSION"),
+    " (",
+    env!("GIT_COMMIT_HASH"),
+    ")"
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version() {
+        assert!(VERSION.starts_with(concat!(env!("CARGO_PKG_VERSION"), " (")));
+        assert!(USER_AGENT.starts_with("ipa-shiken-fetcher/"));
+        // both have the same commit
+        let commit = env!("GIT_COMMIT_HASH");
+        assert!(!commit.is_empty());
+        assert!(VERSION.contains(commit) && USER_AGENT.contains(commit));
+    }
+}